@@ -0,0 +1,253 @@
+//! On-disk archive of finished jobs.
+//!
+//! Every job gets a directory holding the bytes received (`raw.bin`), the JSON
+//! job document (`job.json`) and the rendered receipt (`receipt.png`).
+//! `index.json` at the top keeps one searchable entry per job, including the
+//! plain text of the receipt, so searches never open the job directories.
+
+use super::PrintJob;
+use crate::escpos::diagnostics::Severity;
+use crate::escpos::printer::PrinterState;
+use crate::escpos::raster;
+use crate::export::json::JobDocument;
+use crate::export::text;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+const INDEX_FILE: &str = "index.json";
+const RAW_FILE: &str = "raw.bin";
+const JOB_FILE: &str = "job.json";
+const PNG_FILE: &str = "receipt.png";
+
+/// Bounds on the archive; the oldest jobs are deleted first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub max_jobs: Option<usize>,
+    pub max_bytes: Option<u64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_jobs: Some(1000),
+            max_bytes: Some(500 * 1024 * 1024),
+        }
+    }
+}
+
+/// An archive shared with the background tasks that write jobs into it.
+pub type SharedArchive = Arc<Mutex<JobArchive>>;
+
+/// Index record of one archived job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    /// Directory name under the archive root
+    pub id: String,
+    pub source: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub byte_count: u64,
+    pub command_count: usize,
+    /// Most severe diagnostic, if any
+    pub worst_severity: Option<Severity>,
+    pub diagnostics: Vec<String>,
+    /// Receipt as plain text, for full-text search
+    pub text: String,
+    /// Size of the job's files
+    pub disk_bytes: u64,
+}
+
+/// Filter for `JobArchive::search`; empty fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveQuery {
+    /// Words that must all appear in the receipt text, ignoring case
+    pub text: String,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Part of the source, e.g. an IP address or `serial`
+    pub source: String,
+    /// Only jobs with a diagnostic at least this severe
+    pub min_severity: Option<Severity>,
+}
+
+impl ArchiveEntry {
+    pub fn matches(&self, query: &ArchiveQuery) -> bool {
+        let text = self.text.to_lowercase();
+        query.text.split_whitespace().all(|word| text.contains(&word.to_lowercase()))
+            && query.from.is_none_or(|from| self.ended_at >= from)
+            && query.to.is_none_or(|to| self.started_at <= to)
+            && self.source.to_lowercase().contains(&query.source.trim().to_lowercase())
+            && query.min_severity.is_none_or(|min| self.worst_severity.is_some_and(|worst| worst >= min))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JobArchive {
+    dir: PathBuf,
+    /// Oldest first
+    entries: Vec<ArchiveEntry>,
+    pub retention: RetentionPolicy,
+}
+
+impl JobArchive {
+    /// Open or create an archive in `dir`.
+    pub fn open(dir: &Path, retention: RetentionPolicy) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create archive {}", dir.display()))?;
+        let index = dir.join(INDEX_FILE);
+        let entries = if index.exists() {
+            let text = std::fs::read_to_string(&index)
+                .with_context(|| format!("Failed to read {}", index.display()))?;
+            serde_json::from_str(&text).with_context(|| format!("Corrupt archive index {}", index.display()))?
+        } else {
+            Vec::new()
+        };
+        let mut archive = Self { dir: dir.to_path_buf(), entries, retention };
+        archive.enforce_retention()?;
+        info!("Job archive at {} holds {} job(s)", dir.display(), archive.entries.len());
+        Ok(archive)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Oldest first.
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    pub fn disk_bytes(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.disk_bytes).sum()
+    }
+
+    /// Matching entries, newest first.
+    pub fn search(&self, query: &ArchiveQuery) -> Vec<&ArchiveEntry> {
+        self.entries.iter().rev().filter(|entry| entry.matches(query)).collect()
+    }
+
+    /// Write a finished job and add it to the index.
+    pub fn store(&mut self, printer: &PrinterState, job: &PrintJob) -> Result<()> {
+        let entry = write_job(&self.dir, printer, job)?;
+        self.insert(entry)
+    }
+
+    /// Add an entry written by `write_job`. Entries are kept in start order,
+    /// since jobs written in parallel can finish in any order.
+    pub fn insert(&mut self, entry: ArchiveEntry) -> Result<()> {
+        let position = self.entries.partition_point(|existing| existing.started_at <= entry.started_at);
+        self.entries.insert(position, entry);
+        self.enforce_retention()?;
+        self.save_index()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ArchiveEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    pub fn load_document(&self, id: &str) -> Result<JobDocument> {
+        let path = self.job_dir(id)?.join(JOB_FILE);
+        crate::export::json::import_job(&path)
+    }
+
+    pub fn raw_bytes(&self, id: &str) -> Result<Vec<u8>> {
+        let path = self.job_dir(id)?.join(RAW_FILE);
+        std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))
+    }
+
+    pub fn png_path(&self, id: &str) -> Result<PathBuf> {
+        Ok(self.job_dir(id)?.join(PNG_FILE))
+    }
+
+    pub fn remove(&mut self, id: &str) -> Result<()> {
+        let dir = self.job_dir(id)?;
+        self.entries.retain(|entry| entry.id != id);
+        if let Err(e) = std::fs::remove_dir_all(&dir) {
+            warn!("Failed to delete {}: {}", dir.display(), e);
+        }
+        self.save_index()
+    }
+
+    /// Delete the oldest jobs until the archive is within its retention policy.
+    pub fn enforce_retention(&mut self) -> Result<()> {
+        let mut removed = 0;
+        loop {
+            let over_count = self.retention.max_jobs.is_some_and(|max| self.entries.len() > max);
+            let over_size = self.retention.max_bytes.is_some_and(|max| self.disk_bytes() > max);
+            // Always keep the newest job, even if it alone exceeds the size limit
+            if !(over_count || over_size) || self.entries.len() <= 1 {
+                break;
+            }
+            let oldest = self.entries.remove(0);
+            if let Err(e) = std::fs::remove_dir_all(self.dir.join(&oldest.id)) {
+                warn!("Failed to delete archived job {}: {}", oldest.id, e);
+            }
+            removed += 1;
+        }
+        if removed > 0 {
+            info!("Archive retention removed {} job(s)", removed);
+            self.save_index()?;
+        }
+        Ok(())
+    }
+
+    fn job_dir(&self, id: &str) -> Result<PathBuf> {
+        if self.get(id).is_none() {
+            bail!("No archived job {}", id);
+        }
+        Ok(self.dir.join(id))
+    }
+
+    fn save_index(&self) -> Result<()> {
+        let path = self.dir.join(INDEX_FILE);
+        // Write a temporary file first so a crash never leaves a truncated index
+        let temp = path.with_extension("json.tmp");
+        let json = serde_json::to_string(&self.entries).context("Failed to serialize archive index")?;
+        std::fs::write(&temp, json).with_context(|| format!("Failed to write {}", temp.display()))?;
+        std::fs::rename(&temp, &path).with_context(|| format!("Failed to replace {}", path.display()))
+    }
+}
+
+/// Write the files of a finished job into a new directory of the archive at
+/// `dir`, leaving the index alone, and return its index entry.
+pub fn write_job(dir: &Path, printer: &PrinterState, job: &PrintJob) -> Result<ArchiveEntry> {
+    let started_at: DateTime<Utc> = job.started_at.into();
+    let id = format!("{}-{}", started_at.format("%Y%m%d-%H%M%S"), &uuid::Uuid::new_v4().simple().to_string()[..8]);
+    let job_dir = dir.join(&id);
+    std::fs::create_dir_all(&job_dir).with_context(|| format!("Failed to create {}", job_dir.display()))?;
+
+    let raw: Vec<u8> = job.commands.iter().flat_map(|entry| entry.raw_data.iter().copied()).collect();
+    std::fs::write(job_dir.join(RAW_FILE), &raw).context("Failed to write raw bytes")?;
+    JobDocument::from_finished_job(printer, job).save(&job_dir.join(JOB_FILE))?;
+    raster::render_lines(printer, &job.lines)
+        .save_with_format(job_dir.join(PNG_FILE), ImageFormat::Png)
+        .context("Failed to write receipt PNG")?;
+
+    Ok(ArchiveEntry {
+        id,
+        source: job.source.to_string(),
+        started_at,
+        ended_at: job.ended_at.into(),
+        byte_count: job.byte_count,
+        command_count: job.command_count,
+        worst_severity: job.diagnostics.iter().map(|entry| entry.diagnostic.severity).max(),
+        diagnostics: job.diagnostics.iter().map(|entry| entry.diagnostic.to_string()).collect(),
+        text: text::render_text(printer, &job.lines),
+        disk_bytes: dir_size(&job_dir),
+    })
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.metadata().ok())
+                .map(|metadata| metadata.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
@@ -0,0 +1,57 @@
+//! Live events for observers of the emulator, such as the WebSocket feed.
+
+use super::{CommandEntry, JobSource, PrintJob};
+use crate::escpos::printer::PrinterState;
+use crate::export::{self, base64, ExportFormat};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Something that changed in the emulator, in the order it happened.
+#[derive(Debug, Clone)]
+pub enum EmulatorEvent {
+    /// A command was decoded and printed as part of job `job_id`
+    Command {
+        job_id: u64,
+        source: JobSource,
+        entry: CommandEntry,
+    },
+    JobStarted {
+        job_id: u64,
+        source: JobSource,
+    },
+    /// The job with its captured lines, rendered once for every observer.
+    /// Sent once rendered, so it may follow events of later jobs.
+    JobFinished {
+        job: PrintJob,
+        snapshots: Arc<JobSnapshots>,
+    },
+    /// The profile, paper, job settings or job list changed
+    StatusChanged,
+}
+
+/// A finished job rendered for display.
+#[derive(Debug)]
+pub struct JobSnapshots {
+    /// `data:image/png;base64,...` at one pixel per dot; none if the job
+    /// printed nothing
+    pub png: Option<String>,
+    /// Self-contained HTML document of the receipt
+    pub html: String,
+}
+
+impl JobSnapshots {
+    pub fn render(printer: &PrinterState, job: &PrintJob) -> Self {
+        let png = if job.lines.is_empty() {
+            None
+        } else {
+            match export::encode_lines(printer, &job.lines, ExportFormat::Png { scale: 1 }) {
+                Ok(png) => Some(format!("data:image/png;base64,{}", base64::encode(&png))),
+                Err(e) => {
+                    warn!("Failed to render job #{} for observers: {:#}", job.id, e);
+                    None
+                }
+            }
+        };
+        Self { png, html: export::render_html(printer, &job.lines) }
+    }
+}
@@ -0,0 +1,176 @@
+//! Print jobs: the byte stream divided at cuts, connections or idle gaps.
+
+use super::{CommandEntry, DiagnosticEntry};
+use crate::escpos::printer::ReceiptLine;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::time::{Duration, SystemTime};
+
+/// Where the bytes of a job came from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum JobSource {
+    /// Raw TCP client, `ip:port`
+    Tcp(String),
+    /// HTTP POST client, `ip:port`
+    Http(String),
+    /// Serial port name
+    Serial(String),
+    /// Imported or replayed file
+    File(String),
+    /// Commands fed in from code
+    Local,
+}
+
+impl std::fmt::Display for JobSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobSource::Tcp(peer) => write!(f, "tcp {}", peer),
+            JobSource::Http(peer) => write!(f, "http {}", peer),
+            JobSource::Serial(port) => write!(f, "serial {}", port),
+            JobSource::File(path) => write!(f, "file {}", path),
+            JobSource::Local => write!(f, "local"),
+        }
+    }
+}
+
+/// When to close the current job and start a new one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobSplitPolicy {
+    /// After every paper cut
+    pub on_cut: bool,
+    /// When a TCP or HTTP connection opens or closes
+    pub on_connection: bool,
+    /// After this many seconds without data
+    pub idle_timeout_secs: Option<u64>,
+}
+
+impl Default for JobSplitPolicy {
+    fn default() -> Self {
+        Self {
+            on_cut: true,
+            on_connection: true,
+            idle_timeout_secs: Some(10),
+        }
+    }
+}
+
+impl JobSplitPolicy {
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout_secs.map(Duration::from_secs)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrintJob {
+    pub id: u64,
+    pub source: JobSource,
+    pub started_at: SystemTime,
+    /// Time of the last byte received so far
+    pub ended_at: SystemTime,
+    pub byte_count: u64,
+    pub command_count: usize,
+    /// Pinned jobs are never dropped to make room for new ones
+    pub pinned: bool,
+    /// Still receiving data; its lines are the tail of the printer buffer
+    pub active: bool,
+    /// Commands in arrival order, up to the emulator's per-job caps
+    pub commands: Vec<CommandEntry>,
+    /// Some commands were counted but not stored because of the caps
+    #[serde(default)]
+    pub commands_truncated: bool,
+    pub diagnostics: Vec<DiagnosticEntry>,
+    /// Laid-out lines, captured from the printer buffer when the job ends
+    pub lines: Vec<ReceiptLine>,
+    /// The job's lines in the printer buffer; jobs from different sources
+    /// take turns, so there is a range per turn. The last one is open-ended
+    /// while the job prints.
+    #[serde(skip)]
+    line_ranges: Vec<Range<usize>>,
+    /// Raw bytes held by `commands`
+    #[serde(skip)]
+    stored_bytes: usize,
+}
+
+impl PrintJob {
+    pub(crate) fn new(id: u64, source: JobSource) -> Self {
+        let now = SystemTime::now();
+        Self {
+            id,
+            source,
+            started_at: now,
+            ended_at: now,
+            byte_count: 0,
+            command_count: 0,
+            pinned: false,
+            active: true,
+            commands: Vec::new(),
+            commands_truncated: false,
+            diagnostics: Vec::new(),
+            lines: Vec::new(),
+            line_ranges: Vec::new(),
+            stored_bytes: 0,
+        }
+    }
+
+    /// Count `entry` and store it while the job holds fewer than
+    /// `max_commands` commands and at most `max_bytes` raw bytes. Returns true
+    /// for the first entry that is not stored.
+    pub(crate) fn record(&mut self, entry: CommandEntry, max_commands: usize, max_bytes: usize) -> bool {
+        self.ended_at = entry.timestamp;
+        self.byte_count += entry.raw_data.len() as u64;
+        self.command_count += 1;
+        let bytes = self.stored_bytes + entry.raw_data.len();
+        if !self.commands_truncated && self.commands.len() < max_commands && bytes <= max_bytes {
+            self.stored_bytes = bytes;
+            self.commands.push(entry);
+            return false;
+        }
+        !std::mem::replace(&mut self.commands_truncated, true)
+    }
+
+    /// Printer buffer lines from `start` on belong to this job.
+    pub(crate) fn resume_lines(&mut self, start: usize) {
+        self.line_ranges.push(start..usize::MAX);
+    }
+
+    /// The first buffer line the job printed on.
+    pub(crate) fn first_line(&self) -> Option<usize> {
+        self.line_ranges.first().map(|range| range.start)
+    }
+
+    /// The first `count` lines were dropped from the buffer.
+    pub(crate) fn rebase_lines(&mut self, count: usize) {
+        for range in &mut self.line_ranges {
+            range.start = range.start.saturating_sub(count);
+            if range.end != usize::MAX {
+                range.end = range.end.saturating_sub(count);
+            }
+        }
+    }
+
+    /// Another job prints from buffer line `end` on.
+    pub(crate) fn pause_lines(&mut self, end: usize) {
+        if let Some(range) = self.line_ranges.last_mut() {
+            range.end = range.end.min(end.max(range.start));
+        }
+    }
+
+    /// The job's lines in the printer buffer `buffer`.
+    pub(crate) fn lines_in<'a>(&self, buffer: &'a [ReceiptLine]) -> Cow<'a, [ReceiptLine]> {
+        let clamp = |range: &Range<usize>| range.start.min(buffer.len())..range.end.min(buffer.len());
+        match self.line_ranges.as_slice() {
+            [range] => Cow::Borrowed(&buffer[clamp(range)]),
+            ranges => Cow::Owned(ranges.iter().flat_map(|range| buffer[clamp(range)].iter().cloned()).collect()),
+        }
+    }
+
+    /// Whether no data arrived for longer than `timeout` before `now`.
+    pub fn is_idle(&self, now: SystemTime, timeout: Duration) -> bool {
+        now.duration_since(self.ended_at).is_ok_and(|idle| idle > timeout)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.ended_at.duration_since(self.started_at).unwrap_or_default()
+    }
+}
@@ -0,0 +1,81 @@
+//! GS ^ macro runs.
+//!
+//! The printer pauses between runs, or waits for the FEED button, and holds
+//! back whatever it receives until the last run is done.
+
+use super::JobSource;
+use crate::escpos::parser::ParsedCommand;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+/// GS ^ waits in multiples of this; poll at least this often to keep time.
+pub const MACRO_WAIT_STEP: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub(crate) struct MacroRun {
+    /// The source that sent GS ^; the runs print into its job
+    pub source: JobSource,
+    /// Stream offset of the GS ^, logged for every command of a run
+    pub offset: u64,
+    pub commands: Vec<ParsedCommand>,
+    pub runs_left: u8,
+    pub wait: Duration,
+    /// Mode 1: every run also waits for a FEED button press
+    pub needs_button: bool,
+    pub button_pressed: bool,
+    /// Earliest time of the next run
+    pub due: SystemTime,
+    /// Commands received meanwhile, with their sources and arrival times
+    pub held: VecDeque<(JobSource, ParsedCommand, SystemTime)>,
+}
+
+impl MacroRun {
+    pub fn new(
+        source: JobSource,
+        offset: u64,
+        commands: Vec<ParsedCommand>,
+        repeat: u8,
+        wait: u8,
+        mode: u8,
+        now: SystemTime,
+    ) -> Self {
+        let wait = MACRO_WAIT_STEP * wait as u32;
+        let needs_button = mode == 1;
+        Self {
+            source,
+            offset,
+            commands,
+            runs_left: repeat,
+            wait,
+            needs_button,
+            button_pressed: false,
+            // Mode 0 runs right away and waits between runs; mode 1 waits
+            // before every run
+            due: if needs_button { now + wait } else { now },
+            held: VecDeque::new(),
+        }
+    }
+
+    pub fn ready(&self, now: SystemTime) -> bool {
+        self.runs_left > 0 && now >= self.due && (!self.needs_button || self.button_pressed)
+    }
+
+    /// The wait is over and only the FEED button is missing.
+    pub fn waiting_for_button(&self, now: SystemTime) -> bool {
+        self.runs_left > 0 && self.needs_button && !self.button_pressed && now >= self.due
+    }
+
+    /// Count a run started at `now` and schedule the next one.
+    pub fn start_run(&mut self, now: SystemTime) {
+        self.runs_left -= 1;
+        self.button_pressed = false;
+        self.due = now + self.wait;
+    }
+
+    /// Run the rest back to back, for data that is complete up front.
+    pub fn skip_waits(&mut self) {
+        self.wait = Duration::ZERO;
+        self.needs_button = false;
+        self.due = SystemTime::UNIX_EPOCH;
+    }
+}
@@ -1,8 +1,39 @@
+pub mod archive;
+pub mod events;
+pub mod jobs;
+pub mod macros;
+pub mod pcap;
+pub mod session;
+
+pub use archive::{ArchiveEntry, ArchiveQuery, JobArchive, RetentionPolicy, SharedArchive};
+pub use events::{EmulatorEvent, JobSnapshots};
+pub use jobs::{JobSource, JobSplitPolicy, PrintJob};
+pub use macros::MACRO_WAIT_STEP;
+pub use session::{ReplaySpeed, Session, SessionEvent, SessionEventKind, SessionRecorder, SessionReplayer};
+
 use crate::escpos::commands::EscPosCommand;
-use crate::escpos::printer::{PrinterState, PaperWidth};
+use crate::escpos::diagnostics::{Diagnostic, DiagnosticKind, Severity};
+use crate::escpos::parser::{EscPosParser, ParsedCommand, ParserLimits};
+use crate::escpos::paper::PaperWidth;
+use crate::escpos::printer::{PrinterState, ReceiptLine};
+use crate::escpos::profile::PrinterProfile;
+use crate::export::json::{self, JobDocument};
+use anyhow::{bail, Context, Result};
+use std::borrow::Cow;
 use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, MutexGuard, PoisonError};
+use macros::MacroRun;
 use std::time::SystemTime;
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+
+/// Finished jobs kept for subscribers that fall behind.
+const FINISHED_JOB_BACKLOG: usize = 64;
+/// Live events kept for subscribers that fall behind; one per command, so
+/// larger than the job backlog.
+const EVENT_BACKLOG: usize = 4096;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmulatorState {
@@ -10,6 +41,43 @@ pub struct EmulatorState {
     pub command_history: VecDeque<CommandEntry>,
     pub max_history_size: usize,
     pub start_time: SystemTime,
+    pub parser_limits: ParserLimits,
+    pub diagnostics: VecDeque<DiagnosticEntry>,
+    /// Built-in and user-defined printer profiles available for selection
+    pub profiles: Vec<PrinterProfile>,
+    /// Received jobs, oldest first. Each source has at most one active job.
+    pub jobs: VecDeque<PrintJob>,
+    pub job_policy: JobSplitPolicy,
+    /// Unpinned jobs beyond this count are dropped, oldest first
+    pub max_jobs: usize,
+    /// Commands stored per job; later ones are counted but not kept
+    pub max_job_commands: usize,
+    /// Raw command bytes stored per job
+    pub max_job_command_bytes: usize,
+    /// Drop buffer lines once no active job prints on them, so long-running
+    /// servers do not grow the buffer forever. Off where the whole paper roll
+    /// is shown or rendered.
+    #[serde(default)]
+    pub trim_printed_lines: bool,
+    next_job_id: u64,
+    /// The active job the printer buffer currently ends with
+    #[serde(skip)]
+    printing_job: Option<u64>,
+    /// A GS ^ macro with runs left; input is held back until it is done
+    #[serde(skip)]
+    running_macro: Option<MacroRun>,
+    /// Finished jobs are written here when set, in the background
+    #[serde(skip)]
+    pub archive: Option<SharedArchive>,
+    /// Every inbound and outbound chunk is appended here when set
+    #[serde(skip)]
+    pub recorder: Option<SessionRecorder>,
+    /// Finished jobs are announced here once someone subscribed
+    #[serde(skip)]
+    finished_jobs: Option<broadcast::Sender<PrintJob>>,
+    /// Live events are sent here once someone subscribed
+    #[serde(skip)]
+    events: Option<broadcast::Sender<EmulatorEvent>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +85,14 @@ pub struct CommandEntry {
     pub timestamp: SystemTime,
     pub command: EscPosCommand,
     pub raw_data: Vec<u8>,
+    /// Offset of `raw_data` in the connection's byte stream
+    pub stream_offset: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticEntry {
+    pub timestamp: SystemTime,
+    pub diagnostic: Diagnostic,
 }
 
 impl EmulatorState {
@@ -26,23 +102,655 @@ impl EmulatorState {
             command_history: VecDeque::new(),
             max_history_size: 1000,
             start_time: SystemTime::now(),
+            parser_limits: ParserLimits::default(),
+            diagnostics: VecDeque::new(),
+            profiles: PrinterProfile::builtin(),
+            jobs: VecDeque::new(),
+            job_policy: JobSplitPolicy::default(),
+            max_jobs: 200,
+            max_job_commands: 20_000,
+            max_job_command_bytes: 1024 * 1024,
+            trim_printed_lines: false,
+            next_job_id: 1,
+            printing_job: None,
+            running_macro: None,
+            archive: None,
+            recorder: None,
+            finished_jobs: None,
+            events: None,
         }
     }
 
-    pub fn process_command(&mut self, command: &EscPosCommand) {
-        let entry = CommandEntry {
+    /// A fresh parser for a new stream, using the configured limits and the
+    /// capabilities of the selected printer profile.
+    pub fn new_parser(&self) -> EscPosParser {
+        let profile = &self.printer_state.profile;
+        let mut parser = EscPosParser::with_limits(self.parser_limits.clone());
+        parser.set_unsupported_commands(profile.ignored_prefixes());
+        parser.set_code_pages(profile.code_pages.clone());
+        parser.set_code_page(self.printer_state.codepage);
+        parser
+    }
+
+    /// Add a profile, replacing any existing profile with the same id.
+    pub fn add_profile(&mut self, profile: PrinterProfile) {
+        match self.profiles.iter_mut().find(|p| p.id == profile.id) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+
+    /// Load user-defined profiles from the JSON files in `dir`.
+    pub fn load_profiles_from_dir(&mut self, dir: &Path) -> usize {
+        let profiles = PrinterProfile::load_dir(dir);
+        let count = profiles.len();
+        for profile in profiles {
+            self.add_profile(profile);
+        }
+        count
+    }
+
+    /// Select a profile by id. Returns false if no such profile is known.
+    pub fn select_profile(&mut self, id: &str) -> bool {
+        match self.profiles.iter().find(|p| p.id == id) {
+            Some(profile) => {
+                self.printer_state.set_profile(profile.clone());
+                self.status_changed();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drain status and ID responses produced by the processed commands.
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        self.printer_state.take_responses()
+    }
+
+    /// Log a diagnostic from the stream of `source`, attaching it to that
+    /// source's job.
+    pub fn record_diagnostic(&mut self, source: &JobSource, diagnostic: Diagnostic) {
+        match diagnostic.severity {
+            Severity::Error => error!("Parse diagnostic: {}", diagnostic),
+            Severity::Warning => warn!("Parse diagnostic: {}", diagnostic),
+            Severity::Info => info!("Parse diagnostic: {}", diagnostic),
+        }
+        let entry = DiagnosticEntry {
             timestamp: SystemTime::now(),
-            command: command.clone(),
-            raw_data: vec![], // TODO: Stocker les données brutes
+            diagnostic,
+        };
+        // Parsers report right after the commands of their own stream, so the
+        // newest job of the source is the one those bytes went to, even if a
+        // cut has just finished it
+        if let Some(job) = self.jobs.iter_mut().rev().find(|job| job.source == *source) {
+            job.diagnostics.push(entry.clone());
+        }
+        self.diagnostics.push_back(entry);
+
+        while self.diagnostics.len() > self.max_history_size {
+            self.diagnostics.pop_front();
+        }
+    }
+
+    pub fn get_diagnostics(&self) -> &VecDeque<DiagnosticEntry> {
+        &self.diagnostics
+    }
+
+    pub fn process_command(&mut self, parsed: &ParsedCommand) {
+        self.process_command_from(&JobSource::Local, parsed);
+    }
+
+    /// Process a command received from `source`, adding it to the job from
+    /// that source or starting a new one.
+    pub fn process_command_from(&mut self, source: &JobSource, parsed: &ParsedCommand) {
+        self.process_command_at(source, parsed, SystemTime::now());
+    }
+
+    /// Process a command with the time it was received, for imported traffic.
+    pub fn process_command_at(&mut self, source: &JobSource, parsed: &ParsedCommand, timestamp: SystemTime) {
+        // The printer takes no new input while a macro runs
+        if let Some(run) = &mut self.running_macro {
+            run.held.push_back((source.clone(), parsed.clone(), timestamp));
+            return;
+        }
+        self.execute_command(source, parsed, timestamp);
+        self.run_macro_at(timestamp);
+    }
+
+    fn execute_command(&mut self, source: &JobSource, parsed: &ParsedCommand, timestamp: SystemTime) {
+        let entry = CommandEntry {
+            timestamp,
+            command: parsed.command.clone(),
+            raw_data: parsed.raw.clone(),
+            stream_offset: parsed.offset,
         };
 
+        self.finish_jobs_idle_at(timestamp);
+        let job_id = match self.source_job(source) {
+            Some(job) => job.id,
+            None => self.start_job(source.clone(), timestamp),
+        };
+        self.switch_printing_job(job_id);
+        let (max_commands, max_bytes) = (self.max_job_commands, self.max_job_command_bytes);
+        if let Some(job) = self.jobs.iter_mut().rev().find(|job| job.id == job_id) {
+            let truncated = job.record(entry.clone(), max_commands, max_bytes);
+            self.emit(|| EmulatorEvent::Command { job_id, source: source.clone(), entry: entry.clone() });
+            if truncated {
+                let message = format!(
+                    "job #{} keeps only its first {} commands or {} bytes; later commands are printed but not stored",
+                    job_id, max_commands, max_bytes
+                );
+                self.record_diagnostic(source, Diagnostic::new(parsed.offset, Severity::Warning, DiagnosticKind::LimitExceeded, message));
+            }
+        }
+
         self.command_history.push_back(entry);
 
         while self.command_history.len() > self.max_history_size {
             self.command_history.pop_front();
         }
 
-        self.printer_state.process_command(command);
+        // GS ^ while a macro is defined only aborts the definition
+        let recording = self.printer_state.macro_recording;
+        self.printer_state.process_parsed(parsed);
+
+        match parsed.command {
+            EscPosCommand::CutPaper(_) if self.job_policy.on_cut => self.finish_source_job(source),
+            EscPosCommand::ExecuteMacro { repeat, wait, mode } if !recording => {
+                self.start_macro(source, parsed.offset, repeat, wait, mode, timestamp)
+            }
+            _ => {}
+        }
+    }
+
+    /// GS ^: run the defined macro `repeat` times. Its commands are parsed as
+    /// the printer would and printed for `source` like received ones, so a
+    /// cut in the macro splits jobs.
+    fn start_macro(&mut self, source: &JobSource, offset: u64, repeat: u8, wait: u8, mode: u8, now: SystemTime) {
+        if repeat == 0 || self.printer_state.macro_buffer.is_empty() {
+            return;
+        }
+        let mut parser = self.new_parser();
+        let result = parser.parse_stream(&self.printer_state.macro_buffer);
+        parser.finish();
+        for mut diagnostic in parser.take_diagnostics() {
+            diagnostic.offset = offset;
+            self.record_diagnostic(source, diagnostic);
+        }
+        let commands = match result {
+            Ok(commands) => commands,
+            Err(e) => {
+                warn!("Macro parse error: {}", e);
+                return;
+            }
+        };
+        info!(
+            "Executing macro ({} bytes) {} times, wait {} ms, mode {}",
+            self.printer_state.macro_buffer.len(),
+            repeat,
+            wait as u32 * 100,
+            mode
+        );
+        self.running_macro = Some(MacroRun::new(source.clone(), offset, commands, repeat, wait, mode, now));
+    }
+
+    /// Do the macro runs due at `now`; after the last one, process what was
+    /// held back meanwhile.
+    fn run_macro_at(&mut self, now: SystemTime) {
+        while let Some(mut run) = self.running_macro.take() {
+            if run.runs_left == 0 {
+                for (source, parsed, timestamp) in run.held {
+                    // A held GS ^ starts another macro, which holds the rest
+                    match &mut self.running_macro {
+                        Some(next) => next.held.push_back((source, parsed, timestamp)),
+                        None => self.execute_command(&source, &parsed, timestamp),
+                    }
+                }
+                continue;
+            }
+            if !run.ready(now) {
+                self.running_macro = Some(run);
+                return;
+            }
+            run.start_run(now);
+            for command in &run.commands {
+                let parsed = ParsedCommand { offset: run.offset, ..command.clone() };
+                self.execute_command(&run.source, &parsed, now);
+            }
+            self.running_macro = Some(run);
+        }
+    }
+
+    /// Run what is left of a macro back to back, for streams that are
+    /// complete up front and have nobody to press the FEED button.
+    fn finish_macro(&mut self) {
+        while let Some(run) = &mut self.running_macro {
+            run.skip_waits();
+            self.run_macro_at(SystemTime::now());
+        }
+    }
+
+    /// A GS ^ macro has runs left, so time has to be kept.
+    pub fn macro_running(&self) -> bool {
+        self.running_macro.is_some()
+    }
+
+    /// A macro run waits for the FEED button.
+    pub fn waiting_for_feed_button(&self) -> bool {
+        self.running_macro.as_ref().is_some_and(|run| run.waiting_for_button(SystemTime::now()))
+    }
+
+    /// Press the FEED button: a macro waiting for it does its next run.
+    pub fn press_feed_button(&mut self) {
+        let now = SystemTime::now();
+        if let Some(run) = self.running_macro.as_mut().filter(|run| run.waiting_for_button(now)) {
+            run.button_pressed = true;
+            self.run_macro_at(now);
+        }
+    }
+
+    /// A client connected: with `on_connection`, whatever came before from
+    /// the same source is a finished job.
+    pub fn connection_opened(&mut self, source: &JobSource) {
+        info!("Receiving from {}", source);
+        self.record_session_event(source, SessionEventKind::Connect, &[]);
+        if self.job_policy.on_connection {
+            self.finish_source_job(source);
+        }
+    }
+
+    pub fn connection_closed(&mut self, source: &JobSource) {
+        self.record_session_event(source, SessionEventKind::Disconnect, &[]);
+        if self.job_policy.on_connection {
+            self.finish_source_job(source);
+        }
+    }
+
+    /// Parse and print a complete byte stream from `source`, as one connection.
+    /// Returns the ids of the jobs it printed into, in order; cuts and idle
+    /// gaps can split one stream into several. Macros run without waiting.
+    pub fn print_bytes(&mut self, source: &JobSource, data: &[u8]) -> Vec<u64> {
+        self.connection_opened(source);
+        let continued = self.source_job(source).map(|job| job.id);
+        let first_job = self.next_job_id;
+        self.record_session_event(source, SessionEventKind::Inbound, data);
+        let mut parser = self.new_parser();
+        let result = parser.parse_stream(data);
+        parser.finish();
+        // Record each diagnostic once the commands in front of it are
+        // processed, so it goes to the job its bytes belong to
+        let mut diagnostics = parser.take_diagnostics().into_iter().peekable();
+        match result {
+            Ok(commands) => {
+                for parsed in &commands {
+                    while let Some(diagnostic) = diagnostics.next_if(|diagnostic| diagnostic.offset < parsed.offset) {
+                        self.record_diagnostic(source, diagnostic);
+                    }
+                    self.process_command_from(source, parsed);
+                }
+            }
+            Err(e) => warn!("Parse error in data from {}: {}", source, e),
+        }
+        self.finish_macro();
+        for diagnostic in diagnostics {
+            self.record_diagnostic(source, diagnostic);
+        }
+        self.connection_closed(source);
+        self.jobs
+            .iter()
+            .filter(|job| job.source == *source && (job.id >= first_job || Some(job.id) == continued))
+            .map(|job| job.id)
+            .collect()
+    }
+
+    /// Print a captured `.bin`/`.prn` file into jobs labelled with its name.
+    pub fn print_file(&mut self, path: &Path) -> Result<Vec<u64>> {
+        let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().to_string();
+        let jobs = self.print_bytes(&JobSource::File(name), &data);
+        // Nobody is listening for status replies from a file
+        self.take_responses();
+        if jobs.is_empty() {
+            bail!("{} contains no printable data", path.display());
+        }
+        Ok(jobs)
+    }
+
+    /// Start writing every chunk received and sent to a capture file.
+    pub fn start_recording(&mut self, path: &Path) -> Result<()> {
+        self.recorder = Some(SessionRecorder::create(path)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            info!("Recorded {} event(s) to {}", recorder.event_count(), recorder.path().display());
+        }
+    }
+
+    /// Append to the capture, if recording. Empty data chunks are skipped.
+    pub fn record_session_event(&mut self, source: &JobSource, kind: SessionEventKind, data: &[u8]) {
+        let is_data = matches!(kind, SessionEventKind::Inbound | SessionEventKind::Outbound);
+        if is_data && data.is_empty() {
+            return;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.record(source, kind, data) {
+                // One error per recording, not one per chunk
+                error!("Session recording stopped: {:#}", e);
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// Feed a recorded session back in at once.
+    pub fn replay_session(&mut self, session: &Session) {
+        self.apply_session(session, SessionReplayer::new());
+    }
+
+    /// Feed a session in at once, keeping its timestamps on jobs and commands.
+    pub fn import_session(&mut self, session: &Session) {
+        self.apply_session(session, SessionReplayer::with_recorded_times(session));
+    }
+
+    /// Import the printer-port connections of a pcap/pcapng trace, one job
+    /// per connection. Returns the number of connections found.
+    pub fn import_pcap(&mut self, path: &Path, port: u16) -> Result<usize> {
+        let session = pcap::read_capture(path, port)?;
+        let connections = session.connection_count();
+        info!("Importing {} connection(s) to port {} from {}", connections, port, path.display());
+        self.import_session(&session);
+        Ok(connections)
+    }
+
+    fn apply_session(&mut self, session: &Session, mut replayer: SessionReplayer) {
+        for event in &session.events {
+            replayer.apply(self, event);
+        }
+        replayer.finish(self);
+    }
+
+    /// Close the active jobs that have been idle longer than the policy
+    /// allows, and do a macro run whose wait is over. Called for every
+    /// command and periodically by the GUI and the servers.
+    pub fn finish_idle_jobs(&mut self) {
+        let now = SystemTime::now();
+        self.run_macro_at(now);
+        self.finish_jobs_idle_at(now);
+    }
+
+    fn finish_jobs_idle_at(&mut self, now: SystemTime) {
+        let timeout = match self.job_policy.idle_timeout() {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        while let Some(index) = self.jobs.iter().position(|job| job.active && job.is_idle(now, timeout)) {
+            self.finish_job_at(index);
+        }
+    }
+
+    /// Close every active job, e.g. on shutdown.
+    pub fn finish_jobs(&mut self) {
+        self.finish_macro();
+        while let Some(index) = self.jobs.iter().position(|job| job.active) {
+            self.finish_job_at(index);
+        }
+    }
+
+    /// Close the active job receiving from `source`, if any.
+    pub fn finish_source_job(&mut self, source: &JobSource) {
+        if let Some(index) = self.jobs.iter().position(|job| job.active && job.source == *source) {
+            self.finish_job_at(index);
+        }
+    }
+
+    /// Close a job, capturing its lines from the printer buffer.
+    fn finish_job_at(&mut self, index: usize) {
+        let buffer = self.printer_state.get_buffer();
+        let job = &mut self.jobs[index];
+        job.lines = job.lines_in(buffer).into_owned();
+        job.active = false;
+        info!("Job #{} from {} finished: {} bytes, {} commands", job.id, job.source, job.byte_count, job.command_count);
+        if self.printing_job == Some(job.id) {
+            self.printing_job = None;
+        }
+        self.archive_job(index);
+        self.announce_job(index);
+        self.trim_buffer();
+    }
+
+    /// With `trim_printed_lines`, drop the buffer lines in front of the
+    /// oldest line an active job printed; finished jobs hold their own copy.
+    fn trim_buffer(&mut self) {
+        if !self.trim_printed_lines {
+            return;
+        }
+        // The printer may still continue the last line
+        let last = self.printer_state.get_buffer().len().saturating_sub(1);
+        let count = self.active_jobs().filter_map(PrintJob::first_line).fold(last, usize::min);
+        if count == 0 {
+            return;
+        }
+        self.printer_state.trim_buffer(count);
+        for job in self.jobs.iter_mut().filter(|job| job.active) {
+            job.rebase_lines(count);
+        }
+    }
+
+    /// Receive every job finished from now on, in order.
+    pub fn subscribe_finished_jobs(&mut self) -> broadcast::Receiver<PrintJob> {
+        self.finished_jobs
+            .get_or_insert_with(|| broadcast::channel(FINISHED_JOB_BACKLOG).0)
+            .subscribe()
+    }
+
+    /// Send a finished job to subscribers. Event subscribers get it with its
+    /// snapshots, which are rendered off the caller's lock.
+    fn announce_job(&mut self, index: usize) {
+        let job = self.jobs[index].clone();
+        if let Some(sender) = &self.finished_jobs {
+            // No receivers left is not an error
+            let _ = sender.send(job.clone());
+        }
+        let events = match self.events.as_ref().filter(|sender| sender.receiver_count() > 0) {
+            Some(sender) => sender.clone(),
+            None => return,
+        };
+        let printer = self.printer_settings();
+        run_blocking(move || {
+            let snapshots = Arc::new(JobSnapshots::render(&printer, &job));
+            let _ = events.send(EmulatorEvent::JobFinished { job, snapshots });
+        });
+    }
+
+    /// Receive every event from now on, in order. Events are only built
+    /// while someone is subscribed.
+    pub fn subscribe_events(&mut self) -> broadcast::Receiver<EmulatorEvent> {
+        self.events
+            .get_or_insert_with(|| broadcast::channel(EVENT_BACKLOG).0)
+            .subscribe()
+    }
+
+    /// Tell subscribers the status changed. The setters call this; callers
+    /// that change `job_policy` or `max_jobs` directly must call it themselves.
+    pub fn status_changed(&self) {
+        self.emit(|| EmulatorEvent::StatusChanged);
+    }
+
+    fn emit(&self, event: impl FnOnce() -> EmulatorEvent) {
+        if let Some(sender) = self.events.as_ref().filter(|sender| sender.receiver_count() > 0) {
+            let _ = sender.send(event());
+        }
+    }
+
+    /// Start writing finished jobs to an archive directory.
+    pub fn open_archive(&mut self, dir: &Path, retention: RetentionPolicy) -> Result<()> {
+        self.archive = Some(Arc::new(std::sync::Mutex::new(JobArchive::open(dir, retention)?)));
+        Ok(())
+    }
+
+    /// The open archive, once no job is being added to its index.
+    pub fn archive(&self) -> Option<MutexGuard<'_, JobArchive>> {
+        self.archive.as_ref().map(|archive| lock_archive(archive))
+    }
+
+    pub fn close_archive(&mut self) {
+        self.archive = None;
+    }
+
+    /// Write a finished job to the archive, off the caller's lock.
+    fn archive_job(&mut self, index: usize) {
+        let archive = match &self.archive {
+            Some(archive) => Arc::clone(archive),
+            None => return,
+        };
+        let job = match self.jobs.get(index).filter(|job| job.command_count > 0) {
+            Some(job) => job.clone(),
+            None => return,
+        };
+        // The job has its own copy of its lines
+        let printer = self.printer_settings();
+        let dir = lock_archive(&archive).dir().to_path_buf();
+        run_blocking(move || {
+            let result = archive::write_job(&dir, &printer, &job).and_then(|entry| lock_archive(&archive).insert(entry));
+            if let Err(e) = result {
+                error!("Failed to archive job #{}: {:#}", job.id, e);
+            }
+        });
+    }
+
+    /// A copy of the printer without its printed lines: the settings are all
+    /// a renderer needs to draw lines copied out of the buffer.
+    pub fn printer_settings(&mut self) -> PrinterState {
+        let buffer = std::mem::take(&mut self.printer_state.buffer);
+        let printer = self.printer_state.clone();
+        self.printer_state.buffer = buffer;
+        printer
+    }
+
+    /// Add an archived job to the job list, returning its new job id.
+    pub fn reopen_archived(&mut self, archive_id: &str) -> Result<u64> {
+        let (document, path) = match self.archive() {
+            Some(archive) => (archive.load_document(archive_id)?, archive.dir().join(archive_id)),
+            None => bail!("No job archive is open"),
+        };
+        self.push_document_job(&document, JobSource::File(path.display().to_string()))
+    }
+
+    /// Add a finished job built from a job document.
+    fn push_document_job(&mut self, document: &JobDocument, source: JobSource) -> Result<u64> {
+        let mut job = PrintJob::new(self.next_job_id, source);
+        job.started_at = document.metadata.started_at.into();
+        job.ended_at = document.metadata.ended_at.into();
+        job.byte_count = document.metadata.byte_count;
+        job.command_count = document.metadata.command_count;
+        job.commands_truncated = document.metadata.commands_truncated;
+        job.active = false;
+        job.commands = document.command_entries(self)?;
+        job.diagnostics = document.diagnostic_entries();
+        job.lines = document.receipt_lines()?;
+        self.next_job_id += 1;
+        // Not through start_job: a reopened job has no lines in the printer buffer
+        let id = job.id;
+        self.jobs.push_back(job);
+        Ok(id)
+    }
+
+    /// Start a job for `source` and return its id.
+    fn start_job(&mut self, source: JobSource, started_at: SystemTime) -> u64 {
+        let id = self.next_job_id;
+        let mut job = PrintJob::new(id, source.clone());
+        job.started_at = started_at;
+        job.ended_at = started_at;
+        self.jobs.push_back(job);
+        self.emit(|| EmulatorEvent::JobStarted { job_id: id, source });
+        self.next_job_id += 1;
+
+        while self.jobs.len() > self.max_jobs {
+            match self.jobs.iter().position(|job| !job.pinned && !job.active) {
+                Some(index) => {
+                    self.jobs.remove(index);
+                }
+                None => break,
+            }
+        }
+        id
+    }
+
+    /// Let job `id` print next. The lines printed so far stay with the job
+    /// that printed them.
+    fn switch_printing_job(&mut self, id: u64) {
+        if self.printing_job == Some(id) {
+            return;
+        }
+        // Text after a line feed goes into the empty line the feed left, so
+        // that line belongs to the next job
+        let buffer = self.printer_state.get_buffer();
+        let start = match buffer.last() {
+            Some(ReceiptLine::Text(line)) if line.text.is_empty() => buffer.len() - 1,
+            _ => buffer.len(),
+        };
+        for job in self.jobs.iter_mut().filter(|job| job.active) {
+            if job.id == id {
+                job.resume_lines(start);
+            } else if Some(job.id) == self.printing_job {
+                job.pause_lines(start);
+            }
+        }
+        self.printing_job = Some(id);
+    }
+
+    /// The active job that printed last.
+    pub fn active_job(&self) -> Option<&PrintJob> {
+        self.printing_job
+            .and_then(|id| self.get_job(id))
+            .filter(|job| job.active)
+            .or_else(|| self.active_jobs().last())
+    }
+
+    /// Jobs still receiving data, one per source, oldest first.
+    pub fn active_jobs(&self) -> impl Iterator<Item = &PrintJob> {
+        self.jobs.iter().filter(|job| job.active)
+    }
+
+    /// The active job receiving from `source`.
+    pub fn source_job(&self, source: &JobSource) -> Option<&PrintJob> {
+        self.active_jobs().find(|job| job.source == *source)
+    }
+
+    pub fn get_job(&self, id: u64) -> Option<&PrintJob> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    /// The laid-out lines of a job; those of active jobs are read live from
+    /// the printer buffer.
+    pub fn job_lines<'a>(&'a self, job: &'a PrintJob) -> Cow<'a, [ReceiptLine]> {
+        if job.active {
+            job.lines_in(self.printer_state.get_buffer())
+        } else {
+            Cow::Borrowed(&job.lines)
+        }
+    }
+
+    pub fn delete_job(&mut self, id: u64) {
+        self.jobs.retain(|job| job.id != id || job.active);
+    }
+
+    /// Close the active jobs and drop every job, pinned or not, along with the
+    /// printed receipt. Returns how many jobs were dropped.
+    pub fn clear_jobs(&mut self) -> usize {
+        self.finish_jobs();
+        let count = self.jobs.len();
+        self.jobs.clear();
+        self.printer_state.clear_buffer();
+        self.status_changed();
+        count
+    }
+
+    pub fn set_job_pinned(&mut self, id: u64, pinned: bool) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) {
+            job.pinned = pinned;
+        }
     }
 
     pub fn get_command_history(&self) -> &VecDeque<CommandEntry> {
@@ -51,9 +759,11 @@ impl EmulatorState {
 
     pub fn clear_history(&mut self) {
         self.command_history.clear();
+        self.diagnostics.clear();
     }
 
     pub fn clear_printer_buffer(&mut self) {
+        self.finish_jobs();
         self.printer_state.clear_buffer();
     }
 
@@ -63,7 +773,7 @@ impl EmulatorState {
 
     pub fn get_status_summary(&self) -> StatusSummary {
         StatusSummary {
-            paper_width: format!("{:?}", self.printer_state.paper_width),
+            paper_width: self.printer_state.paper_width.to_string(),
             current_font: format!("{:?}", self.printer_state.current_font),
             justification: format!("{:?}", self.printer_state.justification),
             emphasis: self.printer_state.emphasis,
@@ -72,17 +782,49 @@ impl EmulatorState {
             buffer_lines: self.printer_state.get_buffer().len(),
             command_count: self.command_history.len(),
             dpi: self.printer_state.dpi,
+            profile: self.printer_state.profile.model_name.clone(),
         }
     }
 
-    pub fn set_paper_width(&mut self, width_mm: u32) {
-        let paper_width = match width_mm {
-            50 => PaperWidth::Width50mm,
-            78 => PaperWidth::Width78mm,
-            80 => PaperWidth::Width80mm,
-            _ => PaperWidth::Width80mm,
-        };
-        self.printer_state.set_paper_width(paper_width);
+    pub fn set_paper_width(&mut self, width_mm: f32) -> Result<()> {
+        if !(PaperWidth::MIN_MM..=PaperWidth::MAX_MM).contains(&width_mm) {
+            bail!(
+                "Paper width {}mm is outside {}..{}mm",
+                width_mm,
+                PaperWidth::MIN_MM,
+                PaperWidth::MAX_MM
+            );
+        }
+        self.printer_state.set_paper_width_mm(width_mm);
+        self.status_changed();
+        Ok(())
+    }
+
+    /// Write the session as a versioned JSON job document.
+    pub fn export_job(&self, path: &Path, source: &str) -> Result<()> {
+        json::export_job(self, path, source)
+    }
+
+    /// Load a JSON job document, replacing the history, diagnostics and
+    /// receipt buffer. The job's profile is selected when it is known.
+    pub fn import_job(&mut self, path: &Path) -> Result<JobDocument> {
+        let document = json::import_job(path)?;
+        self.load_job(&document)?;
+        self.push_document_job(&document, JobSource::File(path.display().to_string()))?;
+        Ok(document)
+    }
+
+    pub fn load_job(&mut self, document: &JobDocument) -> Result<()> {
+        let lines = document.receipt_lines()?;
+        self.finish_jobs();
+        if !self.select_profile(&document.metadata.profile) {
+            warn!("Job profile {:?} is not known, keeping {}", document.metadata.profile, self.printer_state.profile.id);
+        }
+        self.printer_state.set_paper_width_mm(document.metadata.paper_width_mm);
+        self.command_history = document.command_entries(self)?.into();
+        self.diagnostics = document.diagnostic_entries().into();
+        self.printer_state.load_buffer(lines);
+        Ok(())
     }
 
     pub fn set_line_height(&mut self, height: u32) {
@@ -94,6 +836,12 @@ impl EmulatorState {
     }
 }
 
+impl Default for EmulatorState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug)]
 pub struct StatusSummary {
     pub paper_width: String,
@@ -105,4 +853,268 @@ pub struct StatusSummary {
     pub buffer_lines: usize,
     pub command_count: usize,
     pub dpi: u32,
+    pub profile: String,
+}
+
+/// Run slow work such as rendering on a blocking thread, so the caller's
+/// lock on the emulator is not held meanwhile; without a tokio runtime, run
+/// it right here.
+fn run_blocking(work: impl FnOnce() + Send + 'static) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => drop(runtime.spawn_blocking(work)),
+        Err(_) => work(),
+    }
+}
+
+/// A panic while writing one job leaves the index as it was, so a poisoned
+/// lock is still usable.
+fn lock_archive(archive: &SharedArchive) -> MutexGuard<'_, JobArchive> {
+    archive.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn feed(state: &mut EmulatorState, source: &JobSource, data: &[u8]) {
+        for parsed in state.new_parser().parse_stream(data).unwrap() {
+            state.process_command_from(source, &parsed);
+        }
+    }
+
+    fn texts(lines: &[ReceiptLine]) -> Vec<&str> {
+        lines
+            .iter()
+            .filter_map(|line| match line {
+                ReceiptLine::Text(text) if !text.text.is_empty() => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn interleaved_sources_keep_their_own_jobs() {
+        let mut state = EmulatorState::new();
+        let (a, b) = (JobSource::Tcp("10.0.0.1:5000".into()), JobSource::Tcp("10.0.0.2:5000".into()));
+        state.connection_opened(&a);
+        feed(&mut state, &a, b"A1\n");
+        state.connection_opened(&b);
+        feed(&mut state, &b, b"B1\n");
+        feed(&mut state, &a, b"A2\n");
+        feed(&mut state, &b, b"B2\n");
+
+        assert_eq!(state.active_jobs().count(), 2);
+        let job_a = state.source_job(&a).unwrap();
+        assert_eq!(texts(&state.job_lines(job_a)), ["A1", "A2"]);
+        assert_eq!(job_a.byte_count, 6);
+
+        state.connection_closed(&b);
+        let job_b = state.jobs.iter().find(|job| job.source == b).unwrap();
+        assert!(!job_b.active);
+        assert_eq!(texts(&job_b.lines), ["B1", "B2"]);
+        assert!(state.source_job(&a).is_some());
+
+        feed(&mut state, &a, b"A3\n");
+        state.connection_closed(&a);
+        assert_eq!(state.jobs.len(), 2);
+        assert_eq!(texts(&state.jobs[0].lines), ["A1", "A2", "A3"]);
+    }
+
+    #[test]
+    fn printed_lines_are_trimmed_once_no_active_job_needs_them() {
+        let mut state = EmulatorState::new();
+        state.trim_printed_lines = true;
+        let (a, b) = (JobSource::Serial("COM1".into()), JobSource::Local);
+        feed(&mut state, &a, b"A1\n");
+        feed(&mut state, &b, b"B1\n");
+        feed(&mut state, &a, b"A2\n");
+        let head = state.printer_state.fed_dots();
+        feed(&mut state, &a, b"\x1dV\x00");
+
+        // B1 is still needed, everything in front of it goes
+        assert_eq!(texts(state.printer_state.get_buffer())[0], "B1");
+        assert_eq!(state.printer_state.fed_dots(), head + state.printer_state.line_height);
+        feed(&mut state, &b, b"B2\n\x1dV\x00");
+        assert_eq!(texts(&state.jobs[0].lines), ["A1", "A2"]);
+        assert_eq!(texts(&state.jobs[1].lines), ["B1", "B2"]);
+        assert_eq!(state.printer_state.get_buffer().len(), 1);
+
+        feed(&mut state, &a, b"A3\n");
+        assert_eq!(texts(&state.job_lines(state.source_job(&a).unwrap())), ["A3"]);
+    }
+
+    #[test]
+    fn a_cut_only_finishes_the_job_of_its_own_source() {
+        let mut state = EmulatorState::new();
+        let (a, b) = (JobSource::Serial("COM1".into()), JobSource::Local);
+        feed(&mut state, &a, b"FIRST\n");
+        feed(&mut state, &b, b"OTHER\n\x1dV\x00");
+        feed(&mut state, &a, b"SECOND\n\x1dV\x00");
+
+        assert_eq!(state.active_jobs().count(), 0);
+        assert_eq!(state.jobs.len(), 2);
+        assert_eq!(texts(&state.jobs[0].lines), ["FIRST", "SECOND"]);
+        assert_eq!(texts(&state.jobs[1].lines), ["OTHER"]);
+    }
+
+    #[test]
+    fn diagnostics_go_to_the_job_of_their_own_source() {
+        let mut state = EmulatorState::new();
+        let (a, b) = (JobSource::Serial("COM1".into()), JobSource::Http("10.0.0.1:5000".into()));
+        feed(&mut state, &a, b"A1\n");
+        let jobs = state.print_bytes(&b, b"ONE\n\x1b\xff\x1dV\x00TWO\n\x1dV\x00\x1b");
+
+        assert!(state.source_job(&a).unwrap().diagnostics.is_empty());
+        let diagnostics = |id| {
+            let job = state.get_job(id).unwrap();
+            job.diagnostics.iter().map(|entry| entry.diagnostic.offset).collect::<Vec<_>>()
+        };
+        assert_eq!(diagnostics(jobs[0]), [4]);
+        assert_eq!(diagnostics(jobs[1]), [16]);
+        assert_eq!(state.get_diagnostics().len(), 2);
+    }
+
+    #[test]
+    fn a_macro_is_recorded_while_it_prints() {
+        let mut state = EmulatorState::new();
+        feed(&mut state, &JobSource::Local, b"\x1d:\x1bE\x01TOTAL\n\x1d:");
+        assert_eq!(state.printer_state.macro_buffer, b"\x1bE\x01TOTAL\n");
+        assert!(!state.printer_state.macro_recording);
+        assert_eq!(texts(state.printer_state.get_buffer()), ["TOTAL"]);
+    }
+
+    #[test]
+    fn a_macro_runs_repeat_times_through_the_command_log() {
+        let mut state = EmulatorState::new();
+        let source = JobSource::Local;
+        feed(&mut state, &source, b"\x1d:M\n\x1d:");
+        let logged = state.get_command_history().len();
+        feed(&mut state, &source, b"\x1d^\x03\x00\x00END\n");
+
+        assert_eq!(texts(state.printer_state.get_buffer()), ["M", "M", "M", "M", "END"]);
+        let job = state.source_job(&source).unwrap();
+        assert_eq!(texts(&state.job_lines(job)), ["M", "M", "M", "M", "END"]);
+        // GS ^, three runs of text and LF, then END and LF
+        assert_eq!(state.get_command_history().len(), logged + 1 + 3 * 2 + 2);
+        let run = &state.get_command_history()[logged + 1];
+        assert_eq!((run.raw_data.as_slice(), run.stream_offset), (&b"M"[..], 0));
+    }
+
+    #[test]
+    fn a_cut_in_a_macro_splits_jobs() {
+        let mut state = EmulatorState::new();
+        let source = JobSource::Tcp("10.0.0.1:5000".into());
+        let jobs = state.print_bytes(&source, b"\x1d:ITEM\n\x1dV\x00\x1d:\x1d^\x02\x00\x00");
+        assert_eq!(jobs.len(), 3);
+        for id in jobs {
+            assert_eq!(texts(&state.get_job(id).unwrap().lines), ["ITEM"]);
+        }
+    }
+
+    #[test]
+    fn macro_runs_wait_and_hold_back_later_input() {
+        let mut state = EmulatorState::new();
+        let source = JobSource::Local;
+        feed(&mut state, &source, b"\x1d:M\n\x1d:");
+        // Two runs 500 ms apart
+        feed(&mut state, &source, b"\x1d^\x02\x05\x00AFTER\n");
+        assert_eq!(texts(state.printer_state.get_buffer()), ["M", "M"]);
+
+        state.run_macro_at(SystemTime::now() + Duration::from_secs(1));
+        assert_eq!(texts(state.printer_state.get_buffer()), ["M", "M", "M", "AFTER"]);
+        assert!(state.running_macro.is_none());
+    }
+
+    #[test]
+    fn a_mode_1_macro_waits_for_the_feed_button() {
+        let mut state = EmulatorState::new();
+        let source = JobSource::Local;
+        feed(&mut state, &source, b"\x1d:M\n\x1d:");
+        feed(&mut state, &source, b"\x1d^\x01\x00\x01");
+        assert!(state.waiting_for_feed_button());
+        assert_eq!(texts(state.printer_state.get_buffer()), ["M"]);
+
+        state.press_feed_button();
+        assert!(!state.waiting_for_feed_button());
+        assert_eq!(texts(state.printer_state.get_buffer()), ["M", "M"]);
+    }
+
+    #[test]
+    fn print_bytes_returns_every_job_a_stream_is_cut_into() {
+        let mut state = EmulatorState::new();
+        let source = JobSource::Http("10.0.0.1:5000".into());
+        let jobs = state.print_bytes(&source, b"ONE\n\x1dV\x00TWO\n\x1dV\x00THREE\n");
+        assert_eq!(jobs, [1, 2, 3]);
+        assert_eq!(texts(&state.get_job(3).unwrap().lines), ["THREE"]);
+        assert!(state.print_bytes(&source, b"").is_empty());
+    }
+
+    #[test]
+    fn finished_jobs_are_archived_with_their_own_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = EmulatorState::new();
+        state.open_archive(dir.path(), RetentionPolicy::default()).unwrap();
+        let (a, b) = (JobSource::Local, JobSource::Serial("COM1".into()));
+        feed(&mut state, &a, b"ALPHA\n");
+        feed(&mut state, &b, b"BRAVO\n\x1dV\x00");
+        state.finish_jobs();
+
+        let archive = state.archive().unwrap();
+        // BRAVO finished first, at its cut, but the index stays in start order
+        let texts: Vec<&str> = archive.entries().iter().map(|entry| entry.text.lines().next().unwrap().trim()).collect();
+        assert_eq!(texts, ["ALPHA", "BRAVO"]);
+        assert!(archive.png_path(&archive.entries()[1].id).unwrap().exists());
+    }
+
+    #[tokio::test]
+    async fn archiving_happens_off_the_calling_thread() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = EmulatorState::new();
+        state.open_archive(dir.path(), RetentionPolicy::default()).unwrap();
+        feed(&mut state, &JobSource::Local, b"ALPHA\n");
+        state.finish_jobs();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while state.archive().unwrap().entries().is_empty() {
+            assert!(std::time::Instant::now() < deadline, "job was never archived");
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(state.archive().unwrap().entries()[0].text.trim(), "ALPHA");
+    }
+
+    #[test]
+    fn a_finished_job_is_rendered_once_for_every_subscriber() {
+        let mut state = EmulatorState::new();
+        let mut first = state.subscribe_events();
+        let mut second = state.subscribe_events();
+        feed(&mut state, &JobSource::Local, b"RECEIPT\n\x1dV\x00");
+
+        let finished = |events: &mut broadcast::Receiver<EmulatorEvent>| loop {
+            if let EmulatorEvent::JobFinished { snapshots, .. } = events.try_recv().unwrap() {
+                return snapshots;
+            }
+        };
+        let (first, second) = (finished(&mut first), finished(&mut second));
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(first.png.as_deref().is_some_and(|png| png.starts_with("data:image/png;base64,")));
+        assert!(first.html.contains("RECEIPT"));
+    }
+
+    #[tokio::test]
+    async fn job_finished_is_published_from_a_blocking_task() {
+        let mut state = EmulatorState::new();
+        let mut events = state.subscribe_events();
+        feed(&mut state, &JobSource::Local, b"RECEIPT\n\x1dV\x00");
+        let finished = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                if let Ok(EmulatorEvent::JobFinished { job, snapshots }) = events.recv().await {
+                    return (job, snapshots);
+                }
+            }
+        });
+        let (job, snapshots) = finished.await.unwrap();
+        assert_eq!(job.id, 1);
+        assert!(snapshots.html.contains("RECEIPT"));
+    }
 }
@@ -0,0 +1,735 @@
+//! Import of pcap and pcapng network traces.
+//!
+//! TCP streams to and from the printer port are reassembled in both
+//! directions and turned into a `Session`, one connection per TCP connection,
+//! so a trace replays like a recording and keeps its own timestamps.
+//! Ethernet (with VLAN tags), Linux cooked v1/v2, BSD loopback and raw IP link
+//! types are understood. IP fragments are skipped.
+
+use super::session::{Session, SessionEvent, SessionEventKind, SessionHeader, SESSION_FORMAT, SESSION_FORMAT_VERSION};
+use super::JobSource;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use tracing::warn;
+
+/// Raw printing port that traces are filtered on unless told otherwise.
+pub const DEFAULT_PRINTER_PORT: u16 = 9100;
+
+/// Read a trace and reassemble the TCP connections to `port` on any host.
+pub fn read_capture(path: &Path, port: u16) -> Result<Session> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let packets = parse_packets(&data).with_context(|| format!("Failed to read capture {}", path.display()))?;
+    let mut assembler = StreamAssembler::new(port);
+    for packet in &packets {
+        if let Some(segment) = ip_payload(packet.link_type, packet.data).and_then(tcp_segment) {
+            assembler.push(packet.time, &segment);
+        }
+    }
+    let session = assembler.finish();
+    if session.events.is_empty() {
+        bail!("{} has no TCP traffic on port {}", path.display(), port);
+    }
+    Ok(session)
+}
+
+/// pcap or pcapng, told apart by the magic number.
+fn parse_packets(data: &[u8]) -> Result<Vec<Packet<'_>>> {
+    match data.get(..4) {
+        Some([0x0a, 0x0d, 0x0d, 0x0a]) => parse_pcapng(data),
+        Some(_) => parse_pcap(data),
+        None => bail!("File is too short to be a capture"),
+    }
+}
+
+struct Packet<'a> {
+    time: DateTime<Utc>,
+    link_type: u32,
+    data: &'a [u8],
+}
+
+#[derive(Clone, Copy)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    fn u16(self, data: &[u8], at: usize) -> Option<u16> {
+        let bytes: [u8; 2] = data.get(at..at + 2)?.try_into().ok()?;
+        Some(match self {
+            Endian::Little => u16::from_le_bytes(bytes),
+            Endian::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32(self, data: &[u8], at: usize) -> Option<u32> {
+        let bytes: [u8; 4] = data.get(at..at + 4)?.try_into().ok()?;
+        Some(match self {
+            Endian::Little => u32::from_le_bytes(bytes),
+            Endian::Big => u32::from_be_bytes(bytes),
+        })
+    }
+}
+
+fn parse_pcap(data: &[u8]) -> Result<Vec<Packet<'_>>> {
+    let (endian, nanos) = match Endian::Little.u32(data, 0) {
+        Some(0xa1b2_c3d4) => (Endian::Little, false),
+        Some(0xa1b2_3c4d) => (Endian::Little, true),
+        Some(0xd4c3_b2a1) => (Endian::Big, false),
+        Some(0x4d3c_b2a1) => (Endian::Big, true),
+        _ => bail!("Not a pcap or pcapng file"),
+    };
+    let link_type = endian.u32(data, 20).context("Truncated pcap header")?;
+    let units_per_second = if nanos { 1_000_000_000 } else { 1_000_000 };
+
+    let mut packets = Vec::new();
+    let mut offset = 24;
+    while offset + 16 <= data.len() {
+        let seconds = endian.u32(data, offset).unwrap_or(0) as u64;
+        let fraction = endian.u32(data, offset + 4).unwrap_or(0) as u64;
+        let length = endian.u32(data, offset + 8).unwrap_or(0) as usize;
+        let start = offset + 16;
+        let frame = match data.get(start..start + length) {
+            Some(frame) => frame,
+            None => {
+                warn!("Capture ends inside a packet, ignoring the rest");
+                break;
+            }
+        };
+        packets.push(Packet {
+            time: timestamp(seconds * units_per_second + fraction, units_per_second),
+            link_type,
+            data: frame,
+        });
+        offset = start + length;
+    }
+    Ok(packets)
+}
+
+fn parse_pcapng(data: &[u8]) -> Result<Vec<Packet<'_>>> {
+    const SECTION_HEADER: u32 = 0x0a0d_0d0a;
+    const INTERFACE_DESCRIPTION: u32 = 1;
+    const SIMPLE_PACKET: u32 = 3;
+    const ENHANCED_PACKET: u32 = 6;
+
+    let mut endian = Endian::Little;
+    // Link type and timestamp units per second of each interface in the section
+    let mut interfaces: Vec<(u32, u64)> = Vec::new();
+    let mut packets = Vec::new();
+    let mut last_time = DateTime::<Utc>::UNIX_EPOCH;
+    let mut offset = 0;
+    while offset + 12 <= data.len() {
+        if Endian::Little.u32(data, offset) == Some(SECTION_HEADER) {
+            endian = match data.get(offset + 8..offset + 12) {
+                Some([0x4d, 0x3c, 0x2b, 0x1a]) => Endian::Little,
+                Some([0x1a, 0x2b, 0x3c, 0x4d]) => Endian::Big,
+                _ => bail!("Invalid pcapng byte-order magic"),
+            };
+            interfaces.clear();
+        }
+        let block_type = endian.u32(data, offset).unwrap_or(0);
+        let block_length = endian.u32(data, offset + 4).unwrap_or(0) as usize;
+        if block_length < 12 || offset + block_length > data.len() {
+            warn!("Capture ends inside a block, ignoring the rest");
+            break;
+        }
+        let body = &data[offset + 8..offset + block_length - 4];
+        match block_type {
+            INTERFACE_DESCRIPTION => {
+                let link_type = endian.u16(body, 0).unwrap_or(0) as u32;
+                interfaces.push((link_type, interface_resolution(endian, body.get(8..).unwrap_or(&[]))));
+            }
+            ENHANCED_PACKET => {
+                let interface = endian.u32(body, 0).unwrap_or(0) as usize;
+                let high = endian.u32(body, 4).unwrap_or(0) as u64;
+                let low = endian.u32(body, 8).unwrap_or(0) as u64;
+                let length = endian.u32(body, 12).unwrap_or(0) as usize;
+                let (link_type, units_per_second) = match interfaces.get(interface) {
+                    Some(&interface) => interface,
+                    None => bail!("Packet refers to undeclared interface {}", interface),
+                };
+                if let Some(frame) = body.get(20..20 + length) {
+                    last_time = timestamp((high << 32) | low, units_per_second);
+                    packets.push(Packet { time: last_time, link_type, data: frame });
+                }
+            }
+            SIMPLE_PACKET => {
+                // No timestamp: it belongs right after the previous packet
+                let length = endian.u32(body, 0).unwrap_or(0) as usize;
+                if let (Some(&(link_type, _)), Some(frame)) = (interfaces.first(), body.get(4..4 + length)) {
+                    packets.push(Packet { time: last_time, link_type, data: frame });
+                }
+            }
+            _ => {}
+        }
+        offset += block_length;
+    }
+    Ok(packets)
+}
+
+/// Timestamp units per second from an interface's `if_tsresol` option.
+fn interface_resolution(endian: Endian, mut options: &[u8]) -> u64 {
+    const END_OF_OPTIONS: u16 = 0;
+    const IF_TSRESOL: u16 = 9;
+
+    while let (Some(code), Some(length)) = (endian.u16(options, 0), endian.u16(options, 2)) {
+        let length = length as usize;
+        if code == END_OF_OPTIONS {
+            break;
+        }
+        if code == IF_TSRESOL {
+            if let Some(&resolution) = options.get(4) {
+                let exponent = (resolution & 0x7f) as u32;
+                // The top bit selects a power of two instead of ten
+                let base: u64 = if resolution & 0x80 == 0 { 10 } else { 2 };
+                return base.checked_pow(exponent).unwrap_or(1_000_000);
+            }
+        }
+        let padded = 4 + length.div_ceil(4) * 4;
+        options = options.get(padded..).unwrap_or(&[]);
+    }
+    1_000_000
+}
+
+fn timestamp(units: u64, units_per_second: u64) -> DateTime<Utc> {
+    let units_per_second = units_per_second.max(1);
+    let seconds = (units / units_per_second) as i64;
+    let nanos = ((units % units_per_second) as u128 * 1_000_000_000 / units_per_second as u128) as u32;
+    DateTime::from_timestamp(seconds, nanos).unwrap_or_default()
+}
+
+/// The IP packet inside a link-layer frame.
+fn ip_payload(link_type: u32, frame: &[u8]) -> Option<&[u8]> {
+    const ETHERNET: u32 = 1;
+    const NULL: u32 = 0;
+    const LOOP: u32 = 108;
+    const LINUX_SLL: u32 = 113;
+    const LINUX_SLL2: u32 = 276;
+
+    match link_type {
+        ETHERNET => {
+            let mut ether_type = u16::from_be_bytes(frame.get(12..14)?.try_into().ok()?);
+            let mut offset = 14;
+            // 802.1Q and 802.1ad tags
+            while ether_type == 0x8100 || ether_type == 0x88a8 {
+                ether_type = u16::from_be_bytes(frame.get(offset + 2..offset + 4)?.try_into().ok()?);
+                offset += 4;
+            }
+            frame.get(offset..)
+        }
+        NULL | LOOP => frame.get(4..),
+        LINUX_SLL => frame.get(16..),
+        LINUX_SLL2 => frame.get(20..),
+        // Raw IP under its various numbers
+        12 | 14 | 101 | 228 | 229 => Some(frame),
+        _ => None,
+    }
+}
+
+struct TcpSegment<'a> {
+    source: SocketAddr,
+    destination: SocketAddr,
+    sequence: u32,
+    syn: bool,
+    fin: bool,
+    rst: bool,
+    payload: &'a [u8],
+}
+
+fn tcp_segment(ip: &[u8]) -> Option<TcpSegment<'_>> {
+    const TCP: u8 = 6;
+
+    let (source, destination, tcp) = match ip.first()? >> 4 {
+        4 => {
+            let header_length = ((ip[0] & 0x0f) as usize) * 4;
+            let total_length = u16::from_be_bytes(ip.get(2..4)?.try_into().ok()?) as usize;
+            let fragment = u16::from_be_bytes(ip.get(6..8)?.try_into().ok()?);
+            // More-fragments flag or a fragment offset
+            if ip.get(9)? != &TCP || fragment & 0x3fff != 0 {
+                return None;
+            }
+            let source: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            let destination: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+            // Frames can be padded past the IP packet; a zero length means segmentation offload
+            let end = if total_length == 0 { ip.len() } else { total_length.min(ip.len()) };
+            (
+                IpAddr::V4(Ipv4Addr::from(source)),
+                IpAddr::V4(Ipv4Addr::from(destination)),
+                ip.get(header_length..end)?,
+            )
+        }
+        6 => {
+            let payload_length = u16::from_be_bytes(ip.get(4..6)?.try_into().ok()?) as usize;
+            let source: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let destination: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            let end = if payload_length == 0 { ip.len() } else { (40 + payload_length).min(ip.len()) };
+            let mut next_header = *ip.get(6)?;
+            let mut offset = 40;
+            // Hop-by-hop, routing and destination options headers
+            while matches!(next_header, 0 | 43 | 60) {
+                next_header = *ip.get(offset)?;
+                offset += (*ip.get(offset + 1)? as usize + 1) * 8;
+            }
+            if next_header != TCP {
+                return None;
+            }
+            (
+                IpAddr::V6(Ipv6Addr::from(source)),
+                IpAddr::V6(Ipv6Addr::from(destination)),
+                ip.get(offset..end)?,
+            )
+        }
+        _ => return None,
+    };
+
+    let source_port = u16::from_be_bytes(tcp.get(0..2)?.try_into().ok()?);
+    let destination_port = u16::from_be_bytes(tcp.get(2..4)?.try_into().ok()?);
+    let sequence = u32::from_be_bytes(tcp.get(4..8)?.try_into().ok()?);
+    let data_offset = ((tcp.get(12)? >> 4) as usize) * 4;
+    let flags = *tcp.get(13)?;
+    Some(TcpSegment {
+        source: SocketAddr::new(source, source_port),
+        destination: SocketAddr::new(destination, destination_port),
+        sequence,
+        fin: flags & 0x01 != 0,
+        syn: flags & 0x02 != 0,
+        rst: flags & 0x04 != 0,
+        payload: tcp.get(data_offset..)?,
+    })
+}
+
+/// One direction of a TCP connection, put back in sequence order.
+#[derive(Default)]
+struct HalfStream {
+    /// Sequence number of the SYN, once seen
+    initial_sequence: Option<u32>,
+    next_sequence: Option<u32>,
+    /// Segments that arrived ahead of a gap
+    pending: Vec<(u32, Vec<u8>)>,
+    fin: bool,
+}
+
+impl HalfStream {
+    /// Take a segment and return the bytes that are now contiguous.
+    fn push(&mut self, segment: &TcpSegment) -> Vec<u8> {
+        let mut sequence = segment.sequence;
+        if segment.syn {
+            // The SYN takes up one sequence number; a retransmitted one must
+            // not rewind the stream
+            sequence = sequence.wrapping_add(1);
+            if self.initial_sequence != Some(segment.sequence) {
+                self.initial_sequence = Some(segment.sequence);
+                self.next_sequence = Some(sequence);
+            }
+        }
+        let mut next = *self.next_sequence.get_or_insert(sequence);
+        if !segment.payload.is_empty() {
+            self.pending.push((sequence, segment.payload.to_vec()));
+        }
+
+        let mut out = Vec::new();
+        // Sequence numbers wrap, so compare them as signed distances from `next`
+        while let Some(index) = self.pending.iter().position(|(start, data)| {
+            let distance = start.wrapping_sub(next) as i32 as i64;
+            distance <= 0 && distance + data.len() as i64 > 0
+        }) {
+            let (start, data) = self.pending.swap_remove(index);
+            // Retransmitted bytes overlapping what was already delivered are skipped
+            let skip = next.wrapping_sub(start) as usize;
+            out.extend_from_slice(&data[skip..]);
+            next = next.wrapping_add((data.len() - skip) as u32);
+        }
+        self.pending
+            .retain(|(start, data)| start.wrapping_sub(next) as i32 as i64 + data.len() as i64 > 0);
+        self.next_sequence = Some(next);
+        self.fin |= segment.fin;
+        out
+    }
+
+    /// Whatever is left after gaps that were never filled, in order.
+    fn drain_after_gaps(&mut self) -> (Vec<u8>, u64) {
+        let mut out = Vec::new();
+        let mut missing = 0;
+        let mut next = match self.next_sequence {
+            Some(next) => next,
+            None => return (out, missing),
+        };
+        self.pending.sort_by_key(|(start, _)| start.wrapping_sub(next));
+        for (start, data) in std::mem::take(&mut self.pending) {
+            let distance = start.wrapping_sub(next) as i32 as i64;
+            if distance + data.len() as i64 <= 0 {
+                continue;
+            }
+            let skip = if distance < 0 { (-distance) as usize } else { 0 };
+            missing += distance.max(0) as u64;
+            out.extend_from_slice(&data[skip..]);
+            next = start.wrapping_add(data.len() as u32);
+        }
+        (out, missing)
+    }
+}
+
+struct TcpConnection {
+    id: u64,
+    source: JobSource,
+    /// Client to printer
+    inbound: HalfStream,
+    /// Printer to client
+    outbound: HalfStream,
+}
+
+/// Collects the printer-port connections of a trace as session events.
+struct StreamAssembler {
+    port: u16,
+    /// Open connections by client address and printer address
+    connections: HashMap<(SocketAddr, SocketAddr), TcpConnection>,
+    next_connection: u64,
+    /// Events with their absolute time
+    events: Vec<(DateTime<Utc>, SessionEvent)>,
+}
+
+impl StreamAssembler {
+    fn new(port: u16) -> Self {
+        Self {
+            port,
+            connections: HashMap::new(),
+            next_connection: 1,
+            events: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, time: DateTime<Utc>, segment: &TcpSegment) {
+        let (key, inbound) = if segment.destination.port() == self.port {
+            ((segment.source, segment.destination), true)
+        } else if segment.source.port() == self.port {
+            ((segment.destination, segment.source), false)
+        } else {
+            return;
+        };
+
+        // A SYN from the client with a new initial sequence number on a known
+        // address pair is a reused port; with the same one, a retransmission
+        let reused = |connection: &TcpConnection| connection.inbound.initial_sequence != Some(segment.sequence);
+        if inbound && segment.syn && self.connections.get(&key).is_some_and(reused) {
+            self.close(time, &key);
+        }
+        if !self.connections.contains_key(&key) {
+            // Stray packets of a connection that already closed
+            if segment.rst || (segment.fin && segment.payload.is_empty()) {
+                return;
+            }
+            let connection = TcpConnection {
+                id: self.next_connection,
+                source: JobSource::Tcp(key.0.to_string()),
+                inbound: HalfStream::default(),
+                outbound: HalfStream::default(),
+            };
+            self.next_connection += 1;
+            self.event(time, &connection, SessionEventKind::Connect, Vec::new());
+            self.connections.insert(key, connection);
+        }
+
+        let connection = match self.connections.get_mut(&key) {
+            Some(connection) => connection,
+            None => return,
+        };
+        let (kind, stream) = if inbound {
+            (SessionEventKind::Inbound, &mut connection.inbound)
+        } else {
+            (SessionEventKind::Outbound, &mut connection.outbound)
+        };
+        let data = stream.push(segment);
+        let done = segment.rst || (connection.inbound.fin && connection.outbound.fin);
+        let (id, source) = (connection.id, connection.source.clone());
+        if !data.is_empty() {
+            self.events.push((time, new_event(id, &source, kind, data)));
+        }
+        if done {
+            self.close(time, &key);
+        }
+    }
+
+    fn close(&mut self, time: DateTime<Utc>, key: &(SocketAddr, SocketAddr)) {
+        let mut connection = match self.connections.remove(key) {
+            Some(connection) => connection,
+            None => return,
+        };
+        for (kind, stream) in [
+            (SessionEventKind::Inbound, &mut connection.inbound),
+            (SessionEventKind::Outbound, &mut connection.outbound),
+        ] {
+            let (data, missing) = stream.drain_after_gaps();
+            if missing > 0 {
+                warn!("{}: {} byte(s) missing from the capture", connection.source, missing);
+            }
+            if !data.is_empty() {
+                self.events.push((time, new_event(connection.id, &connection.source, kind, data)));
+            }
+        }
+        self.event(time, &connection, SessionEventKind::Disconnect, Vec::new());
+    }
+
+    fn event(&mut self, time: DateTime<Utc>, connection: &TcpConnection, kind: SessionEventKind, data: Vec<u8>) {
+        self.events.push((time, new_event(connection.id, &connection.source, kind, data)));
+    }
+
+    /// Close what the trace left open and build the session.
+    fn finish(mut self) -> Session {
+        let end = self.events.last().map(|(time, _)| *time).unwrap_or_default();
+        let mut open: Vec<_> = self.connections.iter().map(|(key, connection)| (connection.id, *key)).collect();
+        open.sort();
+        for (_, key) in open {
+            self.close(end, &key);
+        }
+
+        let started_at = self.events.first().map(|(time, _)| *time).unwrap_or_default();
+        let events = self
+            .events
+            .into_iter()
+            .map(|(time, mut event)| {
+                // Traces are not always in time order; clamp rather than wrap
+                event.at_us = (time - started_at).num_microseconds().unwrap_or(0).max(0) as u64;
+                event
+            })
+            .collect();
+        Session {
+            header: SessionHeader {
+                format: SESSION_FORMAT.to_string(),
+                version: SESSION_FORMAT_VERSION,
+                started_at,
+                emulator_version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            events,
+        }
+    }
+}
+
+fn new_event(connection: u64, source: &JobSource, kind: SessionEventKind, data: Vec<u8>) -> SessionEvent {
+    SessionEvent {
+        at_us: 0,
+        connection,
+        source: source.clone(),
+        kind,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYN: u8 = 0x02;
+    const ACK: u8 = 0x10;
+    const FIN_ACK: u8 = 0x11;
+
+    fn tcp(source: u16, destination: u16, sequence: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = Vec::new();
+        segment.extend_from_slice(&source.to_be_bytes());
+        segment.extend_from_slice(&destination.to_be_bytes());
+        segment.extend_from_slice(&sequence.to_be_bytes());
+        segment.extend_from_slice(&[0, 0, 0, 0, 5 << 4, flags, 0xff, 0xff, 0, 0, 0, 0]);
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    fn ipv4(source: [u8; 4], destination: [u8; 4], segment: Vec<u8>) -> Vec<u8> {
+        let length = (20 + segment.len()) as u16;
+        let mut packet = vec![0x45, 0];
+        packet.extend_from_slice(&length.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 0, 64, 6, 0, 0]);
+        packet.extend_from_slice(&source);
+        packet.extend_from_slice(&destination);
+        packet.extend(segment);
+        packet
+    }
+
+    fn ipv6(source: Ipv6Addr, destination: Ipv6Addr, segment: Vec<u8>) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[6, 64]);
+        packet.extend_from_slice(&source.octets());
+        packet.extend_from_slice(&destination.octets());
+        packet.extend(segment);
+        packet
+    }
+
+    fn ethernet(vlan: Option<u16>, ip: Vec<u8>) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        if let Some(id) = vlan {
+            frame.extend_from_slice(&[0x81, 0x00]);
+            frame.extend_from_slice(&id.to_be_bytes());
+        }
+        let ether_type: u16 = if ip[0] >> 4 == 6 { 0x86dd } else { 0x0800 };
+        frame.extend_from_slice(&ether_type.to_be_bytes());
+        frame.extend(ip);
+        frame
+    }
+
+    /// Client 10.0.0.2:40000 to the printer at 10.0.0.1:9100.
+    fn to_printer(sequence: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        ethernet(None, ipv4([10, 0, 0, 2], [10, 0, 0, 1], tcp(40000, 9100, sequence, flags, payload)))
+    }
+
+    /// A little-endian pcap of Ethernet frames with microsecond timestamps.
+    fn pcap(frames: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut file = Vec::new();
+        for word in [0xa1b2_c3d4u32, 0x0004_0002, 0, 0, 65535, 1] {
+            file.extend_from_slice(&word.to_le_bytes());
+        }
+        for (micros, frame) in frames {
+            let length = frame.len() as u32;
+            for word in [(micros / 1_000_000) as u32, (micros % 1_000_000) as u32, length, length] {
+                file.extend_from_slice(&word.to_le_bytes());
+            }
+            file.extend_from_slice(frame);
+        }
+        file
+    }
+
+    fn block(file: &mut Vec<u8>, block_type: u32, mut body: Vec<u8>) {
+        body.resize(body.len().div_ceil(4) * 4, 0);
+        let length = (body.len() + 12) as u32;
+        file.extend_from_slice(&block_type.to_le_bytes());
+        file.extend_from_slice(&length.to_le_bytes());
+        file.extend(body);
+        file.extend_from_slice(&length.to_le_bytes());
+    }
+
+    /// A little-endian pcapng with one Ethernet interface using `if_tsresol`.
+    fn pcapng(resolution: u8, frames: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut file = Vec::new();
+        let mut section = vec![0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0];
+        section.extend_from_slice(&u64::MAX.to_le_bytes());
+        block(&mut file, 0x0a0d_0d0a, section);
+        let mut interface = vec![1, 0, 0, 0, 0, 0, 0, 0];
+        interface.extend_from_slice(&[9, 0, 1, 0, resolution, 0, 0, 0, 0, 0, 0, 0]);
+        block(&mut file, 1, interface);
+        for (units, frame) in frames {
+            let mut packet = Vec::new();
+            let length = frame.len() as u32;
+            for word in [0, (units >> 32) as u32, *units as u32, length, length] {
+                packet.extend_from_slice(&word.to_le_bytes());
+            }
+            packet.extend_from_slice(frame);
+            block(&mut file, 6, packet);
+        }
+        file
+    }
+
+    /// Frames one microsecond apart.
+    fn numbered<const N: usize>(frames: [Vec<u8>; N]) -> Vec<(u64, Vec<u8>)> {
+        frames.into_iter().enumerate().map(|(i, frame)| (i as u64, frame)).collect()
+    }
+
+    fn assemble(file: &[u8]) -> Session {
+        let mut assembler = StreamAssembler::new(DEFAULT_PRINTER_PORT);
+        for packet in parse_packets(file).unwrap() {
+            if let Some(segment) = ip_payload(packet.link_type, packet.data).and_then(tcp_segment) {
+                assembler.push(packet.time, &segment);
+            }
+        }
+        assembler.finish()
+    }
+
+    fn inbound(session: &Session) -> Vec<u8> {
+        session
+            .events
+            .iter()
+            .filter(|event| event.kind == SessionEventKind::Inbound)
+            .flat_map(|event| event.data.iter().copied())
+            .collect()
+    }
+
+    fn connections(session: &Session) -> usize {
+        session.events.iter().filter(|event| event.kind == SessionEventKind::Connect).count()
+    }
+
+    #[test]
+    fn out_of_order_and_retransmitted_segments_are_reassembled() {
+        let frames = [
+            to_printer(1000, SYN, b""),
+            to_printer(1006, ACK, b"WORLD\n"),
+            to_printer(1001, ACK, b"HELLO"),
+            // Retransmissions, one overlapping what was delivered
+            to_printer(1001, ACK, b"HELLO"),
+            to_printer(1004, ACK, b"LOWORLD\n"),
+            // A retransmitted SYN keeps the connection
+            to_printer(1000, SYN, b""),
+            to_printer(1012, ACK, b"BYE\n"),
+            to_printer(1016, FIN_ACK, b""),
+        ];
+        let session = assemble(&pcap(&numbered(frames)));
+        assert_eq!(inbound(&session), b"HELLOWORLD\nBYE\n");
+        assert_eq!(connections(&session), 1);
+        assert_eq!(session.events[0].source, JobSource::Tcp("10.0.0.2:40000".into()));
+    }
+
+    #[test]
+    fn a_syn_with_a_new_initial_sequence_is_a_new_connection() {
+        let frames = [
+            to_printer(1000, SYN, b""),
+            to_printer(1001, ACK, b"ONE\n"),
+            to_printer(5000, SYN, b""),
+            to_printer(5001, ACK, b"TWO\n"),
+        ];
+        let session = assemble(&pcap(&numbered(frames)));
+        assert_eq!(connections(&session), 2);
+        assert_eq!(inbound(&session), b"ONE\nTWO\n");
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_around() {
+        let start = u32::MAX - 2;
+        let frames = [
+            to_printer(start, SYN, b""),
+            // Lands past the wrap, ahead of the bytes in front of it
+            to_printer(start.wrapping_add(5), ACK, b"EFG\n"),
+            to_printer(start.wrapping_add(1), ACK, b"ABCD"),
+        ];
+        assert_eq!(inbound(&assemble(&pcap(&numbered(frames)))), b"ABCDEFG\n");
+    }
+
+    #[test]
+    fn pcapng_timestamps_follow_the_interface_resolution() {
+        // 10^-9 seconds, then 2^-10 seconds
+        for (resolution, units_per_second) in [(9u8, 1_000_000_000u64), (0x8a, 1024)] {
+            let frames = [
+                (10 * units_per_second, to_printer(1000, SYN, b"")),
+                (10 * units_per_second + units_per_second / 2, to_printer(1001, ACK, b"LATER\n")),
+            ];
+            let session = assemble(&pcapng(resolution, &frames));
+            let data = session.events.iter().find(|event| event.kind == SessionEventKind::Inbound).unwrap();
+            assert_eq!(data.at_us, 500_000, "if_tsresol {:#x}", resolution);
+            assert_eq!(session.header.started_at.timestamp(), 10);
+        }
+    }
+
+    #[test]
+    fn vlan_tagged_frames_are_unwrapped() {
+        let tagged = |sequence, flags, payload: &[u8]| {
+            ethernet(Some(42), ipv4([10, 0, 0, 2], [10, 0, 0, 1], tcp(40000, 9100, sequence, flags, payload)))
+        };
+        let frames = [(0, tagged(7, SYN, b"")), (1, tagged(8, ACK, b"TAGGED\n"))];
+        assert_eq!(inbound(&assemble(&pcap(&frames))), b"TAGGED\n");
+    }
+
+    #[test]
+    fn ipv6_connections_are_followed() {
+        let client = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 2);
+        let printer = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let frames = [
+            (0, ethernet(None, ipv6(client, printer, tcp(40000, 9100, 0, SYN, b"")))),
+            (1, ethernet(None, ipv6(client, printer, tcp(40000, 9100, 1, ACK, b"V6\n")))),
+            // Status answer from the printer
+            (2, ethernet(None, ipv6(printer, client, tcp(9100, 40000, 70, ACK, b"\x12")))),
+        ];
+        let session = assemble(&pcap(&frames));
+        assert_eq!(inbound(&session), b"V6\n");
+        assert_eq!(session.events[0].source, JobSource::Tcp("[fe80::2]:40000".into()));
+        let outbound = session.events.iter().find(|event| event.kind == SessionEventKind::Outbound).unwrap();
+        assert_eq!(outbound.data, b"\x12");
+    }
+}
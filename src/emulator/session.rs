@@ -0,0 +1,398 @@
+//! Session recording and replay of raw input streams.
+//!
+//! A capture file is JSON Lines: a header, then one event per line in the
+//! order the emulator saw them. Events carry the time since recording started,
+//! the connection they belong to and the bytes as hex, including the status
+//! replies sent back, so timing-related bugs can be reproduced byte for byte.
+
+use super::{EmulatorState, JobSource};
+use crate::escpos::parser::EscPosParser;
+use crate::export::json::{from_hex, to_hex};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Value of the header's `format` field.
+pub const SESSION_FORMAT: &str = "escpos-emulator-session";
+/// Version written by this build; captures up to this version can be replayed.
+pub const SESSION_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionHeader {
+    pub format: String,
+    pub version: u32,
+    pub started_at: DateTime<Utc>,
+    pub emulator_version: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEventKind {
+    /// A client connected
+    Connect,
+    /// Bytes received from the client
+    Inbound,
+    /// Status bytes sent back to the client
+    Outbound,
+    /// The client went away
+    Disconnect,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEvent {
+    /// Microseconds since the recording started
+    pub at_us: u64,
+    /// Numbered in order of appearance within the capture
+    pub connection: u64,
+    pub source: JobSource,
+    pub kind: SessionEventKind,
+    #[serde(default, serialize_with = "serialize_hex", deserialize_with = "deserialize_hex")]
+    pub data: Vec<u8>,
+}
+
+impl SessionEvent {
+    pub fn at(&self) -> Duration {
+        Duration::from_micros(self.at_us)
+    }
+}
+
+/// Appends events to a capture file as they happen.
+#[derive(Debug, Clone)]
+pub struct SessionRecorder {
+    path: PathBuf,
+    /// Open for the whole recording
+    file: Arc<std::sync::Mutex<BufWriter<File>>>,
+    started: Instant,
+    /// Connection ids of the sources currently connected
+    connections: HashMap<JobSource, u64>,
+    next_connection: u64,
+    event_count: usize,
+}
+
+impl SessionRecorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create capture {}", path.display()))?;
+        let recorder = Self {
+            path: path.to_path_buf(),
+            file: Arc::new(std::sync::Mutex::new(BufWriter::new(file))),
+            started: Instant::now(),
+            connections: HashMap::new(),
+            next_connection: 1,
+            event_count: 0,
+        };
+        let header = SessionHeader {
+            format: SESSION_FORMAT.to_string(),
+            version: SESSION_FORMAT_VERSION,
+            started_at: Utc::now(),
+            emulator_version: env!("CARGO_PKG_VERSION").to_string(),
+        };
+        recorder.write_line(&header)?;
+        info!("Recording session to {}", path.display());
+        Ok(recorder)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn event_count(&self) -> usize {
+        self.event_count
+    }
+
+    /// Append an event. Data from a source that never connected, such as a
+    /// serial port, opens a connection id implicitly.
+    pub fn record(&mut self, source: &JobSource, kind: SessionEventKind, data: &[u8]) -> Result<()> {
+        let connection = match (kind, self.connections.get(source)) {
+            (SessionEventKind::Connect, _) | (_, None) => {
+                let id = self.next_connection;
+                self.next_connection += 1;
+                self.connections.insert(source.clone(), id);
+                id
+            }
+            (_, Some(&id)) => id,
+        };
+        if kind == SessionEventKind::Disconnect {
+            self.connections.remove(source);
+        }
+        let event = SessionEvent {
+            at_us: self.started.elapsed().as_micros() as u64,
+            connection,
+            source: source.clone(),
+            kind,
+            data: data.to_vec(),
+        };
+        self.write_line(&event)?;
+        self.event_count += 1;
+        Ok(())
+    }
+
+    /// Each line is flushed at once, so a capture survives the crash it is
+    /// meant to explain.
+    fn write_line<T: Serialize>(&self, value: &T) -> Result<()> {
+        let mut line = serde_json::to_vec(value).context("Failed to serialize capture event")?;
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        file.write_all(&line)
+            .and_then(|()| file.flush())
+            .with_context(|| format!("Failed to write capture {}", self.path.display()))
+    }
+}
+
+/// A capture file read back into memory.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub header: SessionHeader,
+    pub events: Vec<SessionEvent>,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open capture {}", path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+        let header: SessionHeader = match lines.next() {
+            Some(line) => serde_json::from_str(&line?).context("Capture header is not valid JSON")?,
+            None => bail!("{} is empty", path.display()),
+        };
+        if header.format != SESSION_FORMAT {
+            bail!("Not an ESC/POS emulator capture (expected format \"{}\")", SESSION_FORMAT);
+        }
+        if header.version == 0 || header.version > SESSION_FORMAT_VERSION {
+            bail!(
+                "Unsupported capture version {} (this build reads 1..={})",
+                header.version,
+                SESSION_FORMAT_VERSION
+            );
+        }
+        let mut events = Vec::new();
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // The header is line 1
+            let event = serde_json::from_str(&line).with_context(|| format!("Invalid event on line {}", index + 2))?;
+            events.push(event);
+        }
+        Ok(Self { header, events })
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.events.last().map(SessionEvent::at).unwrap_or_default()
+    }
+
+    pub fn connection_count(&self) -> usize {
+        let ids: std::collections::HashSet<u64> = self.events.iter().map(|event| event.connection).collect();
+        ids.len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaySpeed {
+    /// Everything at once
+    Instant,
+    /// With the gaps between events as recorded
+    Original,
+}
+
+/// Feeds recorded events back into an emulator, one parser per connection as
+/// the network listeners do. Recorded status replies are not sent anywhere;
+/// the emulator produces its own.
+#[derive(Default)]
+pub struct SessionReplayer {
+    connections: HashMap<u64, (JobSource, EscPosParser)>,
+    /// Start of the recording, when commands keep their recorded times
+    recorded_start: Option<SystemTime>,
+}
+
+impl SessionReplayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stamp commands and jobs with the times in the session instead of now.
+    pub fn with_recorded_times(session: &Session) -> Self {
+        Self {
+            recorded_start: Some(session.header.started_at.into()),
+            ..Self::default()
+        }
+    }
+
+    pub fn apply(&mut self, state: &mut EmulatorState, event: &SessionEvent) {
+        match event.kind {
+            SessionEventKind::Connect => {
+                state.connection_opened(&event.source);
+                self.connections.insert(event.connection, (event.source.clone(), state.new_parser()));
+            }
+            SessionEventKind::Inbound => {
+                let (source, parser) = self
+                    .connections
+                    .entry(event.connection)
+                    .or_insert_with(|| (event.source.clone(), state.new_parser()));
+                state.record_session_event(source, SessionEventKind::Inbound, &event.data);
+                let timestamp = match self.recorded_start {
+                    Some(start) => start + event.at(),
+                    None => SystemTime::now(),
+                };
+                match parser.parse_stream(&event.data) {
+                    Ok(commands) => {
+                        for parsed in &commands {
+                            state.process_command_at(source, parsed, timestamp);
+                        }
+                    }
+                    Err(e) => warn!("Replay parse error: {}", e),
+                }
+                for diagnostic in parser.take_diagnostics() {
+                    state.record_diagnostic(source, diagnostic);
+                }
+                state.take_responses();
+            }
+            SessionEventKind::Outbound => {}
+            SessionEventKind::Disconnect => self.close(state, event.connection, &event.source),
+        }
+    }
+
+    /// Close the connections the capture left open.
+    pub fn finish(mut self, state: &mut EmulatorState) {
+        let open: Vec<(u64, JobSource)> =
+            self.connections.iter().map(|(id, (source, _))| (*id, source.clone())).collect();
+        for (id, source) in open {
+            self.close(state, id, &source);
+        }
+    }
+
+    fn close(&mut self, state: &mut EmulatorState, connection: u64, source: &JobSource) {
+        if let Some((_, mut parser)) = self.connections.remove(&connection) {
+            parser.finish();
+            for diagnostic in parser.take_diagnostics() {
+                state.record_diagnostic(source, diagnostic);
+            }
+        }
+        state.connection_closed(source);
+    }
+}
+
+/// A replay running in the background.
+#[derive(Debug, Clone)]
+pub struct ReplayHandle {
+    running: Arc<AtomicBool>,
+    applied: Arc<AtomicUsize>,
+    total: usize,
+}
+
+impl ReplayHandle {
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Events applied so far, and the total.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.applied.load(Ordering::SeqCst), self.total)
+    }
+}
+
+/// Replay a capture into the emulator on the tokio runtime.
+pub fn start_replay(
+    session: Session,
+    speed: ReplaySpeed,
+    emulator_state: Arc<Mutex<EmulatorState>>,
+    tokio_handle: &tokio::runtime::Handle,
+) -> ReplayHandle {
+    let handle = ReplayHandle {
+        running: Arc::new(AtomicBool::new(true)),
+        applied: Arc::new(AtomicUsize::new(0)),
+        total: session.events.len(),
+    };
+    let task = handle.clone();
+    tokio_handle.spawn(async move {
+        let started = tokio::time::Instant::now();
+        let mut replayer = SessionReplayer::new();
+        for event in &session.events {
+            if !task.is_running() {
+                break;
+            }
+            if speed == ReplaySpeed::Original {
+                tokio::time::sleep_until(started + event.at()).await;
+            }
+            replayer.apply(&mut *emulator_state.lock().await, event);
+            task.applied.fetch_add(1, Ordering::SeqCst);
+        }
+        replayer.finish(&mut *emulator_state.lock().await);
+        task.running.store(false, Ordering::SeqCst);
+        info!("Replay finished: {} of {} event(s)", task.applied.load(Ordering::SeqCst), task.total);
+    });
+    handle
+}
+
+fn serialize_hex<S: Serializer>(data: &[u8], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&to_hex(data))
+}
+
+fn deserialize_hex<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<u8>, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    from_hex(&hex).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::printer::ReceiptLine;
+
+    /// Source, printed text and raw command bytes of every job.
+    fn jobs(state: &EmulatorState) -> Vec<(JobSource, Vec<String>, Vec<Vec<u8>>)> {
+        state
+            .jobs
+            .iter()
+            .map(|job| {
+                let texts = job
+                    .lines
+                    .iter()
+                    .filter_map(|line| match line {
+                        ReceiptLine::Text(text) if !text.text.is_empty() => Some(text.text.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                let raw = job.commands.iter().map(|entry| entry.raw_data.clone()).collect();
+                (job.source.clone(), texts, raw)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_recorded_session_replays_into_the_same_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let mut state = EmulatorState::new();
+        state.start_recording(&path).unwrap();
+        let (a, b) = (JobSource::Tcp("10.0.0.1:5000".into()), JobSource::Tcp("10.0.0.2:5000".into()));
+        state.print_bytes(&a, b"FIRST\n\x1dV\x00SECOND\n");
+        state.print_bytes(&b, b"\x1b!\x10BIG\n\x1b");
+        state.stop_recording();
+
+        let session = Session::load(&path).unwrap();
+        assert_eq!(session.header.version, SESSION_FORMAT_VERSION);
+        assert_eq!(session.connection_count(), 2);
+        use SessionEventKind::{Connect, Disconnect, Inbound};
+        let kinds: Vec<SessionEventKind> = session.events.iter().map(|event| event.kind).collect();
+        assert_eq!(kinds, [Connect, Inbound, Disconnect, Connect, Inbound, Disconnect]);
+        assert_eq!(session.events[4].data, b"\x1b!\x10BIG\n\x1b");
+
+        let mut replayed = EmulatorState::new();
+        replayed.replay_session(&session);
+        assert_eq!(jobs(&replayed), jobs(&state));
+        assert_eq!(jobs(&replayed).len(), 3);
+        assert_eq!(replayed.jobs[2].diagnostics.len(), 1);
+    }
+}
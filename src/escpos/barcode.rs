@@ -0,0 +1,333 @@
+//! Barcode encoders for GS k.
+//!
+//! Each encoder returns the symbol as a row of modules, `true` for a bar,
+//! including start/stop patterns but not the quiet zone. The rasterizer scales
+//! every module to the GS w module width.
+
+use crate::escpos::commands::BarcodeSystem;
+use qrcode::{Color, EcLevel, QrCode, Version};
+
+/// CODE128 symbol values 0..=105 as bar/space widths; 103..=105 are the start codes.
+const CODE128_PATTERNS: [&str; 106] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312",
+    "132212", "221213", "221312", "231212", "112232", "122132", "122231", "113222",
+    "123122", "123221", "223211", "221132", "221231", "213212", "223112", "312131",
+    "311222", "321122", "321221", "312212", "322112", "322211", "212123", "212321",
+    "232121", "111323", "131123", "131321", "112313", "132113", "132311", "211313",
+    "231113", "231311", "112133", "112331", "132131", "113123", "113321", "133121",
+    "313121", "211331", "231131", "213113", "213311", "213131", "311123", "311321",
+    "331121", "312113", "312311", "332111", "314111", "221411", "431111", "111224",
+    "111422", "121124", "121421", "141122", "141221", "112214", "112412", "122114",
+    "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111",
+    "111242", "121142", "121241", "114212", "124112", "124211", "411212", "421112",
+    "421211", "212141", "214121", "412121", "111143", "111341", "131141", "114113",
+    "114311", "411113", "411311", "113141", "114131", "311141", "411131", "211412",
+    "211214", "211232",
+];
+const CODE128_STOP: &str = "2331112";
+
+/// CODE39 characters with their bar/space pattern, `1` for a wide element.
+const CODE39_PATTERNS: [(char, &str); 44] = [
+    ('0', "000110100"), ('1', "100100001"), ('2', "001100001"), ('3', "101100000"),
+    ('4', "000110001"), ('5', "100110000"), ('6', "001110000"), ('7', "000100101"),
+    ('8', "100100100"), ('9', "001100100"), ('A', "100001001"), ('B', "001001001"),
+    ('C', "101001000"), ('D', "000011001"), ('E', "100011000"), ('F', "001011000"),
+    ('G', "000001101"), ('H', "100001100"), ('I', "001001100"), ('J', "000011100"),
+    ('K', "100000011"), ('L', "001000011"), ('M', "101000010"), ('N', "000010011"),
+    ('O', "100010010"), ('P', "001010010"), ('Q', "000000111"), ('R', "100000110"),
+    ('S', "001000110"), ('T', "000010110"), ('U', "110000001"), ('V', "011000001"),
+    ('W', "111000000"), ('X', "010010001"), ('Y', "110010000"), ('Z', "011010000"),
+    ('-', "010000101"), ('.', "110000100"), (' ', "011000100"), ('$', "010101000"),
+    ('/', "010100010"), ('+', "010001010"), ('%', "000101010"), ('*', "010010100"),
+];
+
+/// ITF digit patterns, `1` for a wide element.
+const ITF_PATTERNS: [&str; 10] = [
+    "00110", "10001", "01001", "11000", "00101", "10100", "01100", "00011", "10010", "01010",
+];
+
+/// EAN/UPC left-hand odd parity (L) codes; R codes are their complement and G
+/// codes the reversed R codes.
+const EAN_L: [&str; 10] = [
+    "0001101", "0011001", "0010011", "0111101", "0100011", "0110001", "0101111", "0111011", "0110111", "0001011",
+];
+
+/// L/G parity of the left half of an EAN-13, selected by the first digit.
+const EAN13_PARITY: [&str; 10] = [
+    "LLLLLL", "LLGLGG", "LLGGLG", "LLGGGL", "LGLLGG", "LGGLLG", "LGGGLL", "LGLGLG", "LGLGGL", "LGGLGL",
+];
+
+/// Width of a wide element in modules for CODE39 and ITF.
+const WIDE: usize = 3;
+
+/// Encode `data` as modules, or `None` if the system is not supported or the
+/// data is not valid for it.
+pub fn encode(system: BarcodeSystem, data: &[u8]) -> Option<Vec<bool>> {
+    match system {
+        BarcodeSystem::UpcA => {
+            let digits = digits(data, 11, 12)?;
+            let mut ean = vec![0];
+            ean.extend(digits);
+            encode_ean13(&ean)
+        }
+        BarcodeSystem::Ean13 => encode_ean13(&digits(data, 12, 13)?),
+        BarcodeSystem::Ean8 => encode_ean8(&digits(data, 7, 8)?),
+        BarcodeSystem::Code39 => encode_code39(data),
+        BarcodeSystem::Itf => encode_itf(data),
+        BarcodeSystem::Code128 => encode_code128(data),
+        _ => None,
+    }
+}
+
+/// Human readable interpretation printed above or below the bars.
+pub fn hri_text(system: BarcodeSystem, data: &[u8]) -> String {
+    match system {
+        BarcodeSystem::UpcA => digits(data, 11, 12).map(|d| digit_string(&with_check_digit(&d, 12))),
+        BarcodeSystem::Ean13 => digits(data, 12, 13).map(|d| digit_string(&with_check_digit(&d, 13))),
+        BarcodeSystem::Ean8 => digits(data, 7, 8).map(|d| digit_string(&with_check_digit(&d, 8))),
+        BarcodeSystem::Code39 => Some(format!("*{}*", String::from_utf8_lossy(data))),
+        BarcodeSystem::Code128 => Some(code128_text(data)),
+        _ => None,
+    }
+    .unwrap_or_else(|| String::from_utf8_lossy(data).into_owned())
+}
+
+/// Parse ASCII digits, accepting the data with or without its check digit.
+fn digits(data: &[u8], without_check: usize, with_check: usize) -> Option<Vec<u8>> {
+    if data.len() != without_check && data.len() != with_check {
+        return None;
+    }
+    data.iter()
+        .map(|&b| b.is_ascii_digit().then(|| b - b'0'))
+        .collect()
+}
+
+fn digit_string(digits: &[u8]) -> String {
+    digits.iter().map(|d| char::from(b'0' + d)).collect()
+}
+
+/// Append the modulo 10 check digit unless the data already has `len` digits.
+fn with_check_digit(digits: &[u8], len: usize) -> Vec<u8> {
+    let mut digits = digits[..digits.len().min(len - 1)].to_vec();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| d as u32 * if i % 2 == 0 { 3 } else { 1 })
+        .sum();
+    digits.push(((10 - sum % 10) % 10) as u8);
+    digits
+}
+
+fn push_bits(modules: &mut Vec<bool>, bits: &str) {
+    modules.extend(bits.bytes().map(|b| b == b'1'));
+}
+
+fn ean_code(digit: u8, parity: char) -> String {
+    let l = EAN_L[digit as usize];
+    match parity {
+        'L' => l.to_string(),
+        'R' => l.chars().map(|c| if c == '0' { '1' } else { '0' }).collect(),
+        _ => l.chars().rev().map(|c| if c == '0' { '1' } else { '0' }).collect(),
+    }
+}
+
+fn encode_ean13(digits: &[u8]) -> Option<Vec<bool>> {
+    let digits = with_check_digit(digits, 13);
+    let parity = EAN13_PARITY[digits[0] as usize];
+    let mut modules = Vec::with_capacity(95);
+    push_bits(&mut modules, "101");
+    for (&digit, parity) in digits[1..7].iter().zip(parity.chars()) {
+        push_bits(&mut modules, &ean_code(digit, parity));
+    }
+    push_bits(&mut modules, "01010");
+    for &digit in &digits[7..] {
+        push_bits(&mut modules, &ean_code(digit, 'R'));
+    }
+    push_bits(&mut modules, "101");
+    Some(modules)
+}
+
+fn encode_ean8(digits: &[u8]) -> Option<Vec<bool>> {
+    let digits = with_check_digit(digits, 8);
+    let mut modules = Vec::with_capacity(67);
+    push_bits(&mut modules, "101");
+    for &digit in &digits[..4] {
+        push_bits(&mut modules, &ean_code(digit, 'L'));
+    }
+    push_bits(&mut modules, "01010");
+    for &digit in &digits[4..] {
+        push_bits(&mut modules, &ean_code(digit, 'R'));
+    }
+    push_bits(&mut modules, "101");
+    Some(modules)
+}
+
+/// Append alternating bars and spaces, starting with a bar.
+fn push_elements(modules: &mut Vec<bool>, widths: impl IntoIterator<Item = usize>) {
+    for (i, width) in widths.into_iter().enumerate() {
+        modules.extend(std::iter::repeat_n(i % 2 == 0, width));
+    }
+}
+
+fn encode_code39(data: &[u8]) -> Option<Vec<bool>> {
+    let pattern = |ch: char| {
+        CODE39_PATTERNS
+            .iter()
+            .find(|(c, _)| *c == ch)
+            .map(|(_, p)| p.bytes().map(|b| if b == b'1' { WIDE } else { 1 }))
+    };
+    let body = data.strip_prefix(b"*").unwrap_or(data);
+    let body = body.strip_suffix(b"*").unwrap_or(body);
+
+    let mut modules = Vec::new();
+    for ch in std::iter::once('*').chain(body.iter().map(|&b| b as char)).chain(std::iter::once('*')) {
+        if !modules.is_empty() {
+            modules.push(false);
+        }
+        push_elements(&mut modules, pattern(ch.to_ascii_uppercase())?);
+    }
+    Some(modules)
+}
+
+fn encode_itf(data: &[u8]) -> Option<Vec<bool>> {
+    if data.is_empty() || !data.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let mut digits: Vec<u8> = data.iter().map(|b| b - b'0').collect();
+    if digits.len() % 2 == 1 {
+        digits.insert(0, 0);
+    }
+
+    let mut modules = Vec::new();
+    push_elements(&mut modules, [1, 1, 1, 1]);
+    for pair in digits.chunks(2) {
+        let bars = ITF_PATTERNS[pair[0] as usize].bytes();
+        let spaces = ITF_PATTERNS[pair[1] as usize].bytes();
+        let widths = bars
+            .zip(spaces)
+            .flat_map(|(b, s)| [b, s])
+            .map(|e| if e == b'1' { WIDE } else { 1 });
+        push_elements(&mut modules, widths);
+    }
+    push_elements(&mut modules, [WIDE, 1, 1]);
+    Some(modules)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CodeSet {
+    A,
+    B,
+    C,
+}
+
+/// Symbol values for Epson CODE128 data, which starts with `{A`, `{B` or `{C`
+/// and switches code sets with the same sequences. In code set C each byte is
+/// a value 0..=99.
+fn code128_values(data: &[u8]) -> Option<Vec<u8>> {
+    let mut values = Vec::new();
+    let mut set = None;
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == b'{' && i + 1 < data.len() {
+            let code = data[i + 1];
+            i += 2;
+            let (next, value) = match (code, set) {
+                (b'A', None) => (CodeSet::A, 103),
+                (b'B', None) => (CodeSet::B, 104),
+                (b'C', None) => (CodeSet::C, 105),
+                (b'A', Some(_)) => (CodeSet::A, 101),
+                (b'B', Some(_)) => (CodeSet::B, 100),
+                (b'C', Some(_)) => (CodeSet::C, 99),
+                (b'1', Some(current)) => (current, 102),
+                (b'S', Some(current @ (CodeSet::A | CodeSet::B))) => (current, 98),
+                (b'{', Some(CodeSet::B)) => (CodeSet::B, b'{' - 32),
+                _ => return None,
+            };
+            set = Some(next);
+            values.push(value);
+            continue;
+        }
+        let byte = data[i];
+        i += 1;
+        let value = match set? {
+            CodeSet::A if byte < 0x20 => byte + 64,
+            CodeSet::A if byte < 0x60 => byte - 32,
+            CodeSet::B if (0x20..0x80).contains(&byte) => byte - 32,
+            CodeSet::C if byte < 100 => byte,
+            _ => return None,
+        };
+        values.push(value);
+    }
+    Some(values)
+}
+
+fn encode_code128(data: &[u8]) -> Option<Vec<bool>> {
+    let mut values = code128_values(data)?;
+    if values.len() < 2 {
+        return None;
+    }
+    let checksum = values
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| v as u32 * (i as u32).max(1))
+        .sum::<u32>()
+        % 103;
+    values.push(checksum as u8);
+
+    let widths = |pattern: &'static str| pattern.bytes().map(|b| (b - b'0') as usize);
+    let mut modules = Vec::new();
+    for value in values {
+        push_elements(&mut modules, widths(CODE128_PATTERNS[value as usize]));
+    }
+    push_elements(&mut modules, widths(CODE128_STOP));
+    Some(modules)
+}
+
+/// CODE128 data without the code set sequences; code set C values as two digits.
+fn code128_text(data: &[u8]) -> String {
+    let mut text = String::new();
+    let mut set_c = false;
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == b'{' && i + 1 < data.len() {
+            match data[i + 1] {
+                b'C' => set_c = true,
+                b'A' | b'B' => set_c = false,
+                b'{' => text.push('{'),
+                _ => {}
+            }
+            i += 2;
+            continue;
+        }
+        if set_c {
+            text.push_str(&format!("{:02}", data[i]));
+        } else if data[i] >= 0x20 {
+            text.push(data[i] as char);
+        }
+        i += 1;
+    }
+    text
+}
+
+/// Module grid of the QR symbol for the GS ( k model (49..51) and error
+/// correction level (48..51 = L, M, Q, H), or `None` when the data does not fit.
+/// Model 1 is obsolete and printed as model 2, which every scanner reads; micro
+/// QR has no level H and falls back to Q.
+pub fn qr_symbol(data: &[u8], model: u8, error_correction: u8) -> Option<(u32, Vec<bool>)> {
+    let level = match error_correction {
+        49 => EcLevel::M,
+        50 => EcLevel::Q,
+        51 => EcLevel::H,
+        _ => EcLevel::L,
+    };
+    let code = if model == 51 {
+        let level = if level == EcLevel::H { EcLevel::Q } else { level };
+        (1..=4).find_map(|version| QrCode::with_version(data, Version::Micro(version), level).ok())?
+    } else {
+        QrCode::with_error_correction_level(data, level).ok()?
+    };
+    let size = code.width() as u32;
+    let modules = code.into_colors().into_iter().map(|color| color == Color::Dark).collect();
+    Some((size, modules))
+}
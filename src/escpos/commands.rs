@@ -7,30 +7,83 @@ pub enum EscPosCommand {
     NewLine,
     LineFeed,
     CarriageReturn,
+    /// HT — move to the next horizontal tab stop
+    HorizontalTab,
+    /// ESC D n1 ... nk NUL — tab stops in characters from the left margin; empty clears them
+    SetTabStops(Vec<u8>),
 
     // Font commands
     SetFont(Font),
+    /// ESC ! n — print mode: font, emphasis, double height/width and underline bits
     SetFontSize(u32),
+    /// GS ! n — character size multipliers, 1 to 8 in each direction
+    SetCharacterSize { width: u8, height: u8 },
 
     // Formatting commands
     SetJustification(Justification),
     SetEmphasis(bool),
     SetUnderline(bool),
     SetItalic(bool),
+    /// GS B n — white on black printing
+    SetReverse(bool),
     SetLineHeight(u32),
 
     // Print commands
-    CutPaper,
-    PrintImage(Vec<u8>),
+    /// ESC i / ESC m / GS V — cut the paper
+    CutPaper(CutKind),
+    /// ESC * m nL nH — bit image in column format, `width_dots` columns of 8 or 24 dots
+    PrintImage { mode: u8, width_dots: u16, data: Vec<u8> },
     /// Raster bitmap with width (bytes per row) and height (rows)
     PrintRasterImage { width_bytes: u16, height: u16, data: Vec<u8> },
 
+    // Barcodes
+    /// GS h n — barcode height in dots
+    SetBarcodeHeight(u8),
+    /// GS w n — barcode module width in dots
+    SetBarcodeWidth(u8),
+    /// GS H n — HRI position: 0 none, 1 above, 2 below, 3 both
+    SetHriPosition(u8),
+    /// GS k — print a barcode
+    PrintBarcode { system: BarcodeSystem, data: Vec<u8> },
+    /// GS ( k cn=49 fn=65 — QR code model (49 = model 1, 50 = model 2, 51 = micro QR)
+    SetQrModel(u8),
+    /// GS ( k cn=49 fn=67 — QR code module size in dots
+    SetQrModuleSize(u8),
+    /// GS ( k cn=49 fn=69 — QR code error correction level (48..51 = L, M, Q, H)
+    SetQrErrorCorrection(u8),
+    /// GS ( k cn=49 fn=80 — store QR code data in the symbol storage area
+    StoreQrData(Vec<u8>),
+    /// GS ( k cn=49 fn=81 — print the stored QR code
+    PrintQrCode,
+
     // Codepage selection (ESC t n)
     SetCodepage(u8),
 
     // Control commands
     InitializePrinter,
 
+    // Status requests, answered on the same connection
+    /// DLE EOT n — real-time status transmission
+    RealtimeStatus(u8),
+    /// GS I n — transmit printer ID
+    TransmitPrinterId(u8),
+
+    // Label and black mark paper
+    /// GS ( F — adjust the print starting or cutting position, in dots
+    SetMarkAdjustment { position: MarkPosition, dots: i32 },
+    /// GS FF / FS ( L — feed to the next label or black mark position
+    FeedToMark(MarkPosition),
+
+    // Cash drawer
+    /// ESC p m t1 t2 / DLE DC4 1 m t — pulse a drawer kick-out connector pin
+    DrawerKick { pin: u8, on_ms: u32, off_ms: u32 },
+
+    // Macros (GS : / GS ^ r t m)
+    ToggleMacroDefinition,
+    /// Run the defined macro `repeat` times, waiting `wait` × 100 ms (mode 0)
+    /// or for the FEED button (mode 1) between runs
+    ExecuteMacro { repeat: u8, wait: u8, mode: u8 },
+
     // Unknown commands
     Unknown(Vec<u8>),
 }
@@ -48,3 +101,66 @@ pub enum Justification {
     Center,
     Right,
 }
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CutKind {
+    #[default]
+    Full,
+    /// One or three points left uncut
+    Partial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarkPosition {
+    PrintStart,
+    Cut,
+    Peel,
+}
+
+/// Barcode systems selectable with GS k
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BarcodeSystem {
+    UpcA,
+    UpcE,
+    Ean13,
+    Ean8,
+    Code39,
+    Itf,
+    Codabar,
+    Code93,
+    Code128,
+    Other(u8),
+}
+
+impl BarcodeSystem {
+    /// Map the GS k `m` parameter (function A 0..6 or function B 65..) to a system.
+    pub fn from_selector(m: u8) -> Self {
+        match m {
+            0 | 65 => BarcodeSystem::UpcA,
+            1 | 66 => BarcodeSystem::UpcE,
+            2 | 67 => BarcodeSystem::Ean13,
+            3 | 68 => BarcodeSystem::Ean8,
+            4 | 69 => BarcodeSystem::Code39,
+            5 | 70 => BarcodeSystem::Itf,
+            6 | 71 => BarcodeSystem::Codabar,
+            72 => BarcodeSystem::Code93,
+            73 => BarcodeSystem::Code128,
+            other => BarcodeSystem::Other(other),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            BarcodeSystem::UpcA => "UPC-A".to_string(),
+            BarcodeSystem::UpcE => "UPC-E".to_string(),
+            BarcodeSystem::Ean13 => "EAN13".to_string(),
+            BarcodeSystem::Ean8 => "EAN8".to_string(),
+            BarcodeSystem::Code39 => "CODE39".to_string(),
+            BarcodeSystem::Itf => "ITF".to_string(),
+            BarcodeSystem::Codabar => "CODABAR".to_string(),
+            BarcodeSystem::Code93 => "CODE93".to_string(),
+            BarcodeSystem::Code128 => "CODE128".to_string(),
+            BarcodeSystem::Other(m) => format!("type {}", m),
+        }
+    }
+}
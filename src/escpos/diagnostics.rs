@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiagnosticKind {
+    /// A parameter outside the range allowed by the command; the printer falls back to a default
+    OutOfRange,
+    /// The stream ended in the middle of a command
+    Truncated,
+    /// A command the emulator does not know
+    UnknownCommand,
+    /// A command the selected printer profile ignores
+    Unsupported,
+    /// A command or stream rejected by the configured parser limits
+    LimitExceeded,
+}
+
+/// Something noteworthy about the input stream, anchored to a byte offset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// Stream offset of the first byte of the offending command
+    pub offset: u64,
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(offset: u64, severity: Severity, kind: DiagnosticKind, message: impl Into<String>) -> Self {
+        Self {
+            offset,
+            severity,
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "byte {} [{:?}/{:?}]: {}", self.offset, self.severity, self.kind, self.message)
+    }
+}
+
+/// Human readable form of a command introducer, e.g. `ESC a` or `GS 0x28`.
+pub fn describe_command(raw: &[u8]) -> String {
+    let prefix = match raw.first() {
+        Some(0x1B) => "ESC",
+        Some(0x1D) => "GS",
+        Some(0x1C) => "FS",
+        Some(0x10) => "DLE",
+        Some(b) => return format!("0x{:02X}", b),
+        None => return String::new(),
+    };
+    match raw.get(1) {
+        Some(&b) if b.is_ascii_graphic() => format!("{} {}", prefix, b as char),
+        Some(&b) => format!("{} 0x{:02X}", prefix, b),
+        None => prefix.to_string(),
+    }
+}
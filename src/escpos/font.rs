@@ -0,0 +1,280 @@
+//! Bitmap fonts bundled with the rasterizer.
+//!
+//! Glyphs come from the X11 misc-fixed fonts shipped with `embedded-graphics`,
+//! drawn dot for dot without scaling. Each character cell gets the largest
+//! font that fits it: 10x20 in Font A (12x24), 9x15 in Font B (9x17), and so
+//! on for the other cells a profile defines. Characters are looked up across
+//! the Latin, Greek, Cyrillic and JIS X 0201 subsets; box drawing and block
+//! elements, which the code pages use for frames and shading, are drawn
+//! geometrically so they join across cells.
+
+use crate::escpos::profile::CellSize;
+use embedded_graphics::geometry::{OriginDimensions, Point};
+use embedded_graphics::image::GetPixel;
+use embedded_graphics::mono_font::mapping::{self, StrGlyphMapping};
+use embedded_graphics::mono_font::{
+    iso_8859_1, iso_8859_13, iso_8859_15, iso_8859_16, iso_8859_2, iso_8859_3, iso_8859_4, iso_8859_5,
+    iso_8859_7, iso_8859_9, jis_x0201, MonoFont,
+};
+use embedded_graphics::pixelcolor::BinaryColor;
+
+/// The regular weight of one subset in every size, largest first.
+macro_rules! subset {
+    ($module:ident, $mapping:expr) => {
+        (
+            &$mapping,
+            &[
+                $module::FONT_10X20,
+                $module::FONT_9X18,
+                $module::FONT_9X15,
+                $module::FONT_8X13,
+                $module::FONT_7X14,
+                $module::FONT_7X13,
+                $module::FONT_6X13,
+                $module::FONT_6X12,
+                $module::FONT_6X10,
+                $module::FONT_6X9,
+                $module::FONT_5X8,
+                $module::FONT_5X7,
+                $module::FONT_4X6,
+            ],
+        )
+    };
+}
+
+type Subset = (&'static StrGlyphMapping<'static>, &'static [MonoFont<'static>]);
+
+/// Subsets in lookup order; Latin-1 first since it covers most receipts.
+const SUBSETS: [Subset; 11] = [
+    subset!(iso_8859_1, mapping::ISO_8859_1),
+    subset!(iso_8859_15, mapping::ISO_8859_15),
+    subset!(iso_8859_2, mapping::ISO_8859_2),
+    subset!(iso_8859_3, mapping::ISO_8859_3),
+    subset!(iso_8859_4, mapping::ISO_8859_4),
+    subset!(iso_8859_9, mapping::ISO_8859_9),
+    subset!(iso_8859_13, mapping::ISO_8859_13),
+    subset!(iso_8859_16, mapping::ISO_8859_16),
+    subset!(iso_8859_5, mapping::ISO_8859_5),
+    subset!(iso_8859_7, mapping::ISO_8859_7),
+    (
+        &mapping::JIS_X0201,
+        &[
+            jis_x0201::FONT_10X20,
+            jis_x0201::FONT_9X18,
+            jis_x0201::FONT_9X15,
+            jis_x0201::FONT_8X13,
+            jis_x0201::FONT_7X14,
+            jis_x0201::FONT_6X13,
+        ],
+    ),
+];
+
+/// Line weight of one arm of a box drawing character.
+#[derive(Clone, Copy, PartialEq)]
+enum Arm {
+    None,
+    Light,
+    Double,
+}
+
+/// A character drawn dot for dot into a character cell, row-major, `true`
+/// for a printed dot. Characters no bundled font covers print as a hollow box.
+pub fn glyph_cell(ch: char, cell: CellSize) -> Vec<bool> {
+    let mut dots = vec![false; (cell.width * cell.height) as usize];
+    if ch == ' ' || cell.width == 0 || cell.height == 0 {
+        return dots;
+    }
+    if let Some(arms) = box_arms(ch) {
+        draw_box(&mut dots, cell, arms);
+    } else if !draw_block(&mut dots, cell, ch) && !draw_font_glyph(&mut dots, cell, ch) {
+        draw_missing(&mut dots, cell);
+    }
+    dots
+}
+
+/// Copy the glyph from the largest font of the first subset containing `ch`.
+fn draw_font_glyph(dots: &mut [bool], cell: CellSize, ch: char) -> bool {
+    let Some(font) = SUBSETS.iter().filter(|(mapping, _)| mapping.contains(ch)).find_map(|(_, fonts)| {
+        fonts
+            .iter()
+            .find(|font| font.character_size.width <= cell.width && font.character_size.height <= cell.height)
+    }) else {
+        return false;
+    };
+
+    let size = font.character_size;
+    let per_row = font.image.size().width / size.width;
+    let index = font.glyph_mapping.index(ch) as u32;
+    let (origin_x, origin_y) = ((index % per_row) * size.width, (index / per_row) * size.height);
+    let left = (cell.width - size.width) / 2;
+    let top = (cell.height - size.height) / 2;
+    for y in 0..size.height {
+        for x in 0..size.width {
+            let point = Point::new((origin_x + x) as i32, (origin_y + y) as i32);
+            if font.image.pixel(point) == Some(BinaryColor::On) {
+                dots[((top + y) * cell.width + left + x) as usize] = true;
+            }
+        }
+    }
+    true
+}
+
+/// Arms (up, down, left, right) of the box drawing characters in the code pages.
+fn box_arms(ch: char) -> Option<[Arm; 4]> {
+    use Arm::{Double as D, Light as L, None as N};
+    Some(match ch {
+        '─' => [N, N, L, L],
+        '│' => [L, L, N, N],
+        '┌' => [N, L, N, L],
+        '┐' => [N, L, L, N],
+        '└' => [L, N, N, L],
+        '┘' => [L, N, L, N],
+        '├' => [L, L, N, L],
+        '┤' => [L, L, L, N],
+        '┬' => [N, L, L, L],
+        '┴' => [L, N, L, L],
+        '┼' => [L, L, L, L],
+        '═' => [N, N, D, D],
+        '║' => [D, D, N, N],
+        '╒' => [N, L, N, D],
+        '╓' => [N, D, N, L],
+        '╔' => [N, D, N, D],
+        '╕' => [N, L, D, N],
+        '╖' => [N, D, L, N],
+        '╗' => [N, D, D, N],
+        '╘' => [L, N, N, D],
+        '╙' => [D, N, N, L],
+        '╚' => [D, N, N, D],
+        '╛' => [L, N, D, N],
+        '╜' => [D, N, L, N],
+        '╝' => [D, N, D, N],
+        '╞' => [L, L, N, D],
+        '╟' => [D, D, N, L],
+        '╠' => [D, D, N, D],
+        '╡' => [L, L, D, N],
+        '╢' => [D, D, L, N],
+        '╣' => [D, D, D, N],
+        '╤' => [N, L, D, D],
+        '╥' => [N, D, L, L],
+        '╦' => [N, D, D, D],
+        '╧' => [L, N, D, D],
+        '╨' => [D, N, L, L],
+        '╩' => [D, N, D, D],
+        '╪' => [L, L, D, D],
+        '╫' => [D, D, L, L],
+        '╬' => [D, D, D, D],
+        _ => return None,
+    })
+}
+
+/// Draw each arm from the cell edge to the centre; double arms are two lines
+/// one dot either side of the centre line.
+fn draw_box(dots: &mut [bool], cell: CellSize, [up, down, left, right]: [Arm; 4]) {
+    let (cx, cy) = (cell.width / 2, cell.height / 2);
+    let offsets = |arm: Arm| match arm {
+        Arm::None => vec![],
+        Arm::Light => vec![0i32],
+        Arm::Double => vec![-1, 1],
+    };
+    let mut set = |x: i32, y: i32| {
+        if (0..cell.width as i32).contains(&x) && (0..cell.height as i32).contains(&y) {
+            dots[(y as u32 * cell.width + x as u32) as usize] = true;
+        }
+    };
+    let (cx, cy) = (cx as i32, cy as i32);
+    for offset in offsets(up) {
+        (0..=cy + 1).for_each(|y| set(cx + offset, y));
+    }
+    for offset in offsets(down) {
+        (cy - 1..cell.height as i32).for_each(|y| set(cx + offset, y));
+    }
+    for offset in offsets(left) {
+        (0..=cx + 1).for_each(|x| set(x, cy + offset));
+    }
+    for offset in offsets(right) {
+        (cx - 1..cell.width as i32).for_each(|x| set(x, cy + offset));
+    }
+}
+
+/// Shades, half blocks and the full block, filling the whole cell.
+fn draw_block(dots: &mut [bool], cell: CellSize, ch: char) -> bool {
+    let (w, h) = (cell.width, cell.height);
+    let inside: fn(u32, u32, u32, u32) -> bool = match ch {
+        '█' => |_, _, _, _| true,
+        '▀' => |_, y, _, h| y < h / 2,
+        '▄' => |_, y, _, h| y >= h / 2,
+        '▌' => |x, _, w, _| x < w / 2,
+        '▐' => |x, _, w, _| x >= w / 2,
+        '░' => |x, y, _, _| x % 2 == 0 && y % 2 == 0,
+        '▒' => |x, y, _, _| (x + y) % 2 == 0,
+        '▓' => |x, y, _, _| !(x % 2 == 1 && y % 2 == 1),
+        '■' => |x, y, w, h| {
+            (w / 4..w - w / 4).contains(&x) && ((h / 2).saturating_sub(w / 4)..h / 2 + w / 4).contains(&y)
+        },
+        _ => return false,
+    };
+    for y in 0..h {
+        for x in 0..w {
+            dots[(y * w + x) as usize] = inside(x, y, w, h);
+        }
+    }
+    true
+}
+
+/// Hollow box printed for characters outside the bundled fonts.
+fn draw_missing(dots: &mut [bool], cell: CellSize) {
+    let (w, h) = (cell.width, cell.height);
+    let (left, right) = (1, w.saturating_sub(2));
+    let (top, bottom) = (h / 6, h - h / 6 - 1);
+    for y in top..=bottom {
+        for x in left..=right {
+            if x == left || x == right || y == top || y == bottom {
+                dots[(y * w + x) as usize] = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT_A: CellSize = CellSize { width: 12, height: 24 };
+    const FONT_B: CellSize = CellSize { width: 9, height: 17 };
+
+    /// Columns and rows holding at least one printed dot.
+    fn ink_extent(dots: &[bool], cell: CellSize) -> (u32, u32) {
+        let lit = |x: u32, y: u32| dots[(y * cell.width + x) as usize];
+        let columns = (0..cell.width).filter(|&x| (0..cell.height).any(|y| lit(x, y))).count();
+        let rows = (0..cell.height).filter(|&y| (0..cell.width).any(|x| lit(x, y))).count();
+        (columns as u32, rows as u32)
+    }
+
+    #[test]
+    fn glyphs_are_drawn_inside_their_cell() {
+        for cell in [FONT_A, FONT_B] {
+            let dots = glyph_cell('W', cell);
+            assert_eq!(dots.len(), (cell.width * cell.height) as usize);
+            let (columns, rows) = ink_extent(&dots, cell);
+            assert!(columns > cell.width / 2 && columns <= cell.width, "{:?}: {} columns", cell, columns);
+            assert!(rows > cell.height / 3 && rows < cell.height, "{:?}: {} rows", cell, rows);
+            assert!(glyph_cell(' ', cell).iter().all(|&dot| !dot));
+        }
+    }
+
+    #[test]
+    fn blocks_fill_any_cell_shape() {
+        assert!(glyph_cell('█', FONT_A).iter().all(|&dot| dot));
+        // Wider than tall, where the square used to underflow
+        let flat = CellSize { width: 12, height: 4 };
+        assert!(glyph_cell('■', flat).iter().any(|&dot| dot));
+        assert_eq!(glyph_cell('■', CellSize { width: 1, height: 1 }), [false]);
+    }
+
+    #[test]
+    fn box_lines_join_across_cells() {
+        let dots = glyph_cell('─', FONT_A);
+        let row = FONT_A.height / 2;
+        assert!((0..FONT_A.width).all(|x| dots[(row * FONT_A.width + x) as usize]));
+    }
+}
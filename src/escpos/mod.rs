@@ -1,7 +1,17 @@
+pub mod barcode;
+pub mod codepage;
 pub mod commands;
+pub mod diagnostics;
+pub mod font;
+pub mod paper;
 pub mod parser;
 pub mod printer;
+pub mod profile;
+pub mod raster;
 
 pub use commands::*;
+pub use diagnostics::*;
+pub use paper::*;
 pub use parser::*;
-pub use printer::*;
\ No newline at end of file
+pub use printer::*;
+pub use profile::*;
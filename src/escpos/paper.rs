@@ -0,0 +1,186 @@
+use crate::escpos::commands::MarkPosition;
+use crate::escpos::profile::CellSize;
+use serde::{Deserialize, Serialize};
+
+/// Width of the paper roll together with the resolution of the print head.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PaperWidth {
+    pub width_mm: f32,
+    pub dpi: u32,
+    /// Printable dots per line when the printer profile lists this width;
+    /// otherwise a 4 mm margin is left on each side
+    pub printable_dots: Option<u32>,
+}
+
+impl PaperWidth {
+    /// Roll widths offered in the GUI; any width in `MIN_MM..=MAX_MM` is accepted.
+    pub const COMMON_WIDTHS_MM: [f32; 6] = [50.0, 58.0, 60.0, 76.0, 80.0, 82.5];
+    pub const MIN_MM: f32 = 20.0;
+    pub const MAX_MM: f32 = 120.0;
+
+    pub fn new(width_mm: f32, dpi: u32) -> Self {
+        Self {
+            width_mm,
+            dpi,
+            printable_dots: None,
+        }
+    }
+
+    pub fn with_printable_dots(mut self, dots: Option<u32>) -> Self {
+        self.printable_dots = dots;
+        self
+    }
+
+    /// Full paper width, in dots.
+    pub fn width_dots(&self) -> u32 {
+        mm_to_dots(self.width_mm, self.dpi)
+    }
+
+    pub fn printable_dots(&self) -> u32 {
+        self.printable_dots
+            .unwrap_or_else(|| mm_to_dots(self.width_mm - 8.0, self.dpi))
+            .min(self.width_dots())
+    }
+
+    /// Unprintable margin on each side, in dots.
+    pub fn margin_dots(&self) -> u32 {
+        (self.width_dots() - self.printable_dots()) / 2
+    }
+
+    pub fn chars_per_line(&self, cell: CellSize) -> u32 {
+        self.printable_dots() / cell.width.max(1)
+    }
+}
+
+impl std::fmt::Display for PaperWidth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}mm", self.width_mm)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaperKind {
+    Continuous,
+    BlackMark,
+    Label,
+}
+
+/// Where a form boundary lies on the paper, relative to the top of the receipt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaperEdge {
+    /// Top of a label, or the leading edge of a black mark
+    FormTop { position: u32, index: u32 },
+    /// End of a label, where the gap to the next label starts
+    LabelEnd { position: u32, index: u32 },
+}
+
+impl PaperEdge {
+    pub fn position(&self) -> u32 {
+        match self {
+            PaperEdge::FormTop { position, .. } | PaperEdge::LabelEnd { position, .. } => *position,
+        }
+    }
+}
+
+/// Paper stock loaded in the virtual printer. Distances are in millimetres,
+/// adjustments set with GS ( F are in dots.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaperModel {
+    pub kind: PaperKind,
+    /// Black mark paper: distance from one mark to the next
+    pub mark_pitch_mm: f32,
+    /// Black mark paper: height of the printed mark
+    pub mark_length_mm: f32,
+    /// Label paper: length of one label, excluding the gap
+    pub label_length_mm: f32,
+    /// Label paper: backing gap between two labels
+    pub label_gap_mm: f32,
+    /// Print starting position relative to the form top (GS ( F a=1)
+    pub print_start_offset: i32,
+    /// Cutting position relative to the form top (GS ( F a=2)
+    pub cut_offset: i32,
+}
+
+impl Default for PaperModel {
+    fn default() -> Self {
+        Self {
+            kind: PaperKind::Continuous,
+            mark_pitch_mm: 100.0,
+            mark_length_mm: 4.0,
+            label_length_mm: 40.0,
+            label_gap_mm: 3.0,
+            print_start_offset: 0,
+            cut_offset: 0,
+        }
+    }
+}
+
+pub fn mm_to_dots(mm: f32, dpi: u32) -> u32 {
+    (mm.max(0.0) * dpi as f32 / 25.4).round() as u32
+}
+
+impl PaperModel {
+    /// Distance between consecutive form tops, or `None` for continuous paper.
+    pub fn pitch_dots(&self, dpi: u32) -> Option<u32> {
+        let pitch_mm = match self.kind {
+            PaperKind::Continuous => return None,
+            PaperKind::BlackMark => self.mark_pitch_mm,
+            PaperKind::Label => self.label_length_mm + self.label_gap_mm,
+        };
+        Some(mm_to_dots(pitch_mm, dpi).max(1))
+    }
+
+    /// Length of the black mark or label, in dots.
+    pub fn form_length_dots(&self, dpi: u32) -> u32 {
+        match self.kind {
+            PaperKind::Continuous => 0,
+            PaperKind::BlackMark => mm_to_dots(self.mark_length_mm, dpi),
+            PaperKind::Label => mm_to_dots(self.label_length_mm, dpi),
+        }
+    }
+
+    /// All edges with a position in `start..end`, in feed order.
+    pub fn edges(&self, dpi: u32, start: u32, end: u32) -> Vec<PaperEdge> {
+        let Some(pitch) = self.pitch_dots(dpi) else {
+            return Vec::new();
+        };
+        let label_len = self.form_length_dots(dpi);
+        let mut edges = Vec::new();
+        let mut index = start / pitch;
+        loop {
+            let top = index * pitch;
+            if top >= end {
+                break;
+            }
+            if top >= start {
+                edges.push(PaperEdge::FormTop { position: top, index });
+            }
+            if self.kind == PaperKind::Label {
+                let label_end = top + label_len;
+                if label_end >= start && label_end < end {
+                    edges.push(PaperEdge::LabelEnd { position: label_end, index });
+                }
+            }
+            index += 1;
+        }
+        edges
+    }
+
+    /// The first `target` position at or after `from`, or `None` on continuous paper.
+    pub fn next_position(&self, dpi: u32, from: u32, target: MarkPosition) -> Option<u32> {
+        let pitch = self.pitch_dots(dpi)? as i64;
+        let offset = match target {
+            MarkPosition::PrintStart => self.print_start_offset as i64,
+            MarkPosition::Cut => self.cut_offset as i64,
+            // The label is fully past the head once its end is reached
+            MarkPosition::Peel => self.form_length_dots(dpi) as i64 + self.cut_offset as i64,
+        };
+        let from = from as i64;
+        let index = (from - offset).max(0) as u64 / pitch as u64;
+        let mut position = index as i64 * pitch + offset;
+        while position < from {
+            position += pitch;
+        }
+        Some(position.max(0) as u32)
+    }
+}
@@ -1,30 +1,174 @@
-use crate::escpos::commands::{EscPosCommand, Font, Justification};
+use crate::escpos::codepage;
+use crate::escpos::commands::{BarcodeSystem, CutKind, EscPosCommand, Font, Justification, MarkPosition};
+use crate::escpos::diagnostics::{describe_command, Diagnostic, DiagnosticKind, Severity};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Most tab stops ESC D can set.
+const MAX_TAB_STOPS: usize = 32;
+
+/// A command refused because its declared payload breaks a limit. `length`
+/// spans the whole command, so parsing can resume right after it.
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+struct Rejected {
+    message: String,
+    length: usize,
+}
+
+/// A decoded command together with the exact bytes it was parsed from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParsedCommand {
+    pub command: EscPosCommand,
+    /// Source bytes of the command, reassembled if it spanned several reads
+    pub raw: Vec<u8>,
+    /// Offset of the first byte of `raw` from the start of the stream
+    pub offset: u64,
+}
+
+/// Upper bounds that keep a misbehaving client from exhausting memory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParserLimits {
+    /// Largest image payload (ESC *, GS v 0) accepted, in bytes
+    pub max_raster_bytes: usize,
+    /// Largest incomplete command held while waiting for more data
+    pub max_pending_bytes: usize,
+    /// Largest number of bytes accepted from a single stream
+    pub max_job_bytes: usize,
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        Self {
+            max_raster_bytes: 2 * 1024 * 1024,
+            max_pending_bytes: 4 * 1024 * 1024,
+            max_job_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
 
 pub struct EscPosParser {
     buffer: Vec<u8>,
+    /// Stream offset of `buffer[0]`
+    offset: u64,
+    limits: ParserLimits,
+    diagnostics: Vec<Diagnostic>,
+    /// Command prefixes the printer profile ignores
+    unsupported: Vec<Vec<u8>>,
+    /// Code pages the printer profile provides; empty means all
+    code_pages: Vec<u8>,
+    /// ESC t page that text bytes are decoded with
+    code_page: u8,
+    /// Discarding input until the next command introducer after a rejected command
+    resyncing: bool,
+    /// Payload bytes of a rejected command still to be dropped
+    skipping: usize,
+    /// Stream offset where the current job started
+    job_start: u64,
+    job_limit_exceeded: bool,
 }
 
 impl EscPosParser {
     pub fn new() -> Self {
+        Self::with_limits(ParserLimits::default())
+    }
+
+    pub fn with_limits(limits: ParserLimits) -> Self {
         Self {
             buffer: Vec::new(),
+            offset: 0,
+            limits,
+            diagnostics: Vec::new(),
+            unsupported: Vec::new(),
+            code_pages: Vec::new(),
+            code_page: 0,
+            resyncing: false,
+            skipping: 0,
+            job_start: 0,
+            job_limit_exceeded: false,
         }
     }
 
-    pub fn parse_stream(&mut self, data: &[u8]) -> Result<Vec<EscPosCommand>> {
+    /// Total number of bytes consumed from the stream so far.
+    pub fn stream_offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// True once the current job went past `max_job_bytes`; further input is
+    /// dropped until [`start_job_at`](Self::start_job_at).
+    pub fn job_limit_exceeded(&self) -> bool {
+        self.job_limit_exceeded
+    }
+
+    /// Count `max_job_bytes` afresh from stream offset `offset`, for streams
+    /// that carry several jobs.
+    pub fn start_job_at(&mut self, offset: u64) {
+        self.job_start = offset.min(self.offset + self.buffer.len() as u64);
+        self.job_limit_exceeded = false;
+    }
+
+    /// Commands starting with one of these prefixes are reported and dropped.
+    pub fn set_unsupported_commands(&mut self, prefixes: Vec<Vec<u8>>) {
+        self.unsupported = prefixes;
+    }
+
+    /// Report ESC t selections outside of these code pages.
+    pub fn set_code_pages(&mut self, code_pages: Vec<u8>) {
+        self.code_pages = code_pages;
+    }
+
+    /// Decode text with this ESC t page until the stream selects another one.
+    pub fn set_code_page(&mut self, page: u8) {
+        self.code_page = page;
+    }
+
+    /// Drain the diagnostics collected since the last call.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Signal the end of the stream, reporting any command left incomplete.
+    pub fn finish(&mut self) {
+        if !self.buffer.is_empty() {
+            let message = format!(
+                "stream ended inside {} ({} bytes pending)",
+                describe_command(&self.buffer),
+                self.buffer.len()
+            );
+            self.report(0, Severity::Error, DiagnosticKind::Truncated, message);
+            self.offset += self.buffer.len() as u64;
+            self.buffer.clear();
+        }
+        self.resyncing = false;
+        self.skipping = 0;
+    }
+
+    pub fn parse_stream(&mut self, data: &[u8]) -> Result<Vec<ParsedCommand>> {
+        let data = self.enforce_job_limit(data);
         self.buffer.extend_from_slice(data);
         let mut commands = Vec::new();
         let mut i = 0;
 
+        if self.skipping > 0 {
+            i = self.skipping.min(self.buffer.len());
+            self.skipping -= i;
+        }
+        if self.resyncing {
+            i = self.resync_from(i);
+        }
+
         while i < self.buffer.len() {
             match self.buffer[i] {
                 b'\n' => {
-                    commands.push(EscPosCommand::NewLine);
+                    self.push_command(&mut commands, EscPosCommand::NewLine, i, i + 1);
                     i += 1;
                 }
                 b'\r' => {
-                    commands.push(EscPosCommand::CarriageReturn);
+                    self.push_command(&mut commands, EscPosCommand::CarriageReturn, i, i + 1);
+                    i += 1;
+                }
+                b'\t' => {
+                    self.push_command(&mut commands, EscPosCommand::HorizontalTab, i, i + 1);
                     i += 1;
                 }
                 0x1B => {
@@ -34,11 +178,12 @@ impl EscPosParser {
                     }
                     match self.parse_esc_command(&self.buffer[i..]) {
                         Ok(Some((cmd, consumed))) => {
-                            commands.push(cmd);
+                            self.push_checked(&mut commands, cmd, i, i + consumed);
                             i += consumed;
                         }
+                        Ok(None) if self.pending_exceeded(i) => i = self.reject_pending(i),
                         Ok(None) => break, // Incomplete, wait for more
-                        Err(_) => { i += 2; } // Skip bad ESC sequence
+                        Err(e) => i = self.reject(i, e),
                     }
                 }
                 0x1D => {
@@ -48,11 +193,42 @@ impl EscPosParser {
                     }
                     match self.parse_gs_command(&self.buffer[i..]) {
                         Ok(Some((cmd, consumed))) => {
-                            commands.push(cmd);
+                            self.push_checked(&mut commands, cmd, i, i + consumed);
+                            i += consumed;
+                        }
+                        Ok(None) if self.pending_exceeded(i) => i = self.reject_pending(i),
+                        Ok(None) => break,
+                        Err(e) => i = self.reject(i, e),
+                    }
+                }
+                0x10 => {
+                    // DLE real-time command
+                    if i + 1 >= self.buffer.len() {
+                        break;
+                    }
+                    match self.parse_dle_command(&self.buffer[i..]) {
+                        Ok(Some((cmd, consumed))) => {
+                            self.push_checked(&mut commands, cmd, i, i + consumed);
+                            i += consumed;
+                        }
+                        Ok(None) if self.pending_exceeded(i) => i = self.reject_pending(i),
+                        Ok(None) => break,
+                        Err(e) => i = self.reject(i, e),
+                    }
+                }
+                0x1C => {
+                    // FS sequence
+                    if i + 1 >= self.buffer.len() {
+                        break;
+                    }
+                    match self.parse_fs_command(&self.buffer[i..]) {
+                        Ok(Some((cmd, consumed))) => {
+                            self.push_checked(&mut commands, cmd, i, i + consumed);
                             i += consumed;
                         }
+                        Ok(None) if self.pending_exceeded(i) => i = self.reject_pending(i),
                         Ok(None) => break,
-                        Err(_) => { i += 2; }
+                        Err(e) => i = self.reject(i, e),
                     }
                 }
                 _ => {
@@ -61,15 +237,18 @@ impl EscPosParser {
                     while i < self.buffer.len()
                         && self.buffer[i] != 0x1B
                         && self.buffer[i] != 0x1D
+                        && self.buffer[i] != 0x1C
+                        && self.buffer[i] != 0x10
                         && self.buffer[i] != b'\n'
                         && self.buffer[i] != b'\r'
+                        && self.buffer[i] != b'\t'
                     {
                         i += 1;
                     }
                     if i > text_start {
-                        let text = String::from_utf8_lossy(&self.buffer[text_start..i]).to_string();
+                        let text = codepage::decode(self.code_page, &self.buffer[text_start..i]);
                         if !text.is_empty() {
-                            commands.push(EscPosCommand::Text(text));
+                            self.push_command(&mut commands, EscPosCommand::Text(text), text_start, i);
                         }
                     }
                 }
@@ -78,11 +257,121 @@ impl EscPosParser {
 
         if i > 0 {
             self.buffer.drain(0..i);
+            self.offset += i as u64;
         }
 
         Ok(commands)
     }
 
+    /// Cut `data` down to what still fits in `max_job_bytes`, reporting the overflow once.
+    fn enforce_job_limit<'a>(&mut self, data: &'a [u8]) -> &'a [u8] {
+        let limit = self.limits.max_job_bytes as u64;
+        let received = self.offset + self.buffer.len() as u64 - self.job_start;
+        let allowed = limit.saturating_sub(received) as usize;
+        if data.len() <= allowed {
+            return data;
+        }
+        if !self.job_limit_exceeded {
+            self.job_limit_exceeded = true;
+            self.diagnostics.push(Diagnostic::new(
+                self.job_start + limit,
+                Severity::Error,
+                DiagnosticKind::LimitExceeded,
+                format!("job exceeds {} bytes, discarding the rest", limit),
+            ));
+        }
+        &data[..allowed]
+    }
+
+    fn pending_exceeded(&self, start: usize) -> bool {
+        self.buffer.len() - start > self.limits.max_pending_bytes
+    }
+
+    fn reject_pending(&mut self, start: usize) -> usize {
+        let message = format!(
+            "incomplete {} exceeds the {} byte pending buffer",
+            describe_command(&self.buffer[start..]),
+            self.limits.max_pending_bytes
+        );
+        self.report(start, Severity::Error, DiagnosticKind::LimitExceeded, message);
+        self.resync_from(start + 1)
+    }
+
+    fn report(&mut self, start: usize, severity: Severity, kind: DiagnosticKind, message: String) {
+        self.diagnostics
+            .push(Diagnostic::new(self.offset + start as u64, severity, kind, message));
+    }
+
+    /// Push a decoded ESC/GS command after checking it against the profile and
+    /// the parameter ranges of the specification.
+    fn push_checked(&mut self, commands: &mut Vec<ParsedCommand>, command: EscPosCommand, start: usize, end: usize) {
+        let raw = &self.buffer[start..end];
+        if self.unsupported.iter().any(|prefix| raw.starts_with(prefix)) {
+            let message = format!("{} is not supported by the printer profile", describe_command(raw));
+            self.report(start, Severity::Warning, DiagnosticKind::Unsupported, message);
+            let raw = self.buffer[start..end].to_vec();
+            self.push_command(commands, EscPosCommand::Unknown(raw), start, end);
+            return;
+        }
+
+        if let EscPosCommand::Unknown(_) = command {
+            let message = format!("unknown command {}", describe_command(raw));
+            self.report(start, Severity::Warning, DiagnosticKind::UnknownCommand, message);
+        } else if let EscPosCommand::SetCodepage(page) = command {
+            if !self.code_pages.is_empty() && !self.code_pages.contains(&page) {
+                let message = format!("code page {} is not available on the printer profile", page);
+                self.report(start, Severity::Warning, DiagnosticKind::Unsupported, message);
+            }
+        } else if let Some(message) = check_parameters(raw) {
+            self.report(start, Severity::Warning, DiagnosticKind::OutOfRange, message);
+        }
+        match command {
+            EscPosCommand::SetCodepage(page) => self.code_page = page,
+            EscPosCommand::InitializePrinter => self.code_page = 0,
+            _ => {}
+        }
+        self.push_command(commands, command, start, end);
+    }
+
+    /// Report a command the parser refused and find where parsing resumes: past
+    /// its declared payload when the length fields were readable, otherwise at
+    /// the next command introducer.
+    fn reject(&mut self, start: usize, error: anyhow::Error) -> usize {
+        self.report(start, Severity::Error, DiagnosticKind::LimitExceeded, error.to_string());
+        match error.downcast_ref::<Rejected>() {
+            Some(rejected) => self.skip_from(start, rejected.length),
+            None => self.resync_from(start + 2),
+        }
+    }
+
+    /// Index just past `length` bytes from `start`; bytes still to come are
+    /// dropped as they arrive.
+    fn skip_from(&mut self, start: usize, length: usize) -> usize {
+        let end = start + length;
+        self.skipping = end.saturating_sub(self.buffer.len());
+        end.min(self.buffer.len())
+    }
+
+    /// Index of the next ESC, GS, FS, DLE or LF at or after `start`. If there is
+    /// none the whole buffer is skipped and the search continues with the next read.
+    fn resync_from(&mut self, start: usize) -> usize {
+        let next = self.buffer[start.min(self.buffer.len())..]
+            .iter()
+            .position(|&b| matches!(b, 0x1B | 0x1D | 0x1C | 0x10 | b'\n'))
+            .map(|pos| start + pos);
+        self.resyncing = next.is_none();
+        next.unwrap_or(self.buffer.len())
+    }
+
+    /// Attach the source span `buffer[start..end]` to a decoded command.
+    fn push_command(&self, commands: &mut Vec<ParsedCommand>, command: EscPosCommand, start: usize, end: usize) {
+        commands.push(ParsedCommand {
+            command,
+            raw: self.buffer[start..end].to_vec(),
+            offset: self.offset + start as u64,
+        });
+    }
+
     /// Parse ESC (0x1B) commands. Returns (command, bytes_consumed).
     fn parse_esc_command(&self, data: &[u8]) -> Result<Option<(EscPosCommand, usize)>> {
         if data.len() < 2 {
@@ -97,9 +386,9 @@ impl EscPosParser {
             b'M' => {
                 if data.len() < 3 { return Ok(None); }
                 let font = match data[2] {
-                    0 => Font::FontA,
-                    1 => Font::FontB,
-                    2 => Font::FontC,
+                    0 | b'0' => Font::FontA,
+                    1 | b'1' => Font::FontB,
+                    2 | b'2' => Font::FontC,
                     _ => Font::FontA,
                 };
                 Ok(Some((EscPosCommand::SetFont(font), 3)))
@@ -109,9 +398,9 @@ impl EscPosParser {
             b'a' => {
                 if data.len() < 3 { return Ok(None); }
                 let j = match data[2] {
-                    0 => Justification::Left,
-                    1 => Justification::Center,
-                    2 => Justification::Right,
+                    0 | b'0' => Justification::Left,
+                    1 | b'1' => Justification::Center,
+                    2 | b'2' => Justification::Right,
                     _ => Justification::Left,
                 };
                 Ok(Some((EscPosCommand::SetJustification(j), 3)))
@@ -161,8 +450,34 @@ impl EscPosParser {
                 Ok(Some((EscPosCommand::SetCodepage(data[2]), 3)))
             }
 
-            // Cut paper
-            b'm' | b'i' => Ok(Some((EscPosCommand::CutPaper, 2))),
+            // ESC D n1 ... nk NUL — horizontal tab stops, at most 32; bytes past
+            // the 32nd stop are printed as data
+            b'D' => {
+                let stops = &data[2..data.len().min(2 + MAX_TAB_STOPS)];
+                match stops.iter().position(|&b| b == 0) {
+                    Some(end) => Ok(Some((EscPosCommand::SetTabStops(stops[..end].to_vec()), 2 + end + 1))),
+                    None if stops.len() == MAX_TAB_STOPS => {
+                        Ok(Some((EscPosCommand::SetTabStops(stops.to_vec()), 2 + MAX_TAB_STOPS)))
+                    }
+                    None => Ok(None),
+                }
+            }
+
+            // Cut paper, leaving one (ESC i) or three (ESC m) points uncut
+            b'm' | b'i' => Ok(Some((EscPosCommand::CutPaper(CutKind::Partial), 2))),
+
+            // ESC p m t1 t2 — drawer kick pulse, on and off times in 2 ms units
+            b'p' => {
+                if data.len() < 5 { return Ok(None); }
+                Ok(Some((
+                    EscPosCommand::DrawerKick {
+                        pin: drawer_pin(data[2]),
+                        on_ms: data[3] as u32 * 2,
+                        off_ms: data[4] as u32 * 2,
+                    },
+                    5,
+                )))
+            }
 
             // Paper feed
             b'J' => {
@@ -187,9 +502,19 @@ impl EscPosParser {
                 let bytes_per_col: u16 = match m { 0 | 1 => 1, 32 | 33 => 3, _ => 1 };
                 let total = bytes_per_col as usize * n_dots as usize;
                 let consumed = 5 + total;
+                if total > self.limits.max_raster_bytes {
+                    let message = format!(
+                        "ESC * bit image of {} bytes exceeds the {} byte limit",
+                        total, self.limits.max_raster_bytes
+                    );
+                    return Err(Rejected { message, length: consumed }.into());
+                }
                 if data.len() < consumed { return Ok(None); }
                 let image_data = data[5..consumed].to_vec();
-                Ok(Some((EscPosCommand::PrintImage(image_data), consumed)))
+                Ok(Some((
+                    EscPosCommand::PrintImage { mode: m, width_dots: n_dots, data: image_data },
+                    consumed,
+                )))
             }
 
             _ => {
@@ -218,6 +543,16 @@ impl EscPosParser {
                 let height = y_l + y_h * 256;       // number of rows
                 let total = width_bytes as usize * height as usize;
                 let consumed = 8 + total;
+                if total > self.limits.max_raster_bytes {
+                    let message = format!(
+                        "GS v 0 raster of {}x{} ({} bytes) exceeds the {} byte limit",
+                        width_bytes as u32 * 8,
+                        height,
+                        total,
+                        self.limits.max_raster_bytes
+                    );
+                    return Err(Rejected { message, length: consumed }.into());
+                }
                 if data.len() < consumed { return Ok(None); }
                 let image_data = data[8..consumed].to_vec();
                 Ok(Some((
@@ -226,17 +561,122 @@ impl EscPosParser {
                 )))
             }
 
+            // GS ( fn pL pH ... — extended commands with a length prefix
+            b'(' => {
+                if data.len() < 5 { return Ok(None); }
+                let consumed = 5 + (data[3] as usize + data[4] as usize * 256);
+                if data.len() < consumed { return Ok(None); }
+                match data[2] {
+                    // GS ( F pL pH a m nL nH — set print start / cut position adjustment
+                    b'F' if consumed >= 9 => {
+                        let position = match data[5] {
+                            2 => MarkPosition::Cut,
+                            _ => MarkPosition::PrintStart,
+                        };
+                        let n = data[7] as i32 + data[8] as i32 * 256;
+                        // m = 1 or 49 moves the position backward (against the feed direction)
+                        let dots = if matches!(data[6], 1 | 49) { -n } else { n };
+                        Ok(Some((EscPosCommand::SetMarkAdjustment { position, dots }, consumed)))
+                    }
+                    // GS ( k pL pH cn fn ... — QR code (cn = 49)
+                    b'k' if consumed >= 7 && data[5] == 49 => {
+                        let cmd = match data[6] {
+                            65 if consumed >= 8 => EscPosCommand::SetQrModel(data[7]),
+                            67 if consumed >= 8 => EscPosCommand::SetQrModuleSize(data[7]),
+                            69 if consumed >= 8 => EscPosCommand::SetQrErrorCorrection(data[7]),
+                            80 if consumed >= 8 => EscPosCommand::StoreQrData(data[8..consumed].to_vec()),
+                            81 => EscPosCommand::PrintQrCode,
+                            _ => EscPosCommand::Unknown(data[..consumed].to_vec()),
+                        };
+                        Ok(Some((cmd, consumed)))
+                    }
+                    _ => Ok(Some((EscPosCommand::Unknown(data[..consumed].to_vec()), consumed))),
+                }
+            }
+
+            // GS ! n — Select character size
+            b'!' => {
+                if data.len() < 3 { return Ok(None); }
+                let width = (data[2] >> 4) + 1;
+                let height = (data[2] & 0x0F) + 1;
+                Ok(Some((EscPosCommand::SetCharacterSize { width, height }, 3)))
+            }
+
+            // GS B n — Reverse printing
+            b'B' => {
+                if data.len() < 3 { return Ok(None); }
+                Ok(Some((EscPosCommand::SetReverse(data[2] & 1 == 1), 3)))
+            }
+
+            // GS h n / GS w n / GS H n — Barcode height, module width and HRI position
+            b'h' => {
+                if data.len() < 3 { return Ok(None); }
+                Ok(Some((EscPosCommand::SetBarcodeHeight(data[2]), 3)))
+            }
+            b'w' => {
+                if data.len() < 3 { return Ok(None); }
+                Ok(Some((EscPosCommand::SetBarcodeWidth(data[2]), 3)))
+            }
+            b'H' => {
+                if data.len() < 3 { return Ok(None); }
+                Ok(Some((EscPosCommand::SetHriPosition(data[2] & 0x03), 3)))
+            }
+
+            // GS k m d1...dk NUL (m = 0..6) or GS k m n d1...dn (m >= 65) — Print barcode
+            b'k' => {
+                if data.len() < 3 { return Ok(None); }
+                let m = data[2];
+                let (payload, consumed) = if m < 65 {
+                    match data[3..].iter().position(|&b| b == 0) {
+                        Some(end) => (data[3..3 + end].to_vec(), 3 + end + 1),
+                        None => return Ok(None),
+                    }
+                } else {
+                    if data.len() < 4 { return Ok(None); }
+                    let consumed = 4 + data[3] as usize;
+                    if data.len() < consumed { return Ok(None); }
+                    (data[4..consumed].to_vec(), consumed)
+                };
+                let system = BarcodeSystem::from_selector(m);
+                Ok(Some((EscPosCommand::PrintBarcode { system, data: payload }, consumed)))
+            }
+
+            // GS FF — Feed label/black mark paper to the print starting position
+            0x0C => Ok(Some((EscPosCommand::FeedToMark(MarkPosition::PrintStart), 2))),
+
+            // GS I n — Transmit printer ID
+            b'I' => {
+                if data.len() < 3 { return Ok(None); }
+                Ok(Some((EscPosCommand::TransmitPrinterId(data[2]), 3)))
+            }
+
+            // GS : — Start/end macro definition
+            b':' => Ok(Some((EscPosCommand::ToggleMacroDefinition, 2))),
+
+            // GS ^ r t m — Execute macro
+            b'^' => {
+                if data.len() < 5 { return Ok(None); }
+                Ok(Some((
+                    EscPosCommand::ExecuteMacro { repeat: data[2], wait: data[3], mode: data[4] },
+                    5,
+                )))
+            }
+
             // GS V — Cut paper (with variants)
             b'V' => {
                 if data.len() < 3 { return Ok(None); }
+                // m = 0, 48 and 65 cut fully; 1, 49 and 66 leave a point uncut
+                let kind = match data[2] {
+                    1 | 49 | 66 => CutKind::Partial,
+                    _ => CutKind::Full,
+                };
                 match data[2] {
-                    0 | 1 => Ok(Some((EscPosCommand::CutPaper, 3))),
                     65 | 66 => {
                         // GS V 65/66 n — need one more byte
                         if data.len() < 4 { return Ok(None); }
-                        Ok(Some((EscPosCommand::CutPaper, 4)))
+                        Ok(Some((EscPosCommand::CutPaper(kind), 4)))
                     }
-                    _ => Ok(Some((EscPosCommand::CutPaper, 3))),
+                    _ => Ok(Some((EscPosCommand::CutPaper(kind), 3))),
                 }
             }
 
@@ -245,6 +685,101 @@ impl EscPosParser {
             }
         }
     }
+
+    /// Parse DLE (0x10) real-time commands. Returns (command, bytes_consumed).
+    fn parse_dle_command(&self, data: &[u8]) -> Result<Option<(EscPosCommand, usize)>> {
+        if data.len() < 2 {
+            return Ok(None);
+        }
+
+        match data[1] {
+            // DLE EOT n — real-time status
+            0x04 => {
+                if data.len() < 3 { return Ok(None); }
+                Ok(Some((EscPosCommand::RealtimeStatus(data[2]), 3)))
+            }
+
+            // DLE DC4 1 m t — real-time drawer kick, t × 100 ms on and off
+            0x14 => {
+                if data.len() < 3 { return Ok(None); }
+                if data[2] != 1 {
+                    return Ok(Some((EscPosCommand::Unknown(data[..3].to_vec()), 3)));
+                }
+                if data.len() < 5 { return Ok(None); }
+                let ms = data[4].clamp(1, 8) as u32 * 100;
+                Ok(Some((EscPosCommand::DrawerKick { pin: drawer_pin(data[3]), on_ms: ms, off_ms: ms }, 5)))
+            }
+
+            _ => {
+                Ok(Some((EscPosCommand::Unknown(data[..2].to_vec()), 2)))
+            }
+        }
+    }
+
+    /// Parse FS (0x1C) commands. Returns (command, bytes_consumed).
+    fn parse_fs_command(&self, data: &[u8]) -> Result<Option<(EscPosCommand, usize)>> {
+        if data.len() < 2 {
+            return Ok(None);
+        }
+
+        match data[1] {
+            // FS ( fn pL pH ... — extended commands with a length prefix
+            b'(' => {
+                if data.len() < 5 { return Ok(None); }
+                let consumed = 5 + (data[3] as usize + data[4] as usize * 256);
+                if data.len() < consumed { return Ok(None); }
+                // FS ( L pL pH fn m — label and black mark control
+                let position = match (data[2], data.get(5).filter(|_| consumed > 5)) {
+                    (b'L', Some(65)) => Some(MarkPosition::Peel),
+                    (b'L', Some(66)) => Some(MarkPosition::Cut),
+                    (b'L', Some(67)) => Some(MarkPosition::PrintStart),
+                    _ => None,
+                };
+                let cmd = match position {
+                    Some(position) => EscPosCommand::FeedToMark(position),
+                    None => EscPosCommand::Unknown(data[..consumed].to_vec()),
+                };
+                Ok(Some((cmd, consumed)))
+            }
+
+            _ => {
+                Ok(Some((EscPosCommand::Unknown(data[..2].to_vec()), 2)))
+            }
+        }
+    }
+}
+
+/// Check the parameters of a complete ESC/GS command against the ranges
+/// allowed by the ESC/POS specification.
+fn check_parameters(raw: &[u8]) -> Option<String> {
+    let (&prefix, &cmd) = (raw.first()?, raw.get(1)?);
+    if (prefix, cmd) == (0x1D, b'^') && raw.get(4).is_some_and(|&m| m > 1) {
+        return Some(format!("GS ^ mode {} is out of range", raw[4]));
+    }
+    let n = *raw.get(2)?;
+    if (prefix, cmd) == (0x1D, b'!') && (n >> 4 > 7 || n & 0x0F > 7) {
+        return Some(format!("GS ! size 0x{:02X} exceeds 8x magnification", n));
+    }
+    let allowed: &[u8] = match (prefix, cmd) {
+        (0x1B, b'M') | (0x1B, b'a') | (0x1B, b'-') => &[0, 1, 2, b'0', b'1', b'2'],
+        (0x1B, b'*') => &[0, 1, 32, 33],
+        (0x1D, b'v') => &[0, b'0'],
+        (0x1D, b'V') => &[0, 1, b'0', b'1', 65, 66],
+        (0x1D, b'k') => &[0, 1, 2, 3, 4, 5, 6, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79],
+        (0x1D, b'w') => &[1, 2, 3, 4, 5, 6],
+        (0x1B, b'p') => &[0, 1, b'0', b'1'],
+        (0x10, 0x04) => &[1, 2, 3, 4],
+        _ => return None,
+    };
+    if allowed.contains(&n) {
+        return None;
+    }
+    Some(format!("{} parameter {} is out of range", describe_command(raw), n))
+}
+
+/// Connector pin selected by the `m` of ESC p and DLE DC4: 2 or 5.
+fn drawer_pin(m: u8) -> u8 {
+    if m & 1 == 0 { 2 } else { 5 }
 }
 
 impl Default for EscPosParser {
@@ -257,6 +792,98 @@ impl Clone for EscPosParser {
     fn clone(&self) -> Self {
         Self {
             buffer: self.buffer.clone(),
+            offset: self.offset,
+            limits: self.limits.clone(),
+            diagnostics: self.diagnostics.clone(),
+            unsupported: self.unsupported.clone(),
+            code_pages: self.code_pages.clone(),
+            code_page: self.code_page,
+            resyncing: self.resyncing,
+            skipping: self.skipping,
+            job_start: self.job_start,
+            job_limit_exceeded: self.job_limit_exceeded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_limits() -> ParserLimits {
+        ParserLimits { max_raster_bytes: 16, ..ParserLimits::default() }
+    }
+
+    fn commands(parser: &mut EscPosParser, data: &[u8]) -> Vec<EscPosCommand> {
+        parser.parse_stream(data).unwrap().into_iter().map(|parsed| parsed.command).collect()
+    }
+
+    #[test]
+    fn mark_adjustment_direction() {
+        let mut parser = EscPosParser::new();
+        for (m, expected) in [(0, 16), (1, -16), (48, 16), (49, -16)] {
+            let parsed = commands(&mut parser, &[0x1D, b'(', b'F', 4, 0, 2, m, 16, 0]);
+            assert!(
+                matches!(parsed[..], [EscPosCommand::SetMarkAdjustment { position: MarkPosition::Cut, dots }] if dots == expected),
+                "m = {}: {:?}",
+                m,
+                parsed
+            );
         }
     }
+
+    #[test]
+    fn a_command_split_across_reads_keeps_its_raw_bytes_and_offset() {
+        let mut parser = EscPosParser::new();
+        // GS v 0 of 1x2 bytes, split inside its header
+        let raster = [0x1D, b'v', b'0', 0, 1, 0, 2, 0, 0xAA, 0x55];
+        let mut first = b"AB".to_vec();
+        first.extend_from_slice(&raster[..5]);
+        let mut second = raster[5..].to_vec();
+        second.push(b'\n');
+
+        let parsed = parser.parse_stream(&first).unwrap();
+        assert!(matches!(&parsed[..], [ParsedCommand { command: EscPosCommand::Text(text), raw, offset: 0 }] if text == "AB" && raw == b"AB"));
+        assert_eq!(parser.stream_offset(), 2);
+
+        let parsed = parser.parse_stream(&second).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!((parsed[0].raw.as_slice(), parsed[0].offset), (&raster[..], 2));
+        assert!(
+            matches!(&parsed[0].command, EscPosCommand::PrintRasterImage { width_bytes: 1, height: 2, data } if data == &[0xAA, 0x55]),
+            "{:?}",
+            parsed[0].command
+        );
+        assert_eq!((parsed[1].raw.as_slice(), parsed[1].offset), (&b"\n"[..], 12));
+        assert_eq!(parser.stream_offset(), 13);
+        assert!(parser.take_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn rejected_raster_payload_is_skipped_across_reads() {
+        let mut parser = EscPosParser::with_limits(small_limits());
+        // GS v 0 of 4x8 bytes whose payload is full of ESC and GS bytes
+        let mut data = vec![0x1D, b'v', b'0', 0, 4, 0, 8, 0];
+        data.extend(std::iter::repeat_n([0x1B, 0x1D], 16).flatten());
+        let (first, rest) = data.split_at(12);
+
+        assert!(commands(&mut parser, first).is_empty());
+        let mut tail = rest.to_vec();
+        tail.extend_from_slice(b"OK\n");
+        let parsed = commands(&mut parser, &tail);
+        assert!(matches!(&parsed[..], [EscPosCommand::Text(text), EscPosCommand::NewLine] if text == "OK"));
+        let diagnostics = parser.take_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::LimitExceeded);
+    }
+
+    #[test]
+    fn rejected_bit_image_resumes_at_the_next_command() {
+        let mut parser = EscPosParser::with_limits(small_limits());
+        let mut data = vec![0x1B, b'*', 33, 10, 0];
+        data.extend(std::iter::repeat_n(0x1C, 30));
+        data.extend_from_slice(&[0x10, 0x04, 1]);
+        let parsed = commands(&mut parser, &data);
+        assert!(matches!(&parsed[..], [EscPosCommand::RealtimeStatus(1)]));
+    }
 }
@@ -1,32 +1,25 @@
-use crate::escpos::commands::{EscPosCommand, Font, Justification};
-use image::{ImageBuffer, Rgb, RgbImage};
+use crate::escpos::barcode;
+use crate::escpos::commands::{BarcodeSystem, CutKind, EscPosCommand, Font, Justification, MarkPosition};
+use crate::escpos::paper::{PaperModel, PaperWidth};
+use crate::escpos::profile::PrinterProfile;
+use crate::escpos::parser::ParsedCommand;
+use crate::escpos::raster;
+use crate::export::html;
+use crate::export::pdf::{self, PdfScope};
+use crate::export::text;
+use anyhow::{Context, Result};
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, ImageFormat, Rgb, RgbImage};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::{info, warn};
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum PaperWidth {
-    Width50mm,  // 384 dots (48 chars normal font)
-    Width78mm,  // 576 dots (72 chars normal font)
-    Width80mm,  // 640 dots (80 chars normal font)
-}
-
-impl PaperWidth {
-    pub fn get_width_dots(&self) -> u32 {
-        match self {
-            PaperWidth::Width50mm => 384,
-            PaperWidth::Width78mm => 576,
-            PaperWidth::Width80mm => 640,
-        }
-    }
+/// Size of the macro buffer on Epson TM printers; excess definition data is not stored.
+pub const MACRO_BUFFER_SIZE: usize = 2048;
 
-    pub fn get_max_chars(&self, font_size: u32) -> u32 {
-        let dots = self.get_width_dots();
-        match font_size {
-            8..=12 => dots / 8,
-            13..=16 => dots / 10,
-            17..=24 => dots / 12,
-            _ => dots / 8,
-        }
-    }
+/// Tab stops after ESC @: every 8 characters.
+fn default_tab_stops() -> Vec<u8> {
+    (8..=u8::MAX).step_by(8).collect()
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -38,6 +31,10 @@ pub struct TextLine {
     pub underline: bool,
     pub italic: bool,
     pub font_size: u32,
+    pub reverse: bool,
+    /// Character magnification from ESC ! or GS !
+    pub width_mult: u8,
+    pub height_mult: u8,
 }
 
 /// A single line element in the receipt buffer
@@ -45,8 +42,41 @@ pub struct TextLine {
 pub enum ReceiptLine {
     Text(TextLine),
     /// Monochrome bitmap: width in pixels, height in pixels, 1-bit-per-pixel packed data
-    Bitmap { width_px: u32, height_px: u32, data: Vec<u8> },
-    Separator,
+    Bitmap { width_px: u32, height_px: u32, data: Vec<u8>, justification: Justification },
+    /// Paper cut, drawn as a dashed line
+    Separator(CutKind),
+    /// Blank paper fed without printing, in dots
+    Feed { dots: u32 },
+    /// GS k barcode with the GS h / GS w / GS H settings at the time it was printed
+    Barcode {
+        system: BarcodeSystem,
+        data: Vec<u8>,
+        height: u32,
+        module_width: u32,
+        hri_position: u8,
+        justification: Justification,
+    },
+    /// QR code printed from the GS ( k symbol storage area
+    QrCode { data: Vec<u8>, model: u8, module_size: u32, error_correction: u8, justification: Justification },
+}
+
+impl ReceiptLine {
+    /// Lines exporters embed as images cut from the rasterized receipt.
+    pub fn is_graphic(&self) -> bool {
+        matches!(self, ReceiptLine::Bitmap { .. } | ReceiptLine::Barcode { .. } | ReceiptLine::QrCode { .. })
+    }
+
+    /// Split lines into receipts, each ending with its cut. Blank trailing
+    /// text after the last cut is not a receipt.
+    pub fn split_receipts(lines: &[ReceiptLine]) -> Vec<&[ReceiptLine]> {
+        let mut receipts: Vec<&[ReceiptLine]> =
+            lines.split_inclusive(|line| matches!(line, ReceiptLine::Separator(_))).collect();
+        let blank = |line: &ReceiptLine| matches!(line, ReceiptLine::Text(text) if text.text.is_empty());
+        if receipts.last().is_some_and(|last| last.iter().all(blank)) {
+            receipts.pop();
+        }
+        receipts
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,14 +90,44 @@ pub struct PrinterState {
     pub buffer: Vec<ReceiptLine>,
     pub line_height: u32,
     pub font_size: u32,
+    pub reverse: bool,
+    pub width_mult: u8,
+    pub height_mult: u8,
+    pub barcode_height: u32,
+    pub barcode_module_width: u32,
+    pub hri_position: u8,
+    pub qr_model: u8,
+    pub qr_module_size: u32,
+    pub qr_error_correction: u8,
+    pub qr_data: Vec<u8>,
     pub dpi: u32,
     pub codepage: u8,
+    /// Horizontal tab stops in characters from the left margin, ascending
+    #[serde(default = "default_tab_stops")]
+    pub tab_stops: Vec<u8>,
+    /// Raw bytes of the macro defined with GS :
+    pub macro_buffer: Vec<u8>,
+    pub macro_recording: bool,
+    pub paper: PaperModel,
+    pub profile: PrinterProfile,
+    /// The last line is an ESC * bit image, which the next LF prints
+    #[serde(skip)]
+    bit_image_line: bool,
+    /// Paper fed by lines already dropped from the front of the buffer
+    #[serde(default)]
+    trimmed_dots: u32,
+    /// Bumped on every change that can alter the rendered receipt
+    #[serde(skip)]
+    revision: u64,
+    /// Bytes to send back to the host (status and ID requests)
+    #[serde(skip)]
+    responses: Vec<u8>,
 }
 
 impl PrinterState {
     pub fn new() -> Self {
         Self {
-            paper_width: PaperWidth::Width80mm,
+            paper_width: PrinterProfile::default().paper_width(80.0),
             current_font: Font::FontA,
             justification: Justification::Left,
             emphasis: false,
@@ -76,19 +136,102 @@ impl PrinterState {
             buffer: Vec::new(),
             line_height: 24,
             font_size: 12,
-            dpi: 180,
+            reverse: false,
+            width_mult: 1,
+            height_mult: 1,
+            barcode_height: 162,
+            barcode_module_width: 3,
+            hri_position: 0,
+            qr_model: 50,
+            qr_module_size: 3,
+            qr_error_correction: 48,
+            qr_data: Vec::new(),
+            dpi: PrinterProfile::default().dpi,
             codepage: 0,
+            tab_stops: default_tab_stops(),
+            macro_buffer: Vec::new(),
+            macro_recording: false,
+            paper: PaperModel::default(),
+            profile: PrinterProfile::default(),
+            bit_image_line: false,
+            trimmed_dots: 0,
+            revision: 0,
+            responses: Vec::new(),
+        }
+    }
+
+    /// Changes whenever the receipt may render differently, for caching renders.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn set_profile(&mut self, profile: PrinterProfile) {
+        self.revision += 1;
+        self.paper_width = profile.paper_width(self.paper_width.width_mm);
+        self.dpi = profile.dpi;
+        self.line_height = profile.font_a.height;
+        self.profile = profile;
+    }
+
+    /// Drain the bytes the printer wants to send back to the host.
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.responses)
+    }
+
+    /// Process a command with its source bytes, so it can be captured into a
+    /// macro definition. Commands are still executed while a macro is recorded.
+    pub fn process_parsed(&mut self, parsed: &ParsedCommand) {
+        match parsed.command {
+            EscPosCommand::ToggleMacroDefinition => {}
+            EscPosCommand::ExecuteMacro { .. } if self.macro_recording => {
+                // GS ^ during definition aborts it and clears the macro
+                self.macro_recording = false;
+                self.macro_buffer.clear();
+                return;
+            }
+            _ if self.macro_recording => {
+                let room = MACRO_BUFFER_SIZE - self.macro_buffer.len();
+                if parsed.raw.len() > room {
+                    warn!("Macro definition exceeds {} bytes, excess data dropped", MACRO_BUFFER_SIZE);
+                }
+                self.macro_buffer.extend_from_slice(&parsed.raw[..parsed.raw.len().min(room)]);
+            }
+            _ => {}
         }
+        self.process_command(&parsed.command);
     }
 
     pub fn process_command(&mut self, command: &EscPosCommand) {
+        self.revision += 1;
+        if !matches!(command, EscPosCommand::NewLine) {
+            self.bit_image_line = false;
+        }
         match command {
             EscPosCommand::Text(text) => {
                 self.add_text(text);
             }
+            EscPosCommand::NewLine if self.bit_image_line => {
+                self.bit_image_line = false;
+            }
             EscPosCommand::NewLine => {
                 self.add_new_line();
             }
+            EscPosCommand::HorizontalTab => {
+                self.horizontal_tab();
+            }
+            EscPosCommand::SetTabStops(stops) => {
+                // Stops must ascend; the first one that does not ends the list
+                let mut last = 0;
+                self.tab_stops = stops
+                    .iter()
+                    .copied()
+                    .take_while(|&stop| std::mem::replace(&mut last, stop) < stop)
+                    .collect();
+            }
+            EscPosCommand::InitializePrinter => {
+                self.codepage = 0;
+                self.tab_stops = default_tab_stops();
+            }
             EscPosCommand::SetFont(font) => {
                 self.current_font = font.clone();
             }
@@ -104,12 +247,11 @@ impl PrinterState {
             EscPosCommand::SetItalic(enabled) => {
                 self.italic = *enabled;
             }
-            EscPosCommand::CutPaper => {
-                self.add_separator();
+            EscPosCommand::CutPaper(kind) => {
+                self.add_separator(*kind);
             }
-            EscPosCommand::PrintImage(_image_data) => {
-                // ESC * bit image — store as text placeholder for now
-                self.add_text("[BIT IMAGE]");
+            EscPosCommand::PrintImage { mode, width_dots, data } => {
+                self.add_bit_image(*mode, *width_dots, data);
             }
             EscPosCommand::PrintRasterImage { width_bytes, height, data } => {
                 // GS v 0 raster image — width_bytes is bytes per row, each byte = 8 pixels
@@ -119,6 +261,54 @@ impl PrinterState {
                     width_px,
                     height_px,
                     data: data.clone(),
+                    justification: self.justification.clone(),
+                });
+            }
+            EscPosCommand::SetReverse(enabled) => {
+                self.reverse = *enabled;
+            }
+            EscPosCommand::SetCharacterSize { width, height } => {
+                self.width_mult = (*width).clamp(1, 8);
+                self.height_mult = (*height).clamp(1, 8);
+            }
+            EscPosCommand::SetBarcodeHeight(height) => {
+                self.barcode_height = (*height).max(1) as u32;
+            }
+            EscPosCommand::SetBarcodeWidth(width) => {
+                self.barcode_module_width = (*width as u32).clamp(1, 6);
+            }
+            EscPosCommand::SetHriPosition(position) => {
+                self.hri_position = *position;
+            }
+            EscPosCommand::PrintBarcode { system, data } => {
+                self.buffer.push(ReceiptLine::Barcode {
+                    system: *system,
+                    data: data.clone(),
+                    height: self.barcode_height,
+                    module_width: self.barcode_module_width,
+                    hri_position: self.hri_position,
+                    justification: self.justification.clone(),
+                });
+            }
+            EscPosCommand::SetQrModel(model) => {
+                self.qr_model = *model;
+            }
+            EscPosCommand::SetQrModuleSize(size) => {
+                self.qr_module_size = (*size).clamp(1, 16) as u32;
+            }
+            EscPosCommand::SetQrErrorCorrection(level) => {
+                self.qr_error_correction = *level;
+            }
+            EscPosCommand::StoreQrData(data) => {
+                self.qr_data = data.clone();
+            }
+            EscPosCommand::PrintQrCode if !self.qr_data.is_empty() => {
+                self.buffer.push(ReceiptLine::QrCode {
+                    data: self.qr_data.clone(),
+                    model: self.qr_model,
+                    module_size: self.qr_module_size,
+                    error_correction: self.qr_error_correction,
+                    justification: self.justification.clone(),
                 });
             }
             EscPosCommand::SetCodepage(cp) => {
@@ -128,13 +318,70 @@ impl PrinterState {
                 self.line_height = *height;
             }
             EscPosCommand::SetFontSize(size) => {
-                self.font_size = *size;
+                self.set_print_mode(*size);
+            }
+            EscPosCommand::SetMarkAdjustment { position, dots } => match position {
+                MarkPosition::PrintStart => self.paper.print_start_offset = *dots,
+                MarkPosition::Cut | MarkPosition::Peel => self.paper.cut_offset = *dots,
+            },
+            EscPosCommand::FeedToMark(position) => {
+                self.feed_to_mark(*position);
+            }
+            EscPosCommand::RealtimeStatus(n) => {
+                self.responses.push(self.realtime_status(*n));
+            }
+            EscPosCommand::TransmitPrinterId(n) => {
+                let id = self.printer_id(*n);
+                self.responses.extend_from_slice(&id);
+            }
+            EscPosCommand::ToggleMacroDefinition => {
+                if self.macro_recording {
+                    self.macro_recording = false;
+                } else {
+                    self.macro_recording = true;
+                    self.macro_buffer.clear();
+                }
+            }
+            // Runs are timed and fed back in by the emulator
+            EscPosCommand::ExecuteMacro { .. } => {}
+            EscPosCommand::DrawerKick { pin, on_ms, .. } => {
+                info!("💰 Drawer kick on pin {} for {} ms", pin, on_ms);
             }
             EscPosCommand::Unknown(_) => {}
             _ => {}
         }
     }
 
+    /// DLE EOT n: the virtual printer is always online, closed and has paper.
+    fn realtime_status(&self, n: u8) -> u8 {
+        match n {
+            // Printer status: fixed bits 1 and 4, drawer kick-out connector pin 3 low
+            1 => 0x16,
+            // Offline, error and paper sensor status: only the fixed bits set
+            _ => 0x12,
+        }
+    }
+
+    /// GS I n, answered from the printer profile.
+    fn printer_id(&self, n: u8) -> Vec<u8> {
+        let text = |value: &str| {
+            let mut bytes = vec![b'_'];
+            bytes.extend_from_slice(value.as_bytes());
+            bytes.push(0);
+            bytes
+        };
+        match n {
+            1 | 49 => vec![self.profile.model_id],
+            2 | 50 => vec![self.profile.type_id],
+            3 | 51 => vec![self.profile.firmware_version.bytes().next().unwrap_or(b'0')],
+            65 => text(&self.profile.firmware_version),
+            66 => text(&self.profile.manufacturer),
+            67 => text(&self.profile.model_name),
+            68 => text("EMULATOR0001"),
+            _ => Vec::new(),
+        }
+    }
+
     fn add_text(&mut self, text: &str) {
         let current_line_style = TextLine {
             text: String::new(),
@@ -144,18 +391,32 @@ impl PrinterState {
             underline: self.underline,
             italic: self.italic,
             font_size: self.font_size,
+            reverse: self.reverse,
+            width_mult: self.width_mult,
+            height_mult: self.height_mult,
         };
 
+        let max_chars = self.chars_per_line() / self.width_mult.max(1) as u32;
         if let Some(ReceiptLine::Text(last_line)) = self.buffer.last_mut() {
+            // A line that LF opened and nothing was printed on yet takes the
+            // style in effect when its first character arrives
+            if last_line.text.is_empty() {
+                *last_line = current_line_style;
+                last_line.text.push_str(text);
+                return;
+            }
+
             let matches_style = last_line.font == current_line_style.font
                 && last_line.justification == current_line_style.justification
                 && last_line.emphasis == current_line_style.emphasis
                 && last_line.underline == current_line_style.underline
                 && last_line.italic == current_line_style.italic
-                && last_line.font_size == current_line_style.font_size;
+                && last_line.font_size == current_line_style.font_size
+                && last_line.reverse == current_line_style.reverse
+                && last_line.width_mult == current_line_style.width_mult
+                && last_line.height_mult == current_line_style.height_mult;
 
             if matches_style {
-                let max_chars = self.paper_width.get_max_chars(self.font_size);
                 let current_length = last_line.text.chars().count();
 
                 if current_length + text.chars().count() > max_chars as usize {
@@ -174,6 +435,21 @@ impl PrinterState {
         self.buffer.push(ReceiptLine::Text(new_line));
     }
 
+    /// HT: pad the current line with spaces up to the next tab stop. Without a
+    /// stop to the right, or past the end of the line, HT is ignored.
+    fn horizontal_tab(&mut self) {
+        let column = match self.buffer.last() {
+            Some(ReceiptLine::Text(line)) => line.text.chars().count(),
+            _ => 0,
+        };
+        let max_chars = (self.chars_per_line() / self.width_mult.max(1) as u32) as usize;
+        if let Some(&stop) = self.tab_stops.iter().find(|&&stop| stop as usize > column) {
+            if (stop as usize) <= max_chars {
+                self.add_text(&" ".repeat(stop as usize - column));
+            }
+        }
+    }
+
     fn add_new_line(&mut self) {
         let line = TextLine {
             text: String::new(),
@@ -183,29 +459,135 @@ impl PrinterState {
             underline: self.underline,
             italic: self.italic,
             font_size: self.font_size,
+            reverse: self.reverse,
+            width_mult: self.width_mult,
+            height_mult: self.height_mult,
         };
         self.buffer.push(ReceiptLine::Text(line));
     }
 
-    fn add_separator(&mut self) {
-        self.buffer.push(ReceiptLine::Separator);
+    /// ESC ! n: font, emphasis, double height, double width and underline in one byte.
+    fn set_print_mode(&mut self, mode: u32) {
+        self.font_size = mode;
+        self.current_font = if mode & 0x01 != 0 { Font::FontB } else { Font::FontA };
+        self.emphasis = mode & 0x08 != 0;
+        self.height_mult = if mode & 0x10 != 0 { 2 } else { 1 };
+        self.width_mult = if mode & 0x20 != 0 { 2 } else { 1 };
+        self.underline = mode & 0x80 != 0;
+    }
+
+    /// ESC * column-format bit image, converted to a row-major bitmap at printer
+    /// resolution. Single density modes print every dot twice as wide, 8-dot
+    /// modes three times as tall.
+    fn add_bit_image(&mut self, mode: u8, width_dots: u16, data: &[u8]) {
+        let (column_bytes, scale_x, scale_y) = match mode {
+            0 => (1, 2, 3),
+            1 => (1, 1, 3),
+            32 => (3, 2, 1),
+            _ => (3, 1, 1),
+        };
+        let width_px = width_dots as u32 * scale_x;
+        let height_px = column_bytes * 8 * scale_y;
+        let row_bytes = width_px.div_ceil(8);
+        let mut bitmap = vec![0u8; (row_bytes * height_px) as usize];
+
+        for column in 0..width_dots as u32 {
+            for dot in 0..column_bytes * 8 {
+                let byte = data.get((column * column_bytes + dot / 8) as usize).copied().unwrap_or(0);
+                if byte >> (7 - dot % 8) & 1 == 0 {
+                    continue;
+                }
+                for y in dot * scale_y..(dot + 1) * scale_y {
+                    for x in column * scale_x..(column + 1) * scale_x {
+                        bitmap[(y * row_bytes + x / 8) as usize] |= 0x80 >> (x % 8);
+                    }
+                }
+            }
+        }
+
+        self.buffer.push(ReceiptLine::Bitmap {
+            width_px,
+            height_px,
+            data: bitmap,
+            justification: self.justification.clone(),
+        });
+        self.bit_image_line = true;
+    }
+
+    fn feed_to_mark(&mut self, position: MarkPosition) {
+        let head = self.fed_dots();
+        if let Some(target) = self.paper.next_position(self.dpi, head, position) {
+            if target > head {
+                self.buffer.push(ReceiptLine::Feed { dots: target - head });
+            }
+        }
+    }
+
+    /// Distance from the print head to the next black mark or label top, or
+    /// `None` on continuous paper.
+    pub fn dots_to_next_mark(&self) -> Option<u32> {
+        let head = self.fed_dots();
+        let pitch = self.paper.pitch_dots(self.dpi)?;
+        Some((pitch - head % pitch) % pitch)
+    }
+
+    fn add_separator(&mut self, kind: CutKind) {
+        self.buffer.push(ReceiptLine::Separator(kind));
     }
 
     pub fn clear_buffer(&mut self) {
+        self.revision += 1;
         self.buffer.clear();
+        self.trimmed_dots = 0;
+    }
+
+    /// Replace the receipt buffer, e.g. with lines from an imported job.
+    pub fn load_buffer(&mut self, lines: Vec<ReceiptLine>) {
+        self.revision += 1;
+        self.buffer = lines;
+        self.trimmed_dots = 0;
+        self.bit_image_line = false;
+    }
+
+    /// Drop the first `count` lines of the buffer. The print head stays where
+    /// it is, so marks and labels keep their positions.
+    pub fn trim_buffer(&mut self, count: usize) {
+        let count = count.min(self.buffer.len());
+        let dots: u32 = self.buffer[..count].iter().map(|line| self.line_height_dots(line)).sum();
+        self.trimmed_dots += dots;
+        self.buffer.drain(..count);
+        self.revision += 1;
     }
 
     pub fn get_buffer(&self) -> &[ReceiptLine] {
         &self.buffer
     }
 
+    /// The buffer split into receipts, each ending with its cut.
+    pub fn receipts(&self) -> Vec<&[ReceiptLine]> {
+        ReceiptLine::split_receipts(&self.buffer)
+    }
+
     pub fn get_paper_width_dots(&self) -> u32 {
-        self.paper_width.get_width_dots()
+        self.paper_width.width_dots()
     }
 
     pub fn get_printing_width_dots(&self) -> u32 {
-        let dots = self.paper_width.get_width_dots();
-        dots.saturating_sub(30)
+        self.paper_width.printable_dots()
+    }
+
+    /// Unprintable margin on each side of the paper, in dots.
+    pub fn get_margin_dots(&self) -> u32 {
+        self.paper_width.margin_dots()
+    }
+
+    /// Characters per line in the current font.
+    pub fn chars_per_line(&self) -> u32 {
+        self.chars_per_line_for(&self.current_font)
+    }
+
+    pub fn chars_per_line_for(&self, font: &Font) -> u32 {
+        self.paper_width.chars_per_line(self.profile.cell_size(font))
     }
 
     /// Convert a monochrome 1bpp bitmap to an RGB image for display
@@ -216,50 +598,112 @@ impl PrinterState {
             *pixel = Rgb([255, 255, 255]);
         }
 
-        let bytes_per_row = (width_px + 7) / 8;
+        let bytes_per_row = width_px.div_ceil(8);
         for y in 0..height_px {
             for x in 0..width_px {
                 let byte_idx = (y * bytes_per_row + x / 8) as usize;
                 let bit_idx = 7 - (x % 8);
-                if byte_idx < data.len() {
-                    if (data[byte_idx] >> bit_idx) & 1 == 1 {
-                        image.put_pixel(x, y, Rgb([0, 0, 0])); // Black pixel
-                    }
+                if byte_idx < data.len() && (data[byte_idx] >> bit_idx) & 1 == 1 {
+                    image.put_pixel(x, y, Rgb([0, 0, 0])); // Black pixel
                 }
             }
         }
         image
     }
 
+    /// Rasterize the receipt buffer at printer resolution, one pixel per dot.
     pub fn render_receipt(&self) -> RgbImage {
-        let width = self.get_paper_width_dots();
-        let height = self.calculate_total_height();
+        raster::render(self)
+    }
 
-        let mut image = ImageBuffer::new(width, height);
-        for pixel in image.pixels_mut() {
-            *pixel = Rgb([255, 255, 255]);
+    /// Rasterize the receipt with every dot enlarged to `scale` x `scale` pixels.
+    pub fn render_receipt_scaled(&self, scale: u32) -> RgbImage {
+        let image = self.render_receipt();
+        match scale {
+            0 | 1 => image,
+            _ => imageops::resize(&image, image.width() * scale, image.height() * scale, FilterType::Nearest),
         }
+    }
 
-        image
+    /// Write the receipt as a PNG at printer resolution, optionally upscaled.
+    pub fn export_png(&self, path: &Path, scale: u32) -> Result<()> {
+        self.render_receipt_scaled(scale)
+            .save_with_format(path, ImageFormat::Png)
+            .with_context(|| format!("Failed to write PNG to {}", path.display()))
+    }
+
+    /// Write the last receipt or the whole session as a PDF at the paper's
+    /// physical size, one page per cut.
+    pub fn export_pdf(&self, path: &Path, scope: PdfScope) -> Result<()> {
+        pdf::export_pdf(self, path, scope)
+    }
+
+    /// Write the receipt as a self-contained HTML page with real text.
+    pub fn export_html(&self, path: &Path) -> Result<()> {
+        html::export_html(self, path)
+    }
+
+    /// Write the receipt as an SVG image with real text.
+    pub fn export_svg(&self, path: &Path) -> Result<()> {
+        html::export_svg(self, path)
+    }
+
+    /// The receipt as plain text laid out in the printer's columns.
+    pub fn render_text(&self) -> String {
+        text::render_text(self, &self.buffer)
+    }
+
+    pub fn export_text(&self, path: &Path) -> Result<()> {
+        text::export_text(self, path)
     }
 
     pub fn calculate_total_height(&self) -> u32 {
-        let mut h = 0u32;
-        for line in &self.buffer {
-            match line {
-                ReceiptLine::Text(_) => h += self.line_height,
-                ReceiptLine::Bitmap { height_px, .. } => h += height_px,
-                ReceiptLine::Separator => h += self.line_height,
+        self.buffer_dots().max(1)
+    }
+
+    /// Paper advanced by one buffer line, in dots.
+    pub fn line_height_dots(&self, line: &ReceiptLine) -> u32 {
+        match line {
+            ReceiptLine::Text(text) => {
+                let cell = self.profile.cell_size(&text.font);
+                self.line_height.max(cell.height * text.height_mult as u32)
+            }
+            ReceiptLine::Bitmap { height_px, .. } => *height_px,
+            ReceiptLine::Separator(_) => self.line_height,
+            ReceiptLine::Feed { dots } => *dots,
+            ReceiptLine::Barcode { height, hri_position, .. } => {
+                let hri_lines = (hri_position & 0x01) as u32 + (hri_position >> 1 & 0x01) as u32;
+                height + hri_lines * self.profile.font_a.height
+            }
+            ReceiptLine::QrCode { data, model, module_size, error_correction, .. } => {
+                barcode::qr_symbol(data, *model, *error_correction).map_or(0, |(size, _)| size * module_size)
             }
         }
-        h.max(1)
+    }
+
+    /// Total paper fed since the buffer was cleared, i.e. the print head position.
+    pub fn fed_dots(&self) -> u32 {
+        self.trimmed_dots + self.buffer_dots()
+    }
+
+    /// Paper taken by the lines still in the buffer.
+    fn buffer_dots(&self) -> u32 {
+        self.buffer.iter().map(|line| self.line_height_dots(line)).sum()
     }
 
     pub fn set_paper_width(&mut self, width: PaperWidth) {
+        self.revision += 1;
         self.paper_width = width;
     }
 
+    /// Select a roll width; printable dots come from the printer profile.
+    pub fn set_paper_width_mm(&mut self, width_mm: f32) {
+        self.revision += 1;
+        self.paper_width = self.profile.paper_width(width_mm);
+    }
+
     pub fn set_line_height(&mut self, height: u32) {
+        self.revision += 1;
         self.line_height = height;
     }
 
@@ -267,3 +711,9 @@ impl PrinterState {
         self.font_size = size;
     }
 }
+
+impl Default for PrinterState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,371 @@
+use crate::escpos::commands::Font;
+use crate::escpos::paper::PaperWidth;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::warn;
+
+/// Size of one character cell, in dots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Printable line for one paper width.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaperSpec {
+    pub width_mm: f32,
+    pub dots_per_line: u32,
+}
+
+/// Capabilities of a printer model. Built-in profiles ship with the emulator,
+/// user-defined ones are loaded from JSON files with the same shape.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrinterProfile {
+    /// Short identifier used on the command line and in settings, e.g. `tm-t88vi`
+    pub id: String,
+    pub manufacturer: String,
+    pub model_name: String,
+    pub dpi: u32,
+    pub paper_widths: Vec<PaperSpec>,
+    pub font_a: CellSize,
+    pub font_b: CellSize,
+    /// Not every model has a third font; Font A is used instead
+    pub font_c: Option<CellSize>,
+    /// Code pages selectable with ESC t
+    pub code_pages: Vec<u8>,
+    /// Barcode symbologies the model can print, e.g. `CODE128`, `QR`
+    pub symbologies: Vec<String>,
+    pub nv_graphics_bytes: u32,
+    pub nv_user_memory_bytes: u32,
+    /// Commands the model ignores, written as `ESC 4` or `GS ( L`
+    pub ignored_commands: Vec<String>,
+    /// Values reported by GS I
+    pub model_id: u8,
+    pub type_id: u8,
+    pub firmware_version: String,
+}
+
+impl Default for PrinterProfile {
+    fn default() -> Self {
+        Self::tm_t88vi()
+    }
+}
+
+const EPSON_CODE_PAGES: &[u8] = &[
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 40, 41, 42, 43, 44, 45,
+    46, 47, 48, 49, 50, 51, 52, 53, 254, 255,
+];
+
+const FULL_SYMBOLOGIES: &[&str] = &[
+    "UPC-A", "UPC-E", "EAN13", "EAN8", "CODE39", "ITF", "CODABAR", "CODE93", "CODE128", "GS1-128",
+    "PDF417", "QR", "MAXICODE", "GS1-DATABAR",
+];
+
+fn strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+impl PrinterProfile {
+    pub fn tm_t88vi() -> Self {
+        Self {
+            id: "tm-t88vi".to_string(),
+            manufacturer: "EPSON".to_string(),
+            model_name: "TM-T88VI".to_string(),
+            dpi: 180,
+            paper_widths: vec![
+                PaperSpec { width_mm: 80.0, dots_per_line: 512 },
+                PaperSpec { width_mm: 58.0, dots_per_line: 360 },
+            ],
+            font_a: CellSize { width: 12, height: 24 },
+            font_b: CellSize { width: 9, height: 17 },
+            font_c: None,
+            code_pages: EPSON_CODE_PAGES.to_vec(),
+            symbologies: strings(FULL_SYMBOLOGIES),
+            nv_graphics_bytes: 256 * 1024,
+            nv_user_memory_bytes: 1024,
+            ignored_commands: strings(&["ESC 4", "ESC 5"]),
+            model_id: 0x20,
+            type_id: 0x02,
+            firmware_version: "30.01 ESC/POS".to_string(),
+        }
+    }
+
+    pub fn tm_t20iii() -> Self {
+        Self {
+            id: "tm-t20iii".to_string(),
+            model_name: "TM-T20III".to_string(),
+            dpi: 203,
+            paper_widths: vec![
+                PaperSpec { width_mm: 80.0, dots_per_line: 576 },
+                PaperSpec { width_mm: 58.0, dots_per_line: 420 },
+            ],
+            font_b: CellSize { width: 9, height: 17 },
+            nv_graphics_bytes: 256 * 1024,
+            ignored_commands: strings(&["ESC 4", "ESC 5", "GS ( F", "GS FF", "FS ( L"]),
+            model_id: 0x20,
+            type_id: 0x02,
+            firmware_version: "10.01 ESC/POS".to_string(),
+            ..Self::tm_t88vi()
+        }
+    }
+
+    pub fn tm_m30() -> Self {
+        Self {
+            id: "tm-m30".to_string(),
+            model_name: "TM-m30".to_string(),
+            dpi: 203,
+            paper_widths: vec![
+                PaperSpec { width_mm: 80.0, dots_per_line: 576 },
+                PaperSpec { width_mm: 58.0, dots_per_line: 384 },
+            ],
+            font_b: CellSize { width: 10, height: 24 },
+            font_c: Some(CellSize { width: 9, height: 17 }),
+            nv_graphics_bytes: 384 * 1024,
+            ignored_commands: strings(&["ESC 4", "ESC 5", "GS ( F", "GS FF", "FS ( L"]),
+            model_id: 0x20,
+            type_id: 0x02,
+            firmware_version: "11.03 ESC/POS".to_string(),
+            ..Self::tm_t88vi()
+        }
+    }
+
+    pub fn bixolon_srp350iii() -> Self {
+        Self {
+            id: "srp-350iii".to_string(),
+            manufacturer: "BIXOLON".to_string(),
+            model_name: "SRP-350III".to_string(),
+            dpi: 180,
+            paper_widths: vec![
+                PaperSpec { width_mm: 80.0, dots_per_line: 512 },
+                PaperSpec { width_mm: 58.0, dots_per_line: 360 },
+            ],
+            font_b: CellSize { width: 9, height: 17 },
+            font_c: Some(CellSize { width: 9, height: 24 }),
+            code_pages: vec![0, 1, 2, 3, 4, 5, 16, 17, 18, 19, 20, 21, 26, 30, 31, 41, 42, 255],
+            symbologies: strings(&["UPC-A", "UPC-E", "EAN13", "EAN8", "CODE39", "ITF", "CODABAR", "CODE93", "CODE128", "PDF417", "QR"]),
+            nv_graphics_bytes: 256 * 1024,
+            nv_user_memory_bytes: 0,
+            ignored_commands: strings(&["ESC 4", "ESC 5", "FS ( L"]),
+            model_id: 0x20,
+            type_id: 0x02,
+            firmware_version: "V01.00".to_string(),
+            ..Self::tm_t88vi()
+        }
+    }
+
+    pub fn citizen_cts310ii() -> Self {
+        Self {
+            id: "ct-s310ii".to_string(),
+            manufacturer: "CITIZEN".to_string(),
+            model_name: "CT-S310II".to_string(),
+            dpi: 203,
+            paper_widths: vec![
+                PaperSpec { width_mm: 80.0, dots_per_line: 576 },
+                PaperSpec { width_mm: 58.0, dots_per_line: 432 },
+            ],
+            font_b: CellSize { width: 8, height: 16 },
+            font_c: None,
+            code_pages: vec![0, 1, 2, 3, 4, 5, 16, 17, 18, 19, 255],
+            symbologies: strings(&["UPC-A", "UPC-E", "EAN13", "EAN8", "CODE39", "ITF", "CODABAR", "CODE93", "CODE128", "PDF417", "QR"]),
+            nv_graphics_bytes: 384 * 1024,
+            nv_user_memory_bytes: 0,
+            ignored_commands: strings(&["ESC 5", "FS ( L"]),
+            model_id: 0x20,
+            type_id: 0x02,
+            firmware_version: "1.00".to_string(),
+            ..Self::tm_t88vi()
+        }
+    }
+
+    pub fn star_tsp143iv() -> Self {
+        Self {
+            id: "tsp143iv".to_string(),
+            manufacturer: "STAR".to_string(),
+            model_name: "TSP143IV (ESC/POS mode)".to_string(),
+            dpi: 203,
+            paper_widths: vec![
+                PaperSpec { width_mm: 80.0, dots_per_line: 576 },
+                PaperSpec { width_mm: 58.0, dots_per_line: 416 },
+            ],
+            font_b: CellSize { width: 9, height: 24 },
+            font_c: None,
+            code_pages: vec![0, 1, 2, 3, 4, 5, 16, 17, 18, 19, 255],
+            symbologies: strings(&["UPC-A", "UPC-E", "EAN13", "EAN8", "CODE39", "ITF", "CODE93", "CODE128", "QR"]),
+            nv_graphics_bytes: 0,
+            nv_user_memory_bytes: 0,
+            ignored_commands: strings(&["ESC 4", "ESC 5", "GS :", "GS ^", "GS ( F", "GS FF", "FS ( L"]),
+            model_id: 0x20,
+            type_id: 0x02,
+            firmware_version: "1.0".to_string(),
+            ..Self::tm_t88vi()
+        }
+    }
+
+    /// The typical unbranded 58 mm kiosk / mobile printer: 203 dpi, 384-dot line.
+    pub fn generic_58mm() -> Self {
+        Self {
+            id: "generic-58".to_string(),
+            manufacturer: "Generic".to_string(),
+            model_name: "58mm 203dpi".to_string(),
+            dpi: 203,
+            paper_widths: vec![PaperSpec { width_mm: 58.0, dots_per_line: 384 }],
+            font_b: CellSize { width: 9, height: 17 },
+            font_c: None,
+            code_pages: Vec::new(),
+            symbologies: strings(&["UPC-A", "UPC-E", "EAN13", "EAN8", "CODE39", "ITF", "CODABAR", "CODE93", "CODE128", "QR"]),
+            nv_graphics_bytes: 64 * 1024,
+            nv_user_memory_bytes: 0,
+            ignored_commands: strings(&["GS ( F", "GS FF", "FS ( L"]),
+            model_id: 0x20,
+            type_id: 0x02,
+            firmware_version: "1.0".to_string(),
+            ..Self::tm_t88vi()
+        }
+    }
+
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            Self::tm_t88vi(),
+            Self::tm_t20iii(),
+            Self::tm_m30(),
+            Self::bixolon_srp350iii(),
+            Self::citizen_cts310ii(),
+            Self::star_tsp143iv(),
+            Self::generic_58mm(),
+        ]
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read profile {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("Invalid profile {}", path.display()))
+    }
+
+    /// Load every `*.json` profile in `dir`; unreadable files are skipped with a warning.
+    pub fn load_dir(dir: &Path) -> Vec<Self> {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut profiles = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                match Self::load_from_file(&path) {
+                    Ok(profile) => profiles.push(profile),
+                    Err(e) => warn!("Skipping printer profile: {:#}", e),
+                }
+            }
+        }
+        profiles
+    }
+
+    pub fn cell_size(&self, font: &Font) -> CellSize {
+        match font {
+            Font::FontA => self.font_a,
+            Font::FontB => self.font_b,
+            Font::FontC => self.font_c.unwrap_or(self.font_a),
+        }
+    }
+
+    /// Printable dots per line, if the model lists this paper width.
+    pub fn dots_per_line(&self, width_mm: f32) -> Option<u32> {
+        self.paper_widths
+            .iter()
+            .find(|spec| (spec.width_mm - width_mm).abs() < 0.05)
+            .map(|spec| spec.dots_per_line)
+    }
+
+    /// A roll of `width_mm` loaded in this model.
+    pub fn paper_width(&self, width_mm: f32) -> PaperWidth {
+        PaperWidth::new(width_mm, self.dpi).with_printable_dots(self.dots_per_line(width_mm))
+    }
+
+    /// Byte prefixes of `ignored_commands`, for the parser.
+    pub fn ignored_prefixes(&self) -> Vec<Vec<u8>> {
+        self.ignored_commands
+            .iter()
+            .filter_map(|notation| parse_command_notation(notation))
+            .collect()
+    }
+}
+
+/// Turn `ESC 4`, `GS ( L` or `GS 0x0C` into the bytes the command starts with.
+pub fn parse_command_notation(notation: &str) -> Option<Vec<u8>> {
+    notation
+        .split_whitespace()
+        .map(|token| match token {
+            "ESC" => Some(0x1B),
+            "GS" => Some(0x1D),
+            "FS" => Some(0x1C),
+            "DLE" => Some(0x10),
+            "FF" => Some(0x0C),
+            _ if token.starts_with("0x") => u8::from_str_radix(&token[2..], 16).ok(),
+            _ if token.len() == 1 => Some(token.as_bytes()[0]),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_c_falls_back_to_font_a_on_models_without_it() {
+        let t88 = PrinterProfile::tm_t88vi();
+        assert_eq!(t88.cell_size(&Font::FontA), CellSize { width: 12, height: 24 });
+        assert_eq!(t88.cell_size(&Font::FontB), CellSize { width: 9, height: 17 });
+        assert_eq!(t88.cell_size(&Font::FontC), t88.font_a);
+
+        let m30 = PrinterProfile::tm_m30();
+        assert_eq!(m30.cell_size(&Font::FontC), CellSize { width: 9, height: 17 });
+    }
+
+    #[test]
+    fn paper_width_uses_the_printable_line_of_the_model() {
+        let t20 = PrinterProfile::tm_t20iii();
+        assert_eq!(t20.dots_per_line(80.0), Some(576));
+        assert_eq!(t20.dots_per_line(58.0), Some(420));
+        assert_eq!(t20.dots_per_line(112.0), None);
+        let paper = t20.paper_width(58.0);
+        assert_eq!((paper.dpi, paper.printable_dots()), (203, 420));
+        assert_eq!(paper.chars_per_line(t20.font_a), 35);
+
+        let generic = PrinterProfile::generic_58mm();
+        assert_eq!(generic.paper_width(58.0).printable_dots(), 384);
+        assert!(generic.code_pages.is_empty());
+    }
+
+    #[test]
+    fn ignored_commands_become_byte_prefixes() {
+        assert_eq!(
+            PrinterProfile::tm_t20iii().ignored_prefixes(),
+            vec![
+                vec![0x1B, b'4'],
+                vec![0x1B, b'5'],
+                vec![0x1D, b'(', b'F'],
+                vec![0x1D, 0x0C],
+                vec![0x1C, b'(', b'L'],
+            ]
+        );
+        assert_eq!(parse_command_notation("GS 0x0C"), Some(vec![0x1D, 0x0C]));
+        assert_eq!(parse_command_notation("ESC 0xZZ"), None);
+        assert_eq!(parse_command_notation("ESC four"), None);
+    }
+
+    #[test]
+    fn builtin_ids_are_unique_and_profiles_round_trip_through_json() {
+        let profiles = PrinterProfile::builtin();
+        let mut ids: Vec<_> = profiles.iter().map(|profile| profile.id.as_str()).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), profiles.len());
+
+        let star = PrinterProfile::star_tsp143iv();
+        assert!(star.ignored_commands.iter().any(|command| command == "GS ^"));
+        let json = serde_json::to_string(&star).unwrap();
+        assert_eq!(serde_json::from_str::<PrinterProfile>(&json).unwrap(), star);
+    }
+}
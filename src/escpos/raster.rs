@@ -0,0 +1,333 @@
+//! Software rasterizer for the receipt buffer.
+//!
+//! Draws every `ReceiptLine` at printer resolution, one pixel per dot, so the
+//! preview, exports and tests all work from the same image.
+
+use crate::escpos::barcode;
+use crate::escpos::commands::{BarcodeSystem, Justification};
+use crate::escpos::font;
+use crate::escpos::printer::{PrinterState, ReceiptLine, TextLine};
+use crate::escpos::profile::CellSize;
+use image::{Rgb, RgbImage};
+use std::collections::HashMap;
+
+pub const PAPER: Rgb<u8> = Rgb([255, 255, 255]);
+pub const INK: Rgb<u8> = Rgb([0, 0, 0]);
+/// Cut marks are drawn in grey: they show where the cutter acted, not printed dots.
+pub const CUT_MARK: Rgb<u8> = Rgb([160, 160, 160]);
+
+/// Render the whole buffer of `state` into an image as wide as the paper.
+pub fn render(state: &PrinterState) -> RgbImage {
+    render_lines(state, state.get_buffer())
+}
+
+/// Render part of a buffer, e.g. one receipt, with the layout settings of `state`.
+pub fn render_lines(state: &PrinterState, lines: &[ReceiptLine]) -> RgbImage {
+    let height = lines.iter().map(|line| state.line_height_dots(line)).sum::<u32>().max(1);
+    let mut canvas = Canvas::new(state, height);
+    let mut top = 0;
+    for line in lines {
+        let height = state.line_height_dots(line);
+        match line {
+            ReceiptLine::Text(text) => canvas.draw_text_line(state, text, top),
+            ReceiptLine::Bitmap { width_px, height_px, data, justification } => {
+                canvas.draw_bitmap(*width_px, *height_px, data, justification, top)
+            }
+            ReceiptLine::Separator(_) => canvas.draw_cut_mark(top + height / 2),
+            ReceiptLine::Feed { .. } => {}
+            ReceiptLine::Barcode { system, data, height, module_width, hri_position, justification } => {
+                let bars = BarSpec { height: *height, module_width: *module_width, hri_position: *hri_position };
+                canvas.draw_barcode(state, *system, data, &bars, justification, top)
+            }
+            ReceiptLine::QrCode { data, model, module_size, error_correction, justification } => {
+                canvas.draw_qr_code(data, *model, *module_size, *error_correction, justification, top)
+            }
+        }
+        top += height;
+    }
+    canvas.image
+}
+
+/// The rasterized band of every graphic line (bitmap, barcode, QR code) in
+/// `lines`, full paper width, in buffer order.
+pub fn graphic_bands(state: &PrinterState, lines: &[ReceiptLine]) -> Vec<RgbImage> {
+    if !lines.iter().any(ReceiptLine::is_graphic) {
+        return Vec::new();
+    }
+    let image = render_lines(state, lines);
+    let mut bands = Vec::new();
+    let mut top = 0;
+    for line in lines {
+        let rows = state.line_height_dots(line);
+        if line.is_graphic() {
+            bands.push(image::imageops::crop_imm(&image, 0, top, image.width(), rows).to_image());
+        }
+        top += rows;
+    }
+    bands
+}
+
+/// Left edge, in dots from the paper edge, of a block `width` dots wide placed
+/// in the printable area.
+pub fn justify(state: &PrinterState, width: u32, justification: &Justification) -> u32 {
+    align(state.get_margin_dots(), state.get_printing_width_dots(), width, justification)
+}
+
+/// Width of a text line in dots, including character magnification.
+pub fn text_width(state: &PrinterState, line: &TextLine) -> u32 {
+    let cell = state.profile.cell_size(&line.font);
+    line.text.chars().count() as u32 * cell.width * line.width_mult.max(1) as u32
+}
+
+fn align(left: u32, printable: u32, width: u32, justification: &Justification) -> u32 {
+    let free = printable.saturating_sub(width);
+    left + match justification {
+        Justification::Left => 0,
+        Justification::Center => free / 2,
+        Justification::Right => free,
+    }
+}
+
+struct BarSpec {
+    height: u32,
+    module_width: u32,
+    hri_position: u8,
+}
+
+/// Character attributes that change how a glyph is drawn.
+#[derive(Clone, Copy, Default)]
+struct GlyphStyle {
+    width_mult: u32,
+    height_mult: u32,
+    emphasis: bool,
+    italic: bool,
+    reverse: bool,
+    underline: bool,
+}
+
+struct Canvas {
+    image: RgbImage,
+    left: u32,
+    printable: u32,
+    glyphs: HashMap<(char, u32, u32), Vec<bool>>,
+}
+
+impl Canvas {
+    fn new(state: &PrinterState, height: u32) -> Self {
+        let width = state.get_paper_width_dots();
+        Self {
+            image: RgbImage::from_pixel(width, height, PAPER),
+            left: state.get_margin_dots(),
+            printable: state.get_printing_width_dots(),
+            glyphs: HashMap::new(),
+        }
+    }
+
+    /// Set one dot, clipped to the printable area.
+    fn dot(&mut self, x: u32, y: u32, color: Rgb<u8>) {
+        if x >= self.left && x < self.left + self.printable && y < self.image.height() {
+            self.image.put_pixel(x, y, color);
+        }
+    }
+
+    fn fill(&mut self, x: u32, y: u32, width: u32, height: u32, color: Rgb<u8>) {
+        for dy in y..y + height {
+            for dx in x..x + width {
+                self.dot(dx, dy, color);
+            }
+        }
+    }
+
+    fn align(&self, width: u32, justification: &Justification) -> u32 {
+        align(self.left, self.printable, width, justification)
+    }
+
+    fn glyph(&mut self, ch: char, cell: CellSize) -> &[bool] {
+        self.glyphs
+            .entry((ch, cell.width, cell.height))
+            .or_insert_with(|| font::glyph_cell(ch, cell))
+    }
+
+    fn draw_glyph(&mut self, ch: char, cell: CellSize, style: GlyphStyle, x: u32, y: u32) {
+        let (wm, hm) = (style.width_mult.max(1), style.height_mult.max(1));
+        let (width, height) = (cell.width * wm, cell.height * hm);
+        let dots = self.glyph(ch, cell).to_vec();
+        let lit = |cx: i64, cy: u32| {
+            (0..width as i64).contains(&cx) && dots[((cy / hm) * cell.width + cx as u32 / wm) as usize]
+        };
+
+        let (ink, paper) = if style.reverse { (PAPER, INK) } else { (INK, PAPER) };
+        if style.reverse {
+            self.fill(x, y, width, height, paper);
+        }
+        for cy in 0..height {
+            // Italic slants the top of the cell right by a sixth of its height
+            let shear = if style.italic { ((height - 1 - cy) / 6) as i64 } else { 0 };
+            for cx in 0..width {
+                let sx = cx as i64 - shear;
+                // Emphasis prints every dot a second time, one dot to the right
+                if lit(sx, cy) || (style.emphasis && lit(sx - 1, cy)) {
+                    self.dot(x + cx, y + cy, ink);
+                }
+            }
+        }
+        if style.underline && !style.reverse {
+            self.fill(x, y + height - 1, width, 1, INK);
+        }
+    }
+
+    fn draw_string(&mut self, text: &str, cell: CellSize, style: GlyphStyle, x: u32, y: u32) {
+        let advance = cell.width * style.width_mult.max(1);
+        for (i, ch) in text.chars().enumerate() {
+            self.draw_glyph(ch, cell, style, x + i as u32 * advance, y);
+        }
+    }
+
+    fn draw_text_line(&mut self, state: &PrinterState, line: &TextLine, top: u32) {
+        let cell = state.profile.cell_size(&line.font);
+        let style = GlyphStyle {
+            width_mult: line.width_mult as u32,
+            height_mult: line.height_mult as u32,
+            emphasis: line.emphasis,
+            italic: line.italic,
+            reverse: line.reverse,
+            underline: line.underline,
+        };
+        let x = self.align(text_width(state, line), &line.justification);
+        self.draw_string(&line.text, cell, style, x, top);
+    }
+
+    fn draw_bitmap(&mut self, width: u32, height: u32, data: &[u8], justification: &Justification, top: u32) {
+        let x = self.align(width, justification);
+        let row_bytes = width.div_ceil(8);
+        for y in 0..height {
+            for dx in 0..width {
+                let byte = data.get((y * row_bytes + dx / 8) as usize).copied().unwrap_or(0);
+                if byte >> (7 - dx % 8) & 1 == 1 {
+                    self.dot(x + dx, top + y, INK);
+                }
+            }
+        }
+    }
+
+    /// Dashed line across the full paper width where the cutter acted.
+    fn draw_cut_mark(&mut self, y: u32) {
+        if y >= self.image.height() {
+            return;
+        }
+        for x in (0..self.image.width()).filter(|x| x % 12 < 8) {
+            self.image.put_pixel(x, y, CUT_MARK);
+        }
+    }
+
+    fn draw_barcode(
+        &mut self,
+        state: &PrinterState,
+        system: BarcodeSystem,
+        data: &[u8],
+        bars: &BarSpec,
+        justification: &Justification,
+        top: u32,
+    ) {
+        let cell = state.profile.font_a;
+        let text = barcode::hri_text(system, data);
+        let text_width = text.chars().count() as u32 * cell.width;
+        let modules = barcode::encode(system, data);
+        let width = match &modules {
+            Some(modules) => modules.len() as u32 * bars.module_width,
+            None => text_width.max(cell.width * 8),
+        };
+        let x = self.align(width, justification);
+        let hri_x = x + width.saturating_sub(text_width) / 2;
+        let style = GlyphStyle { width_mult: 1, height_mult: 1, ..Default::default() };
+
+        let mut y = top;
+        if bars.hri_position & 0x01 != 0 {
+            self.draw_string(&text, cell, style, hri_x, y);
+            y += cell.height;
+        }
+        match modules {
+            Some(modules) => {
+                for (i, _) in modules.iter().enumerate().filter(|(_, &bar)| bar) {
+                    self.fill(x + i as u32 * bars.module_width, y, bars.module_width, bars.height, INK);
+                }
+            }
+            None => {
+                // No encoder for this system: outline the symbol area and hatch it
+                for dy in 0..bars.height {
+                    for dx in 0..width {
+                        let border = dx == 0 || dy == 0 || dx == width - 1 || dy == bars.height - 1;
+                        if border || (dx + dy) % 8 == 0 {
+                            self.dot(x + dx, y + dy, INK);
+                        }
+                    }
+                }
+            }
+        }
+        y += bars.height;
+        if bars.hri_position & 0x02 != 0 {
+            self.draw_string(&text, cell, style, hri_x, y);
+        }
+    }
+
+    fn draw_qr_code(
+        &mut self,
+        data: &[u8],
+        model: u8,
+        module_size: u32,
+        error_correction: u8,
+        justification: &Justification,
+        top: u32,
+    ) {
+        let Some((size, modules)) = barcode::qr_symbol(data, model, error_correction) else {
+            return;
+        };
+        let x = self.align(size * module_size, justification);
+        for (i, _) in modules.iter().enumerate().filter(|(_, &dark)| dark) {
+            let (mx, my) = (i as u32 % size, i as u32 / size);
+            self.fill(x + mx * module_size, top + my * module_size, module_size, module_size, INK);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::parser::EscPosParser;
+
+    fn print(data: &[u8]) -> PrinterState {
+        let mut state = PrinterState::new();
+        for parsed in EscPosParser::new().parse_stream(data).unwrap() {
+            state.process_parsed(&parsed);
+        }
+        state
+    }
+
+    /// Width and height of the box around every inked pixel.
+    fn ink_box(image: &RgbImage) -> (u32, u32) {
+        let inked: Vec<(u32, u32)> =
+            image.enumerate_pixels().filter(|(_, _, pixel)| **pixel == INK).map(|(x, y, _)| (x, y)).collect();
+        let extent = |values: Vec<u32>| values.iter().max().unwrap() - values.iter().min().unwrap() + 1;
+        (extent(inked.iter().map(|p| p.0).collect()), extent(inked.iter().map(|p| p.1).collect()))
+    }
+
+    #[test]
+    fn text_lines_are_one_cell_high_at_printer_resolution() {
+        let state = print(b"ABC\n");
+        let image = render(&state);
+        assert_eq!(image.width(), state.get_paper_width_dots());
+        assert_eq!(image.height(), state.calculate_total_height());
+        let (width, height) = ink_box(&image);
+        let cell = state.profile.cell_size(&state.current_font);
+        assert!(width <= 3 * cell.width && width > 2 * cell.width, "{} dots wide", width);
+        assert!(height <= cell.height, "{} dots high", height);
+    }
+
+    #[test]
+    fn double_width_and_height_scale_every_dot() {
+        let single = ink_box(&render(&print(b"H\n")));
+        assert_eq!(ink_box(&render(&print(b"\x1d!\x10H\n"))), (single.0 * 2, single.1));
+        assert_eq!(ink_box(&render(&print(b"\x1d!\x01H\n"))), (single.0, single.1 * 2));
+        assert_eq!(ink_box(&render(&print(b"\x1d!\x11H\n"))), (single.0 * 2, single.1 * 2));
+    }
+}
@@ -0,0 +1,321 @@
+//! HTML and SVG export with real text.
+//!
+//! One CSS pixel / SVG unit is one printer dot. Each buffer line becomes one
+//! element on its own output line, so receipts diff cleanly as text. Graphics
+//! are embedded as PNG data URIs cut from the rasterized receipt.
+
+use crate::escpos::barcode;
+use crate::escpos::commands::Justification;
+use crate::escpos::printer::{PrinterState, ReceiptLine, TextLine};
+use crate::escpos::raster;
+use crate::export::base64;
+use anyhow::{Context, Result};
+use image::{DynamicImage, ImageOutputFormat, RgbImage};
+use std::fmt::Write as _;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Monospace fonts advance every glyph by about 0.6 em.
+const MONOSPACE_ADVANCE: f32 = 0.6;
+
+const STYLE: &str = "\
+body { background: #e8e8e8; margin: 16px; }
+.paper { background: #fff; margin: 0 auto; font-family: 'Courier New', Courier, monospace; color: #000; }
+.line { white-space: pre; overflow: hidden; }
+.line.center { text-align: center; }
+.line.right { text-align: right; }
+.line span { display: inline-block; transform-origin: top left; }
+.b { font-weight: bold; }
+.u { text-decoration: underline; }
+.i { font-style: italic; }
+.r { background: #000; color: #fff; }
+.graphic { display: block; image-rendering: pixelated; }
+.cut { border-top: 1px dashed #a0a0a0; box-sizing: border-box; }
+";
+
+/// A self-contained HTML document for `lines`.
+pub fn render_html(state: &PrinterState, lines: &[ReceiptLine]) -> String {
+    let mut graphics = raster::graphic_bands(state, lines).into_iter();
+    let margin = state.get_margin_dots();
+    let mut html = String::new();
+    let _ = writeln!(html, "<!DOCTYPE html>");
+    let _ = writeln!(html, "<html>\n<head>\n<meta charset=\"utf-8\">");
+    let _ = writeln!(html, "<title>{} receipt</title>", escape(&state.profile.model_name));
+    let _ = writeln!(html, "<style>\n{}</style>\n</head>\n<body>", STYLE);
+    let _ = writeln!(
+        html,
+        "<div class=\"paper\" style=\"width: {}px; padding: 0 {}px; box-sizing: border-box;\" data-profile=\"{}\" data-paper=\"{}\">",
+        state.get_paper_width_dots(),
+        margin,
+        escape(&state.profile.id),
+        state.paper_width
+    );
+
+    for line in lines {
+        let height = state.line_height_dots(line);
+        match line {
+            ReceiptLine::Text(text) => {
+                let _ = writeln!(
+                    html,
+                    "<div class=\"line{}\" style=\"height: {}px;\">{}</div>",
+                    justification_class(&text.justification),
+                    height,
+                    text_span(state, text)
+                );
+            }
+            ReceiptLine::Feed { .. } => {
+                let _ = writeln!(html, "<div class=\"feed\" style=\"height: {}px;\"></div>", height);
+            }
+            ReceiptLine::Separator(_) => {
+                let _ = writeln!(
+                    html,
+                    "<div class=\"cut\" style=\"height: {}px; margin: 0 -{}px;\"></div>",
+                    height, margin
+                );
+            }
+            line => {
+                let band = graphics.next().unwrap_or_default();
+                let _ = writeln!(
+                    html,
+                    "<img class=\"graphic\" style=\"margin: 0 -{}px;\" width=\"{}\" height=\"{}\" {} src=\"{}\">",
+                    margin,
+                    band.width(),
+                    band.height(),
+                    graphic_attributes(line),
+                    png_data_uri(&band)
+                );
+            }
+        }
+    }
+
+    let _ = writeln!(html, "</div>\n</body>\n</html>");
+    html
+}
+
+/// A standalone SVG image of `lines`, with text as `<text>` elements sized to
+/// the exact printer columns.
+pub fn render_svg(state: &PrinterState, lines: &[ReceiptLine]) -> String {
+    let mut graphics = raster::graphic_bands(state, lines).into_iter();
+    let width = state.get_paper_width_dots();
+    let height = lines.iter().map(|line| state.line_height_dots(line)).sum::<u32>().max(1);
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" \
+         xml:space=\"preserve\" font-family=\"'Courier New', Courier, monospace\">",
+        w = width,
+        h = height
+    );
+    let _ = writeln!(svg, "<rect width=\"{}\" height=\"{}\" fill=\"#fff\"/>", width, height);
+
+    let mut top = 0;
+    for line in lines {
+        let rows = state.line_height_dots(line);
+        match line {
+            ReceiptLine::Text(text) if !text.text.is_empty() => svg_text(&mut svg, state, text, top),
+            ReceiptLine::Text(_) | ReceiptLine::Feed { .. } => {}
+            ReceiptLine::Separator(_) => {
+                let y = top + rows / 2;
+                let _ = writeln!(
+                    svg,
+                    "<line class=\"cut\" x1=\"0\" y1=\"{y}\" x2=\"{}\" y2=\"{y}\" stroke=\"#a0a0a0\" stroke-dasharray=\"8 4\"/>",
+                    width,
+                    y = y
+                );
+            }
+            line => {
+                let band = graphics.next().unwrap_or_default();
+                let _ = writeln!(
+                    svg,
+                    "<image x=\"0\" y=\"{}\" width=\"{}\" height=\"{}\" {} style=\"image-rendering: pixelated\" href=\"{}\"/>",
+                    top,
+                    band.width(),
+                    band.height(),
+                    graphic_attributes(line),
+                    png_data_uri(&band)
+                );
+            }
+        }
+        top += rows;
+    }
+    let _ = writeln!(svg, "</svg>");
+    svg
+}
+
+pub fn export_html(state: &PrinterState, path: &Path) -> Result<()> {
+    std::fs::write(path, render_html(state, state.get_buffer()))
+        .with_context(|| format!("Failed to write HTML to {}", path.display()))
+}
+
+pub fn export_svg(state: &PrinterState, path: &Path) -> Result<()> {
+    std::fs::write(path, render_svg(state, state.get_buffer()))
+        .with_context(|| format!("Failed to write SVG to {}", path.display()))
+}
+
+fn justification_class(justification: &Justification) -> &'static str {
+    match justification {
+        Justification::Left => "",
+        Justification::Center => " center",
+        Justification::Right => " right",
+    }
+}
+
+/// ` class="..."` for the character styles of `line`, or nothing for plain text.
+fn style_classes(line: &TextLine) -> String {
+    let classes: Vec<&str> = [(line.emphasis, "b"), (line.underline, "u"), (line.italic, "i"), (line.reverse, "r")]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, class)| *class)
+        .collect();
+    if classes.is_empty() {
+        String::new()
+    } else {
+        format!(" class=\"{}\"", classes.join(" "))
+    }
+}
+
+/// A span exactly as wide as the printed characters; double height without
+/// double width is a vertical stretch.
+fn text_span(state: &PrinterState, line: &TextLine) -> String {
+    if line.text.is_empty() {
+        return String::new();
+    }
+    let cell = state.profile.cell_size(&line.font);
+    let (wm, hm) = (line.width_mult.max(1) as u32, line.height_mult.max(1) as u32);
+    let font_size = (cell.width * wm) as f32 / MONOSPACE_ADVANCE;
+    let mut style = format!(
+        "width: {}px; font-size: {:.2}px; line-height: {}px;",
+        raster::text_width(state, line),
+        font_size,
+        cell.height * wm
+    );
+    if hm != wm {
+        let _ = write!(style, " transform: scaleY({:.3});", hm as f32 / wm as f32);
+    }
+    format!(
+        "<span{} style=\"{}\" data-font=\"{:?}\" data-size=\"{}x{}\">{}</span>",
+        style_classes(line),
+        style,
+        line.font,
+        wm,
+        hm,
+        escape(&line.text)
+    )
+}
+
+fn svg_text(svg: &mut String, state: &PrinterState, line: &TextLine, top: u32) {
+    let cell = state.profile.cell_size(&line.font);
+    let hm = line.height_mult.max(1) as u32;
+    let width = raster::text_width(state, line);
+    let height = cell.height * hm;
+    let x = raster::justify(state, width, &line.justification);
+
+    let mut attributes = String::new();
+    if line.emphasis {
+        attributes.push_str(" font-weight=\"bold\"");
+    }
+    if line.italic {
+        attributes.push_str(" font-style=\"italic\"");
+    }
+    if line.underline {
+        attributes.push_str(" text-decoration=\"underline\"");
+    }
+    if line.reverse {
+        let _ = writeln!(svg, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#000\"/>", x, top, width, height);
+        attributes.push_str(" fill=\"#fff\"");
+    }
+    let _ = writeln!(
+        svg,
+        "<text x=\"{}\" y=\"{:.1}\" font-size=\"{:.1}\" textLength=\"{}\" lengthAdjust=\"spacingAndGlyphs\"{}>{}</text>",
+        x,
+        top as f32 + height as f32 * 0.8,
+        height as f32 * 0.9,
+        width,
+        attributes,
+        escape(&line.text)
+    );
+}
+
+/// Describe a graphic line for `alt` text and data attributes.
+fn graphic_attributes(line: &ReceiptLine) -> String {
+    match line {
+        ReceiptLine::Bitmap { width_px, height_px, .. } => {
+            format!("data-kind=\"bitmap\" alt=\"bitmap {}x{}\"", width_px, height_px)
+        }
+        ReceiptLine::Barcode { system, data, .. } => {
+            let value = escape(&barcode::hri_text(*system, data));
+            format!(
+                "data-kind=\"barcode\" data-symbology=\"{}\" data-value=\"{}\" alt=\"{} {}\"",
+                system.name(),
+                value,
+                system.name(),
+                value
+            )
+        }
+        ReceiptLine::QrCode { data, .. } => {
+            let value = escape(&String::from_utf8_lossy(data));
+            format!("data-kind=\"qr\" data-value=\"{}\" alt=\"QR {}\"", value, value)
+        }
+        _ => String::new(),
+    }
+}
+
+fn png_data_uri(image: &RgbImage) -> String {
+    let gray = DynamicImage::ImageRgb8(image.clone()).into_luma8();
+    let mut png = Cursor::new(Vec::new());
+    // Encoding an in-memory image to PNG cannot fail for valid dimensions
+    let _ = DynamicImage::ImageLuma8(gray).write_to(&mut png, ImageOutputFormat::Png);
+    format!("data:image/png;base64,{}", base64::encode(png.get_ref()))
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::parser::EscPosParser;
+
+    /// Text and a QR code whose contents need escaping.
+    fn printed() -> PrinterState {
+        let mut data = b"a<b & \"c\"\n".to_vec();
+        let qr = b"<&\">";
+        data.extend_from_slice(&[0x1D, b'(', b'k', qr.len() as u8 + 3, 0, 0x31, 0x50, 0x30]);
+        data.extend_from_slice(qr);
+        data.extend_from_slice(&[0x1D, b'(', b'k', 3, 0, 0x31, 0x51, 0x30]);
+        let mut state = PrinterState::new();
+        for parsed in EscPosParser::new().parse_stream(&data).unwrap() {
+            state.process_parsed(&parsed);
+        }
+        state
+    }
+
+    #[test]
+    fn html_escapes_text_and_qr_data() {
+        let state = printed();
+        let html = render_html(&state, state.get_buffer());
+        assert!(html.contains(">a&lt;b &amp; &quot;c&quot;</span>"), "{}", html);
+        assert!(html.contains("data-value=\"&lt;&amp;&quot;&gt;\""));
+        assert!(!html.contains("a<b") && !html.contains("\"<&"));
+    }
+
+    #[test]
+    fn svg_escapes_text_and_qr_data() {
+        let state = printed();
+        let svg = render_svg(&state, state.get_buffer());
+        assert!(svg.contains(">a&lt;b &amp; &quot;c&quot;</text>"), "{}", svg);
+        assert!(svg.contains("data-value=\"&lt;&amp;&quot;&gt;\""));
+        assert!(!svg.contains("a<b") && !svg.contains("\"<&"));
+    }
+}
@@ -0,0 +1,106 @@
+pub mod base64;
+pub mod html;
+pub mod json;
+pub mod pdf;
+pub mod text;
+
+pub use html::*;
+pub use json::*;
+pub use pdf::*;
+pub use text::*;
+
+use crate::escpos::printer::{PrinterState, ReceiptLine};
+use crate::escpos::raster;
+use anyhow::{Context, Result};
+use image::imageops::{self, FilterType};
+use image::ImageFormat;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Output formats for a set of receipt lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// PNG with every dot enlarged to `scale` x `scale` pixels
+    Png { scale: u32 },
+    Pdf(PdfScope),
+    Html,
+    Svg,
+    Text,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png { .. } => "png",
+            ExportFormat::Pdf(_) => "pdf",
+            ExportFormat::Html => "html",
+            ExportFormat::Svg => "svg",
+            ExportFormat::Text => "txt",
+        }
+    }
+}
+
+/// Write `lines`, e.g. a single job, with the layout settings of `state`.
+pub fn export_lines(state: &PrinterState, lines: &[ReceiptLine], path: &Path, format: ExportFormat) -> Result<()> {
+    let data = encode_lines(state, lines, format)?;
+    std::fs::write(path, data).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// `lines` as the contents of a file in `format`.
+pub fn encode_lines(state: &PrinterState, lines: &[ReceiptLine], format: ExportFormat) -> Result<Vec<u8>> {
+    Ok(match format {
+        ExportFormat::Png { scale } => {
+            let image = raster::render_lines(state, lines);
+            let image = match scale {
+                0 | 1 => image,
+                _ => imageops::resize(&image, image.width() * scale, image.height() * scale, FilterType::Nearest),
+            };
+            let mut png = Vec::new();
+            image
+                .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                .context("Failed to encode PNG")?;
+            png
+        }
+        ExportFormat::Pdf(scope) => render_pdf_lines(state, lines, scope),
+        ExportFormat::Html => render_html(state, lines).into_bytes(),
+        ExportFormat::Svg => render_svg(state, lines).into_bytes(),
+        ExportFormat::Text => render_text(state, lines).into_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{render_bytes, RenderOptions};
+
+    fn png_size(profile: &str, width_mm: f32, scale: u32) -> ((u32, u32), u32) {
+        let options = RenderOptions {
+            profile: Some(profile.to_string()),
+            paper_width_mm: Some(width_mm),
+            ..Default::default()
+        };
+        let job = render_bytes(b"TOTAL  12.50\n\x1bd\x03\x1dV\x00", "test.bin", &options).unwrap();
+        let state = &job.state.printer_state;
+        let height: u32 = job.lines().iter().map(|line| state.line_height_dots(line)).sum();
+        let png = encode_lines(state, job.lines(), ExportFormat::Png { scale }).unwrap();
+        let image = image::load_from_memory_with_format(&png, ImageFormat::Png).unwrap();
+        ((image.width(), image.height()), height)
+    }
+
+    #[test]
+    fn png_is_one_pixel_per_dot_of_the_paper() {
+        // 80 mm at 180 dpi and 80 and 58 mm at 203 dpi
+        for (profile, width_mm, width_dots) in [("tm-t88vi", 80.0, 567), ("tm-t20iii", 80.0, 639), ("tm-t20iii", 58.0, 464)] {
+            let ((width, height), raster_height) = png_size(profile, width_mm, 1);
+            assert_eq!(width, width_dots, "{} {} mm", profile, width_mm);
+            assert_eq!(height, raster_height, "{} {} mm", profile, width_mm);
+            assert!(height > 0);
+        }
+    }
+
+    #[test]
+    fn png_scale_enlarges_every_dot() {
+        let ((width, height), raster_height) = png_size("tm-t20iii", 58.0, 3);
+        assert_eq!((width, height), (464 * 3, raster_height * 3));
+    }
+}
@@ -0,0 +1,337 @@
+//! PDF export at the physical size of the paper.
+//!
+//! Every receipt becomes one page as wide as the roll and as long as the
+//! printed length. Text is set in the standard Courier fonts scaled to the
+//! printer's character cells, so it stays selectable and searchable; bitmaps,
+//! barcodes and QR codes are embedded as 1-bit images cut from the rasterized
+//! receipt.
+
+use crate::escpos::printer::{PrinterState, ReceiptLine, TextLine};
+use crate::escpos::raster;
+use anyhow::{Context, Result};
+use image::RgbImage;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Which part of the buffer to export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdfScope {
+    /// The most recent receipt only
+    LastReceipt,
+    /// Every receipt in the buffer, one page per cut
+    Session,
+}
+
+const MM_PER_INCH: f32 = 25.4;
+const POINTS_PER_INCH: f32 = 72.0;
+/// Courier advances every glyph by 0.6 em.
+const COURIER_ADVANCE: f32 = 0.6;
+
+/// Regular, bold, oblique and bold oblique, indexed by `emphasis as usize + 2 * italic as usize`.
+const FONTS: [&str; 4] = ["Courier", "Courier-Bold", "Courier-Oblique", "Courier-BoldOblique"];
+
+pub fn render_pdf(state: &PrinterState, scope: PdfScope) -> Vec<u8> {
+    render_pdf_lines(state, state.get_buffer(), scope)
+}
+
+/// A PDF of `lines`, e.g. one job, with the layout settings of `state`.
+pub fn render_pdf_lines(state: &PrinterState, lines: &[ReceiptLine], scope: PdfScope) -> Vec<u8> {
+    let mut receipts = ReceiptLine::split_receipts(lines);
+    if scope == PdfScope::LastReceipt && receipts.len() > 1 {
+        receipts.drain(..receipts.len() - 1);
+    }
+    if receipts.is_empty() {
+        receipts.push(&[]);
+    }
+
+    let mut pdf = PdfWriter::default();
+    let catalog = pdf.add(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    let pages = pdf.add(Vec::new());
+    let fonts: Vec<usize> = FONTS
+        .iter()
+        .map(|name| {
+            pdf.add(
+                format!("<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>", name)
+                    .into_bytes(),
+            )
+        })
+        .collect();
+    let font_resources: String = fonts
+        .iter()
+        .enumerate()
+        .map(|(i, id)| format!("/F{} {} 0 R ", i, id))
+        .collect();
+
+    let mut kids = Vec::new();
+    for lines in receipts {
+        let page = PageBuilder::new(state, lines);
+        let content = page.content();
+        let mut xobjects = String::new();
+        for (i, image) in page.images.iter().enumerate() {
+            let id = pdf.add(image_object(image));
+            let _ = write!(xobjects, "/Im{} {} 0 R ", i, id);
+        }
+        let content_id = pdf.add(stream_object("", content.as_bytes()));
+        let page_id = pdf.add(
+            format!(
+                "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {:.2} {:.2}] /Contents {} 0 R \
+                 /Resources << /Font << {}>> /XObject << {}>> >> >>",
+                pages, page.width, page.height, content_id, font_resources, xobjects
+            )
+            .into_bytes(),
+        );
+        kids.push(format!("{} 0 R", page_id));
+    }
+    pdf.set(
+        pages,
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), kids.len()).into_bytes(),
+    );
+    let info = pdf.add(
+        format!(
+            "<< /Title ({}) /Producer (ESC/POS Emulator) /CreationDate (D:{}) >>",
+            pdf_string(&format!("{} receipt", state.profile.model_name)),
+            chrono::Local::now().format("%Y%m%d%H%M%S")
+        )
+        .into_bytes(),
+    );
+    pdf.finish(catalog, info)
+}
+
+/// Write the receipt buffer as a PDF with the paper's physical dimensions.
+pub fn export_pdf(state: &PrinterState, path: &Path, scope: PdfScope) -> Result<()> {
+    std::fs::write(path, render_pdf(state, scope))
+        .with_context(|| format!("Failed to write PDF to {}", path.display()))
+}
+
+/// Drawing operators and images of one page, in points with the origin at the
+/// bottom left as PDF expects.
+struct PageBuilder<'a> {
+    state: &'a PrinterState,
+    lines: &'a [ReceiptLine],
+    /// Points per printer dot
+    scale: f32,
+    width: f32,
+    height: f32,
+    images: Vec<RgbImage>,
+}
+
+impl<'a> PageBuilder<'a> {
+    fn new(state: &'a PrinterState, lines: &'a [ReceiptLine]) -> Self {
+        let scale = POINTS_PER_INCH / state.dpi as f32;
+        let height_dots = lines.iter().map(|line| state.line_height_dots(line)).sum::<u32>().max(1);
+        Self {
+            state,
+            lines,
+            scale,
+            width: state.paper_width.width_mm / MM_PER_INCH * POINTS_PER_INCH,
+            height: height_dots as f32 * scale,
+            // Graphics come from the rasterizer so they match the preview dot for dot
+            images: raster::graphic_bands(state, lines),
+        }
+    }
+
+    /// PDF y coordinate of a position `dots` below the top of the page.
+    fn y(&self, dots: f32) -> f32 {
+        self.height - dots * self.scale
+    }
+
+    fn content(&self) -> String {
+        let mut ops = String::new();
+        let mut top = 0;
+        let mut image_index = 0;
+        for line in self.lines {
+            let rows = self.state.line_height_dots(line);
+            match line {
+                ReceiptLine::Text(text) if !text.text.is_empty() => self.text_ops(&mut ops, text, top),
+                line if line.is_graphic() => {
+                    let _ = writeln!(
+                        ops,
+                        "q {:.3} 0 0 {:.3} 0 {:.3} cm /Im{} Do Q",
+                        self.width,
+                        rows as f32 * self.scale,
+                        self.y((top + rows) as f32),
+                        image_index
+                    );
+                    image_index += 1;
+                }
+                ReceiptLine::Separator(_) => {
+                    let y = self.y(top as f32 + rows as f32 / 2.0);
+                    let _ = writeln!(
+                        ops,
+                        "q 0.63 G 0.5 w [3 2] 0 d 0 {:.3} m {:.3} {:.3} l S Q",
+                        y, self.width, y
+                    );
+                }
+                _ => {}
+            }
+            top += rows;
+        }
+        ops
+    }
+
+    fn text_ops(&self, ops: &mut String, line: &TextLine, top: u32) {
+        let cell = self.state.profile.cell_size(&line.font);
+        let (wm, hm) = (line.width_mult.max(1) as u32, line.height_mult.max(1) as u32);
+        let char_width = (cell.width * wm) as f32 * self.scale;
+        let char_height = (cell.height * hm) as f32 * self.scale;
+        let text_width = raster::text_width(self.state, line) as f32 * self.scale;
+        let x = raster::justify(self.state, raster::text_width(self.state, line), &line.justification) as f32 * self.scale;
+        let cell_bottom = self.y((top + cell.height * hm) as f32);
+
+        if line.reverse {
+            let _ = writeln!(ops, "0 g {:.3} {:.3} {:.3} {:.3} re f", x, cell_bottom, text_width, char_height);
+        }
+        let font = line.emphasis as usize + 2 * line.italic as usize;
+        let _ = writeln!(
+            ops,
+            "BT {} g /F{} 1 Tf {:.3} 0 0 {:.3} {:.3} {:.3} Tm ({}) Tj ET",
+            if line.reverse { 1 } else { 0 },
+            font,
+            char_width / COURIER_ADVANCE,
+            char_height * 1.1,
+            x,
+            cell_bottom + char_height * 0.22,
+            pdf_string(&line.text)
+        );
+        if line.underline && !line.reverse {
+            let _ = writeln!(ops, "0 g {:.3} {:.3} {:.3} {:.3} re f", x, cell_bottom, text_width, self.scale);
+        }
+    }
+}
+
+/// A PDF literal string body in WinAnsi encoding; characters outside Latin-1
+/// become `?`.
+fn pdf_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            ' '..='~' => out.push(ch),
+            '\u{A0}'..='\u{FF}' => {
+                let _ = write!(out, "\\{:03o}", ch as u32);
+            }
+            _ => out.push('?'),
+        }
+    }
+    out
+}
+
+/// A 1-bit grey image XObject; dark pixels print black.
+fn image_object(image: &RgbImage) -> Vec<u8> {
+    let row_bytes = image.width().div_ceil(8) as usize;
+    let mut data = vec![0u8; row_bytes * image.height() as usize];
+    for (x, y, pixel) in image.enumerate_pixels() {
+        // DeviceGray 1-bit: set bits are white
+        if pixel.0.iter().map(|&c| c as u32).sum::<u32>() > 3 * 128 {
+            data[y as usize * row_bytes + x as usize / 8] |= 0x80 >> (x % 8);
+        }
+    }
+    stream_object(
+        &format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray /BitsPerComponent 1",
+            image.width(),
+            image.height()
+        ),
+        &data,
+    )
+}
+
+fn stream_object(dict: &str, data: &[u8]) -> Vec<u8> {
+    let mut object = format!("<< {} /Length {} >>\nstream\n", dict, data.len()).into_bytes();
+    object.extend_from_slice(data);
+    object.extend_from_slice(b"\nendstream");
+    object
+}
+
+/// Collects numbered objects and writes them with a cross-reference table.
+#[derive(Default)]
+struct PdfWriter {
+    objects: Vec<Vec<u8>>,
+}
+
+impl PdfWriter {
+    /// Add an object and return its number.
+    fn add(&mut self, body: Vec<u8>) -> usize {
+        self.objects.push(body);
+        self.objects.len()
+    }
+
+    fn set(&mut self, id: usize, body: Vec<u8>) {
+        self.objects[id - 1] = body;
+    }
+
+    fn finish(self, root: usize, info: usize) -> Vec<u8> {
+        let mut out = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::with_capacity(self.objects.len());
+        for (i, body) in self.objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            out.extend_from_slice(body);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = out.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(table, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            table,
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.objects.len() + 1,
+            root,
+            info,
+            xref
+        );
+        out.extend_from_slice(table.as_bytes());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::parser::EscPosParser;
+
+    fn pdf_for(width_mm: f32) -> Vec<u8> {
+        let mut state = PrinterState::new();
+        state.set_paper_width_mm(width_mm);
+        for parsed in EscPosParser::new().parse_stream(b"TOTAL 12.50\n\x1dV\x00").unwrap() {
+            state.process_parsed(&parsed);
+        }
+        render_pdf(&state, PdfScope::Session)
+    }
+
+    fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+        data.windows(needle.len()).position(|window| window == needle)
+    }
+
+    #[test]
+    fn xref_offsets_point_at_their_objects() {
+        let pdf = pdf_for(80.0);
+        let text = String::from_utf8_lossy(&pdf);
+        let startxref: usize = text.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+        assert!(pdf[startxref..].starts_with(b"xref\n0 "));
+
+        let table = &text[text.find("xref\n0 ").unwrap()..];
+        let count: usize = table.lines().nth(1).unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+        for (number, entry) in table.lines().skip(3).take(count - 1).enumerate() {
+            // 20 bytes with the line feed
+            assert_eq!(entry.len(), 19, "{:?}", entry);
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj\n", number + 1).as_bytes()), "object {}", number + 1);
+        }
+        assert!(find(&pdf, format!("/Size {} ", count).as_bytes()).is_some());
+    }
+
+    #[test]
+    fn media_box_is_the_physical_paper_width() {
+        for (width_mm, points) in [(58.0, "164.41"), (80.0, "226.77")] {
+            let pdf = pdf_for(width_mm);
+            let media_box = format!("/MediaBox [0 0 {} ", points);
+            assert!(find(&pdf, media_box.as_bytes()).is_some(), "{}mm", width_mm);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Plain-text export with the printed column layout.
+//!
+//! Every output line is what the printer puts on one line of paper, character
+//! for character: the unprintable margin and justification become leading
+//! spaces and double-width characters take two columns. Tabs are already
+//! expanded to their stops by the printer state. Graphics are replaced with
+//! bracketed placeholders.
+
+use crate::escpos::barcode;
+use crate::escpos::commands::{Font, Justification};
+use crate::escpos::printer::{PrinterState, ReceiptLine, TextLine};
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// The receipt as plain text. Columns follow the characters-per-line of
+/// each line's font; margins and placeholders use the printer's current font.
+pub fn render_text(state: &PrinterState, lines: &[ReceiptLine]) -> String {
+    let layout = TextLayout::new(state);
+    let mut out = String::new();
+    for line in lines {
+        for row in layout.rows(line) {
+            out.push_str(row.trim_end());
+            out.push('\n');
+        }
+    }
+    out
+}
+
+pub fn export_text(state: &PrinterState, path: &Path) -> Result<()> {
+    std::fs::write(path, render_text(state, state.get_buffer()))
+        .with_context(|| format!("Failed to write text to {}", path.display()))
+}
+
+struct TextLayout<'a> {
+    state: &'a PrinterState,
+    /// Leading spaces standing for the unprintable margin
+    margin: String,
+    /// Characters per line of the current font
+    columns: usize,
+}
+
+impl<'a> TextLayout<'a> {
+    fn new(state: &'a PrinterState) -> Self {
+        let cell = state.profile.cell_size(&state.current_font);
+        Self {
+            state,
+            margin: " ".repeat((state.get_margin_dots() / cell.width.max(1)) as usize),
+            columns: state.chars_per_line() as usize,
+        }
+    }
+
+    fn rows(&self, line: &ReceiptLine) -> Vec<String> {
+        match line {
+            ReceiptLine::Text(text) => self.text_rows(text),
+            ReceiptLine::Feed { dots } => {
+                let line_height = self.state.line_height.max(1);
+                vec![String::new(); ((dots + line_height / 2) / line_height) as usize]
+            }
+            ReceiptLine::Separator(_) => {
+                let label = "[CUT]";
+                let dashes = self.columns.saturating_sub(label.len());
+                vec![format!(
+                    "{}{}{}{}",
+                    self.margin,
+                    "-".repeat(dashes / 2),
+                    label,
+                    "-".repeat(dashes - dashes / 2)
+                )]
+            }
+            ReceiptLine::Bitmap { width_px, height_px, justification, .. } => {
+                self.placeholder(&format!("[IMAGE {}x{}]", width_px, height_px), justification)
+            }
+            ReceiptLine::Barcode { system, data, justification, .. } => self.placeholder(
+                &format!("[BARCODE {}: {}]", system.name(), barcode::hri_text(*system, data)),
+                justification,
+            ),
+            ReceiptLine::QrCode { data, justification, .. } => {
+                self.placeholder(&format!("[QR: {}]", String::from_utf8_lossy(data)), justification)
+            }
+        }
+    }
+
+    /// Text wrapped at the line's own characters per line, with each character repeated as a space for every extra column of width.
+    fn text_rows(&self, line: &TextLine) -> Vec<String> {
+        let wm = line.width_mult.max(1) as usize;
+        let columns = self.columns_for(&line.font);
+        let per_row = (columns / wm).max(1);
+        let chars: Vec<char> = line.text.chars().collect();
+        if chars.is_empty() {
+            return vec![String::new()];
+        }
+        chars
+            .chunks(per_row)
+            .map(|chunk| {
+                let mut cells = String::with_capacity(chunk.len() * wm);
+                for &ch in chunk {
+                    cells.push(ch);
+                    cells.extend(std::iter::repeat_n(' ', wm - 1));
+                }
+                self.justified(&cells, chunk.len() * wm, columns, &line.justification)
+            })
+            .collect()
+    }
+
+    fn placeholder(&self, label: &str, justification: &Justification) -> Vec<String> {
+        vec![self.justified(label, label.chars().count(), self.columns, justification)]
+    }
+
+    fn justified(&self, cells: &str, width: usize, columns: usize, justification: &Justification) -> String {
+        let free = columns.saturating_sub(width);
+        let pad = match justification {
+            Justification::Left => 0,
+            Justification::Center => free / 2,
+            Justification::Right => free,
+        };
+        format!("{}{}{}", self.margin, " ".repeat(pad), cells)
+    }
+
+    fn columns_for(&self, font: &Font) -> usize {
+        self.state.chars_per_line_for(font) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::parser::EscPosParser;
+
+    fn render(data: &[u8]) -> String {
+        let mut state = PrinterState::new();
+        for parsed in EscPosParser::new().parse_stream(data).unwrap() {
+            state.process_command(&parsed.command);
+        }
+        render_text(&state, state.get_buffer())
+    }
+
+    #[test]
+    fn style_change_after_line_feed_starts_no_extra_line() {
+        let text = render(b"TOTAL 12.50\n\x1bE\x01THANK YOU\x1bE\x00\n");
+        let lines: Vec<&str> = text.lines().map(str::trim).collect();
+        assert_eq!(lines, ["TOTAL 12.50", "THANK YOU", ""]);
+    }
+
+    #[test]
+    fn blank_lines_survive_a_style_change() {
+        let text = render(b"A\n\n\x1ba\x01B\n");
+        let lines: Vec<&str> = text.lines().map(str::trim).collect();
+        assert_eq!(lines, ["A", "", "B", ""]);
+    }
+}
@@ -1,27 +1,24 @@
 use crate::emulator::EmulatorState;
-use crate::gui::{CommandLog, ReceiptViewer, SettingsPanel};
+use crate::gui::{ArchiveBrowser, CommandLog, ReceiptViewer, SettingsPanel};
 use crate::networking::serial::SerialHandle;
 use eframe::egui::{CentralPanel, TopBottomPanel};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub enum Tab {
+    #[default]
     Receipt,
     Commands,
+    Archive,
     Settings,
 }
 
-impl Default for Tab {
-    fn default() -> Self {
-        Tab::Receipt
-    }
-}
-
 pub struct EscPosEmulatorApp {
     pub emulator_state: std::sync::Arc<tokio::sync::Mutex<EmulatorState>>,
     tokio_handle: tokio::runtime::Handle,
     selected_tab: Tab,
     receipt_viewer: ReceiptViewer,
     command_log: CommandLog,
+    archive_browser: ArchiveBrowser,
     settings_panel: SettingsPanel,
     serial_handle: Option<SerialHandle>,
 }
@@ -37,6 +34,7 @@ impl EscPosEmulatorApp {
             selected_tab: Tab::Receipt,
             receipt_viewer: ReceiptViewer::new(),
             command_log: CommandLog::new(),
+            archive_browser: ArchiveBrowser::new(),
             settings_panel: SettingsPanel::default(),
             serial_handle: None,
         }
@@ -55,6 +53,7 @@ impl EscPosEmulatorApp {
             ui.horizontal(|ui| {
                 ui.selectable_value(&mut self.selected_tab, Tab::Receipt, "🖨️ Receipt");
                 ui.selectable_value(&mut self.selected_tab, Tab::Commands, "📋 Commands");
+                ui.selectable_value(&mut self.selected_tab, Tab::Archive, "🗄️ Archive");
                 ui.selectable_value(&mut self.selected_tab, Tab::Settings, "⚙️ Settings");
             });
         });
@@ -67,6 +66,12 @@ impl EscPosEmulatorApp {
                 Tab::Commands => {
                     self.command_log.show(ui, &self.emulator_state);
                 }
+                Tab::Archive => {
+                    if let Some(job_id) = self.archive_browser.show(ui, &self.emulator_state) {
+                        self.receipt_viewer.select_job(Some(job_id));
+                        self.selected_tab = Tab::Receipt;
+                    }
+                }
                 Tab::Settings => {
                     self.settings_panel.show(
                         ui,
@@ -0,0 +1,216 @@
+use crate::emulator::{ArchiveQuery, EmulatorState, RetentionPolicy};
+use crate::escpos::diagnostics::Severity;
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+use egui::{Color32, RichText, ScrollArea, Ui};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub struct ArchiveBrowser {
+    archive_dir: String,
+    max_jobs: usize,
+    max_megabytes: u64,
+    text: String,
+    source: String,
+    /// Local dates as YYYY-MM-DD, inclusive
+    from_date: String,
+    to_date: String,
+    min_severity: Option<Severity>,
+    selected: Option<String>,
+    status_message: String,
+}
+
+impl Default for ArchiveBrowser {
+    fn default() -> Self {
+        let retention = RetentionPolicy::default();
+        Self {
+            archive_dir: "archive".to_string(),
+            max_jobs: retention.max_jobs.unwrap_or(1000),
+            max_megabytes: retention.max_bytes.unwrap_or(0) / (1024 * 1024),
+            text: String::new(),
+            source: String::new(),
+            from_date: String::new(),
+            to_date: String::new(),
+            min_severity: None,
+            selected: None,
+            status_message: String::new(),
+        }
+    }
+}
+
+impl ArchiveBrowser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id of a job reopened into the job list.
+    pub fn show(&mut self, ui: &mut Ui, emulator_state: &Arc<Mutex<EmulatorState>>) -> Option<u64> {
+        let mut state = match emulator_state.try_lock() {
+            Ok(state) => state,
+            Err(_) => {
+                ui.colored_label(Color32::RED, "Loading printer state...");
+                return None;
+            }
+        };
+
+        ui.group(|ui| {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("🗄️ Archive directory:").strong());
+                ui.text_edit_singleline(&mut self.archive_dir);
+                ui.label("Keep");
+                ui.add(egui::DragValue::new(&mut self.max_jobs).clamp_range(1..=1_000_000).suffix(" jobs"));
+                ui.add(egui::DragValue::new(&mut self.max_megabytes).clamp_range(1..=1_000_000).suffix(" MB"));
+                let retention = RetentionPolicy {
+                    max_jobs: Some(self.max_jobs),
+                    max_bytes: Some(self.max_megabytes * 1024 * 1024),
+                };
+                if state.archive.is_some() {
+                    if let Some(mut archive) = state.archive().filter(|archive| archive.retention != retention) {
+                        archive.retention = retention;
+                        if let Err(e) = archive.enforce_retention() {
+                            self.status_message = format!("❌ {:#}", e);
+                        }
+                    }
+                    if ui.button("⏹ Stop archiving").clicked() {
+                        state.close_archive();
+                        self.status_message = "Archiving stopped".to_string();
+                    }
+                } else if ui.button("▶ Archive finished jobs").clicked() {
+                    self.status_message = match state.open_archive(Path::new(self.archive_dir.trim()), retention) {
+                        Ok(()) => format!("✅ Archiving to {}", self.archive_dir.trim()),
+                        Err(e) => format!("❌ {:#}", e),
+                    };
+                }
+            });
+            if !self.status_message.is_empty() {
+                ui.label(&self.status_message);
+            }
+        });
+
+        let archive = match state.archive() {
+            Some(archive) => archive,
+            None => {
+                ui.label("Finished jobs are not being archived. Choose a directory and start archiving to keep them across restarts.");
+                return None;
+            }
+        };
+        ui.label(format!(
+            "{} job(s), {:.1} MB in {}",
+            archive.entries().len(),
+            archive.disk_bytes() as f64 / (1024.0 * 1024.0),
+            archive.dir().display()
+        ));
+
+        ui.horizontal_wrapped(|ui| {
+            ui.label("🔍 Text:");
+            ui.text_edit_singleline(&mut self.text);
+            ui.label("Source:");
+            ui.add(egui::TextEdit::singleline(&mut self.source).desired_width(120.0));
+            ui.label("From:");
+            ui.add(egui::TextEdit::singleline(&mut self.from_date).hint_text("YYYY-MM-DD").desired_width(90.0));
+            ui.label("To:");
+            ui.add(egui::TextEdit::singleline(&mut self.to_date).hint_text("YYYY-MM-DD").desired_width(90.0));
+            egui::ComboBox::from_id_source("archive_severity")
+                .selected_text(match self.min_severity {
+                    None => "Any diagnostics",
+                    Some(Severity::Info) => "With diagnostics",
+                    Some(Severity::Warning) => "Warnings or errors",
+                    Some(Severity::Error) => "Errors only",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.min_severity, None, "Any diagnostics");
+                    ui.selectable_value(&mut self.min_severity, Some(Severity::Info), "With diagnostics");
+                    ui.selectable_value(&mut self.min_severity, Some(Severity::Warning), "Warnings or errors");
+                    ui.selectable_value(&mut self.min_severity, Some(Severity::Error), "Errors only");
+                });
+        });
+
+        let query = ArchiveQuery {
+            text: self.text.clone(),
+            from: parse_local_date(&self.from_date, 0),
+            to: parse_local_date(&self.to_date, 1),
+            source: self.source.clone(),
+            min_severity: self.min_severity,
+        };
+        let results: Vec<_> = archive.search(&query).into_iter().cloned().collect();
+        drop(archive);
+        ui.separator();
+
+        let mut reopen = None;
+        let mut delete = None;
+        ui.columns(2, |columns| {
+            ScrollArea::vertical().id_source("archive_results").show(&mut columns[0], |ui| {
+                ui.label(format!("{} match(es)", results.len()));
+                for entry in &results {
+                    let started = DateTime::<Local>::from(entry.started_at);
+                    let first_line = entry.text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("");
+                    let mut label = format!(
+                        "{}  {}  {} B\n{}",
+                        started.format("%Y-%m-%d %H:%M:%S"),
+                        entry.source,
+                        entry.byte_count,
+                        first_line
+                    );
+                    if let Some(severity) = entry.worst_severity {
+                        label.push_str(&format!("\n⚠ {} {:?}", entry.diagnostics.len(), severity));
+                    }
+                    let selected = self.selected.as_deref() == Some(entry.id.as_str());
+                    if ui.selectable_label(selected, label).clicked() {
+                        self.selected = Some(entry.id.clone());
+                    }
+                }
+            });
+
+            let ui = &mut columns[1];
+            match results.iter().find(|entry| Some(&entry.id) == self.selected.as_ref()) {
+                Some(entry) => {
+                    ui.horizontal(|ui| {
+                        if ui.button("↩ Open in receipt viewer").clicked() {
+                            reopen = Some(entry.id.clone());
+                        }
+                        if ui.button("🗑 Delete").clicked() {
+                            delete = Some(entry.id.clone());
+                        }
+                    });
+                    ui.label(format!("{} commands, {} bytes from {}", entry.command_count, entry.byte_count, entry.source));
+                    for diagnostic in &entry.diagnostics {
+                        ui.label(RichText::new(diagnostic).small());
+                    }
+                    ui.separator();
+                    ScrollArea::vertical().id_source("archive_preview").show(ui, |ui| {
+                        ui.label(RichText::new(&entry.text).monospace());
+                    });
+                }
+                None => {
+                    ui.label("Select a job to preview it");
+                }
+            }
+        });
+
+        if let Some(id) = delete {
+            if let Some(mut archive) = state.archive() {
+                self.status_message = match archive.remove(&id) {
+                    Ok(()) => format!("Deleted {}", id),
+                    Err(e) => format!("❌ {:#}", e),
+                };
+            }
+            self.selected = None;
+        }
+        match reopen.map(|id| state.reopen_archived(&id)) {
+            Some(Ok(job_id)) => Some(job_id),
+            Some(Err(e)) => {
+                self.status_message = format!("❌ {:#}", e);
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+/// Local midnight `days_after` days after a YYYY-MM-DD date, so a "to" date
+/// includes the whole day.
+fn parse_local_date(text: &str, days_after: u64) -> Option<DateTime<Utc>> {
+    let date = NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").ok()?;
+    let midnight = date.checked_add_days(chrono::Days::new(days_after))?.and_hms_opt(0, 0, 0)?;
+    Local.from_local_datetime(&midnight).earliest().map(|local| local.with_timezone(&Utc))
+}
@@ -1,5 +1,6 @@
 use crate::emulator::EmulatorState;
-use egui::{ScrollArea, Ui};
+use crate::escpos::diagnostics::Severity;
+use egui::{Color32, RichText, ScrollArea, Ui};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -8,6 +9,7 @@ pub struct CommandLog {
     show_raw_data: bool,
     max_display_lines: usize,
     filter_text: String,
+    min_severity: Severity,
 }
 
 impl Default for CommandLog {
@@ -17,6 +19,7 @@ impl Default for CommandLog {
             show_raw_data: false,
             max_display_lines: 1000,
             filter_text: String::new(),
+            min_severity: Severity::Info,
         }
     }
 }
@@ -37,6 +40,14 @@ impl CommandLog {
             
             ui.label("Filter:");
             ui.text_edit_singleline(&mut self.filter_text);
+
+            egui::ComboBox::from_id_source("diagnostic_severity")
+                .selected_text(format!("Diagnostics: {:?}+", self.min_severity))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.min_severity, Severity::Info, "Info");
+                    ui.selectable_value(&mut self.min_severity, Severity::Warning, "Warning");
+                    ui.selectable_value(&mut self.min_severity, Severity::Error, "Error");
+                });
             
             if ui.button("🗑️ Clear").clicked() {
                 if let Ok(mut state) = emulator_state.try_lock() {
@@ -58,6 +69,8 @@ impl CommandLog {
     }
 
     fn render_command_list(&self, ui: &mut Ui, state: &EmulatorState) {
+        self.render_diagnostics(ui, state);
+
         let history = state.get_command_history();
         
         if history.is_empty() {
@@ -103,6 +116,37 @@ impl CommandLog {
         ));
     }
 
+    fn render_diagnostics(&self, ui: &mut Ui, state: &EmulatorState) {
+        let diagnostics = state.get_diagnostics();
+        let shown: Vec<_> = diagnostics
+            .iter()
+            .filter(|entry| entry.diagnostic.severity >= self.min_severity)
+            .collect();
+        if shown.is_empty() {
+            return;
+        }
+
+        let errors = shown.iter().filter(|e| e.diagnostic.severity == Severity::Error).count();
+        let header = RichText::new(format!("🩺 Diagnostics ({} shown, {} errors)", shown.len(), errors))
+            .color(severity_color(if errors > 0 { Severity::Error } else { Severity::Warning }));
+        ui.collapsing(header, |ui| {
+            for entry in shown.iter().rev() {
+                let d = &entry.diagnostic;
+                let icon = match d.severity {
+                    Severity::Error => "❌",
+                    Severity::Warning => "⚠️",
+                    Severity::Info => "ℹ️",
+                };
+                ui.label(
+                    RichText::new(format!("{} @{} {:?}: {}", icon, d.offset, d.kind, d.message))
+                        .monospace()
+                        .color(severity_color(d.severity)),
+                );
+            }
+        });
+        ui.separator();
+    }
+
     fn render_command_entry(&self, ui: &mut Ui, entry: &crate::emulator::CommandEntry) {
         ui.group(|ui| {
             // Timestamp
@@ -143,11 +187,30 @@ impl CommandLog {
                 crate::escpos::commands::EscPosCommand::SetItalic(enabled) => {
                     format!("📝 Italic: {}", if *enabled { "ON" } else { "OFF" })
                 }
-                crate::escpos::commands::EscPosCommand::CutPaper => {
-                    "✂️ Paper cut".to_string()
+                crate::escpos::commands::EscPosCommand::CutPaper(kind) => match kind {
+                    crate::escpos::commands::CutKind::Full => "✂️ Paper cut".to_string(),
+                    crate::escpos::commands::CutKind::Partial => "✂️ Partial paper cut".to_string(),
+                },
+                crate::escpos::commands::EscPosCommand::DrawerKick { pin, on_ms, .. } => {
+                    format!("💰 Drawer kick: pin {}, {} ms", pin, on_ms)
+                }
+                crate::escpos::commands::EscPosCommand::PrintImage { mode, width_dots, .. } => {
+                    format!("🖼️ Bit Image (ESC *) mode {}, {} columns", mode, width_dots)
                 }
-                crate::escpos::commands::EscPosCommand::PrintImage(_) => {
-                    "🖼️ Bit Image (ESC *)".to_string()
+                crate::escpos::commands::EscPosCommand::SetCharacterSize { width, height } => {
+                    format!("🔠 Character size: {}×{}", width, height)
+                }
+                crate::escpos::commands::EscPosCommand::SetReverse(enabled) => {
+                    format!("🔳 Reverse: {}", if *enabled { "ON" } else { "OFF" })
+                }
+                crate::escpos::commands::EscPosCommand::PrintBarcode { system, data } => {
+                    format!("▮ Barcode {}: {}", system.name(), String::from_utf8_lossy(data))
+                }
+                crate::escpos::commands::EscPosCommand::StoreQrData(data) => {
+                    format!("📱 QR data: {}", String::from_utf8_lossy(data))
+                }
+                crate::escpos::commands::EscPosCommand::PrintQrCode => {
+                    "📱 Print QR code".to_string()
                 }
                 crate::escpos::commands::EscPosCommand::PrintRasterImage { width_bytes, height, .. } => {
                     format!("🖼️ Raster Image (GS v 0) {}×{}", width_bytes * 8, height)
@@ -161,6 +224,18 @@ impl CommandLog {
                 crate::escpos::commands::EscPosCommand::SetFontSize(size) => {
                     format!("🔤 Font size: {}", size)
                 }
+                crate::escpos::commands::EscPosCommand::SetMarkAdjustment { position, dots } => {
+                    format!("📍 {:?} adjustment: {} dots", position, dots)
+                }
+                crate::escpos::commands::EscPosCommand::FeedToMark(position) => {
+                    format!("⏭️ Feed to {:?} position", position)
+                }
+                crate::escpos::commands::EscPosCommand::ToggleMacroDefinition => {
+                    "🎬 Macro definition start/end".to_string()
+                }
+                crate::escpos::commands::EscPosCommand::ExecuteMacro { repeat, wait, .. } => {
+                    format!("▶️ Execute macro ×{} (wait {} ms)", repeat, *wait as u32 * 100)
+                }
                 crate::escpos::commands::EscPosCommand::Unknown(_) => {
                     "❓ Unknown command".to_string()
                 }
@@ -178,8 +253,16 @@ impl CommandLog {
                     .collect::<Vec<_>>()
                     .join(" ");
                 
-                ui.label(format!("🔢 Data: {}", hex_data));
+                ui.label(format!("🔢 Data @{}: {}", entry.stream_offset, hex_data));
             }
         });
     }
 }
+
+fn severity_color(severity: Severity) -> Color32 {
+    match severity {
+        Severity::Error => Color32::from_rgb(231, 76, 60),
+        Severity::Warning => Color32::from_rgb(230, 126, 34),
+        Severity::Info => Color32::from_rgb(150, 150, 160),
+    }
+}
@@ -1,9 +1,11 @@
 pub mod app;
+pub mod archive_browser;
 pub mod receipt_viewer;
 pub mod command_log;
 pub mod settings_panel;
 
 pub use app::EscPosEmulatorApp;
+pub use archive_browser::ArchiveBrowser;
 pub use receipt_viewer::ReceiptViewer;
 pub use command_log::CommandLog;
 pub use settings_panel::SettingsPanel;
@@ -1,31 +1,49 @@
-use crate::emulator::EmulatorState;
-use crate::escpos::commands::{Font, Justification};
-use crate::escpos::printer::{PaperWidth, PrinterState, ReceiptLine, TextLine};
+use crate::emulator::pcap::DEFAULT_PRINTER_PORT;
+use crate::emulator::{EmulatorState, JobSource, MACRO_WAIT_STEP};
+use crate::escpos::barcode;
+use crate::escpos::paper::{PaperEdge, PaperKind, PaperWidth};
+use crate::escpos::printer::{PrinterState, ReceiptLine};
+use crate::escpos::raster;
+use crate::export::{self, ExportFormat, JobDocument, PdfScope};
 use egui::{
     vec2, Color32, ColorImage, Frame, Layout, Margin, RichText, ScrollArea, Stroke, TextureHandle,
     TextureOptions, Ui,
 };
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ReceiptSubTab {
+    #[default]
     RealPreview,
     RawText,
 }
 
-impl Default for ReceiptSubTab {
-    fn default() -> Self {
-        ReceiptSubTab::RealPreview
-    }
-}
-
 pub struct ReceiptViewer {
     selected_sub_tab: ReceiptSubTab,
     show_paper_shadow: bool,
     zoom_factor: f32,
-    bitmap_cache: HashMap<u64, TextureHandle>,
+    /// Rasterized receipt split into textures, with their height in dots
+    tiles: Vec<(TextureHandle, u32)>,
+    /// Printer revision and job the tiles were rendered from
+    tiles_revision: Option<(u64, Option<u64>)>,
+    /// Job shown and exported instead of the whole paper roll
+    selected_job: Option<u64>,
+    /// Captured `.bin`/`.prn` file to print
+    capture_path: String,
+    /// Printer port whose connections are imported from network traces
+    pcap_port: u16,
+    /// Directory that exports and saved jobs go to
+    export_dir: PathBuf,
+    /// File name of exports, without extension
+    export_path: String,
+    /// Pixels per printer dot in exported images
+    export_scale: u32,
+    /// Export every receipt in the buffer, not just the last one
+    export_session: bool,
+    status_message: String,
 }
 
 impl Default for ReceiptViewer {
@@ -34,25 +52,30 @@ impl Default for ReceiptViewer {
             selected_sub_tab: ReceiptSubTab::RealPreview,
             show_paper_shadow: true,
             zoom_factor: 1.0,
-            bitmap_cache: HashMap::new(),
+            tiles: Vec::new(),
+            tiles_revision: None,
+            selected_job: None,
+            capture_path: String::new(),
+            pcap_port: DEFAULT_PRINTER_PORT,
+            export_dir: std::env::current_dir().unwrap_or_default(),
+            export_path: "receipt".to_string(),
+            export_scale: 1,
+            export_session: false,
+            status_message: String::new(),
         }
     }
 }
 
-fn hash_bytes(data: &[u8]) -> u64 {
-    let mut hash: u64 = 0xcbf29ce484222325;
-    for &byte in data {
-        hash ^= byte as u64;
-        hash = hash.wrapping_mul(0x100000001b3);
-    }
-    hash
-}
-
 impl ReceiptViewer {
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Show one job, or the whole paper roll for `None`.
+    pub fn select_job(&mut self, job_id: Option<u64>) {
+        self.selected_job = job_id;
+    }
+
     pub fn show(&mut self, ui: &mut Ui, emulator_state: &Arc<Mutex<EmulatorState>>) {
         ui.vertical(|ui| {
             // Header & Sub-Tab Navigation
@@ -73,22 +96,26 @@ impl ReceiptViewer {
                 // Paper width selector
                 ui.label(RichText::new("📏 Paper Width:").strong());
                 if let Ok(state) = emulator_state.try_lock() {
-                    let current_pw = state.printer_state.paper_width.clone();
+                    let current_mm = state.printer_state.paper_width.width_mm;
                     drop(state);
 
-                    if ui.selectable_label(current_pw == PaperWidth::Width50mm, "50mm").clicked() {
-                        if let Ok(mut state) = emulator_state.try_lock() {
-                            state.set_paper_width(50);
-                        }
-                    }
-                    if ui.selectable_label(current_pw == PaperWidth::Width78mm, "78mm").clicked() {
-                        if let Ok(mut state) = emulator_state.try_lock() {
-                            state.set_paper_width(78);
+                    let mut width_mm = current_mm;
+                    for common in PaperWidth::COMMON_WIDTHS_MM {
+                        if ui.selectable_label(width_mm == common, format!("{}mm", common)).clicked() {
+                            width_mm = common;
                         }
                     }
-                    if ui.selectable_label(current_pw == PaperWidth::Width80mm, "80mm").clicked() {
+                    ui.add(
+                        egui::DragValue::new(&mut width_mm)
+                            .speed(0.5)
+                            .clamp_range(PaperWidth::MIN_MM..=PaperWidth::MAX_MM)
+                            .suffix(" mm"),
+                    )
+                    .on_hover_text("Custom paper width");
+
+                    if width_mm != current_mm {
                         if let Ok(mut state) = emulator_state.try_lock() {
-                            state.set_paper_width(80);
+                            let _ = state.set_paper_width(width_mm);
                         }
                     }
                 }
@@ -100,16 +127,31 @@ impl ReceiptViewer {
                     ui.add(egui::Slider::new(&mut self.zoom_factor, 0.75..=1.40).text("Zoom"));
                 }
 
+                let waiting = emulator_state.try_lock().is_ok_and(|state| state.waiting_for_feed_button());
+                let feed = ui.add_enabled(waiting, egui::Button::new("🔘 FEED"));
+                if feed.on_hover_text("Press the FEED button a macro waits for").clicked() {
+                    if let Ok(mut state) = emulator_state.try_lock() {
+                        state.press_feed_button();
+                    }
+                }
+
                 if ui.button("🗑️ Clear").on_hover_text("Clear current receipt buffer").clicked() {
                     if let Ok(mut state) = emulator_state.try_lock() {
                         state.clear_printer_buffer();
                     }
-                    self.bitmap_cache.clear();
                 }
             });
 
+            self.render_open_bar(ui, emulator_state);
+            self.render_export_bar(ui, emulator_state);
+
             ui.separator();
 
+            egui::SidePanel::left("job_list")
+                .resizable(true)
+                .default_width(230.0)
+                .show_inside(ui, |ui| self.render_job_list(ui, emulator_state));
+
             // Render selected sub-tab
             match self.selected_sub_tab {
                 ReceiptSubTab::RealPreview => {
@@ -138,20 +180,326 @@ impl ReceiptViewer {
                     });
                 }
             }
+
+            self.handle_dropped_files(ui, emulator_state);
+        });
+    }
+
+    fn render_open_bar(&mut self, ui: &mut Ui, emulator_state: &Arc<Mutex<EmulatorState>>) {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("🖨️ Print file:").strong());
+            let open_hover = "Print a captured .bin/.prn file as a new job, or import the printer connections of a .pcap/.pcapng trace";
+            if ui.button("📂 Open file…").on_hover_text(open_hover).clicked() {
+                let picked = rfd::FileDialog::new()
+                    .set_title("Print a capture")
+                    .add_filter("Captures and traces", &["bin", "prn", "pcap", "pcapng", "cap"])
+                    .add_filter("All files", &["*"])
+                    .pick_file();
+                if let Some(path) = picked {
+                    self.capture_path = path.display().to_string();
+                    self.print_file(emulator_state, &path);
+                }
+            }
+            let response = ui.add(egui::TextEdit::singleline(&mut self.capture_path).hint_text("or type a path"));
+            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("Print path").on_hover_text("Print the file at the typed path").clicked() || submitted {
+                let path = Path::new(self.capture_path.trim()).to_path_buf();
+                self.print_file(emulator_state, &path);
+            }
+            ui.label(RichText::new("or drop files onto the receipt").weak());
+            ui.separator();
+            ui.label("Trace port:");
+            ui.add(egui::DragValue::new(&mut self.pcap_port).clamp_range(1..=65535))
+                .on_hover_text("TCP port of the printer in .pcap/.pcapng traces");
         });
     }
 
+    /// Print files dropped anywhere on the receipt view, one job per file.
+    fn handle_dropped_files(&mut self, ui: &mut Ui, emulator_state: &Arc<Mutex<EmulatorState>>) {
+        let hovering = ui.ctx().input(|i| !i.raw.hovered_files.is_empty());
+        if hovering {
+            let rect = ui.clip_rect();
+            let painter = ui.ctx().layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("receipt_drop")));
+            painter.rect_filled(rect, 0.0, Color32::from_black_alpha(160));
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "🖨️ Drop .bin / .prn files to print them",
+                egui::FontId::proportional(22.0),
+                Color32::WHITE,
+            );
+        }
+
+        let dropped = ui.ctx().input(|i| i.raw.dropped_files.clone());
+        for file in dropped {
+            match (&file.path, &file.bytes) {
+                (Some(path), _) => self.print_file(emulator_state, path),
+                (None, Some(bytes)) => {
+                    let source = JobSource::File(file.name.clone());
+                    self.status_message = match emulator_state.try_lock() {
+                        Ok(mut state) => {
+                            let jobs = state.print_bytes(&source, bytes);
+                            state.take_responses();
+                            match jobs.first() {
+                                Some(first) => {
+                                    self.selected_job = Some(*first);
+                                    format!("✅ Printed {}{}", file.name, job_count_note(&jobs))
+                                }
+                                None => format!("❌ {} contains no printable data", file.name),
+                            }
+                        }
+                        Err(_) => "❌ Printer state is busy, try again".to_string(),
+                    };
+                }
+                (None, None) => {}
+            }
+        }
+    }
+
+    fn print_file(&mut self, emulator_state: &Arc<Mutex<EmulatorState>>, path: &Path) {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        if matches!(extension.as_str(), "pcap" | "pcapng" | "cap") {
+            self.import_pcap(emulator_state, path);
+            return;
+        }
+        self.status_message = match emulator_state.try_lock() {
+            Ok(mut state) => match state.print_file(path) {
+                Ok(jobs) => {
+                    self.selected_job = jobs.first().copied();
+                    format!("✅ Printed {}{}", path.display(), job_count_note(&jobs))
+                }
+                Err(e) => format!("❌ {:#}", e),
+            },
+            Err(_) => "❌ Printer state is busy, try again".to_string(),
+        };
+    }
+
+    fn render_export_bar(&mut self, ui: &mut Ui, emulator_state: &Arc<Mutex<EmulatorState>>) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label(RichText::new("💾 Export to").strong());
+            if ui.button(format!("📁 {}", self.export_dir.display())).on_hover_text("Choose the export folder").clicked() {
+                if let Some(dir) = rfd::FileDialog::new().set_directory(&self.export_dir).pick_folder() {
+                    self.export_dir = dir;
+                }
+            }
+            ui.label("as");
+            ui.add(egui::TextEdit::singleline(&mut self.export_path).desired_width(120.0))
+                .on_hover_text("File name; the extension follows the format");
+            egui::ComboBox::from_id_source("export_scale")
+                .selected_text(format!("{}x", self.export_scale))
+                .show_ui(ui, |ui| {
+                    for scale in [1, 2, 4] {
+                        ui.selectable_value(&mut self.export_scale, scale, format!("{}x", scale));
+                    }
+                })
+                .response
+                .on_hover_text("Pixels per printer dot");
+            if ui.button("🖼️ PNG").on_hover_text("Save the receipt as printed, one pixel per dot").clicked() {
+                self.export(emulator_state, ExportFormat::Png { scale: self.export_scale });
+            }
+            ui.separator();
+            ui.checkbox(&mut self.export_session, "All receipts")
+                .on_hover_text("One PDF page per cut instead of only the last receipt");
+            if ui.button("📄 PDF").on_hover_text("Save at the paper's physical size with selectable text").clicked() {
+                let scope = if self.export_session { PdfScope::Session } else { PdfScope::LastReceipt };
+                self.export(emulator_state, ExportFormat::Pdf(scope));
+            }
+            ui.separator();
+            if ui.button("🌐 HTML").on_hover_text("Save as a web page with real, styled text").clicked() {
+                self.export(emulator_state, ExportFormat::Html);
+            }
+            if ui.button("✏️ SVG").on_hover_text("Save as a vector image with real text").clicked() {
+                self.export(emulator_state, ExportFormat::Svg);
+            }
+            if ui.button("📝 TXT").on_hover_text("Save as plain text in the printer's columns").clicked() {
+                self.export(emulator_state, ExportFormat::Text);
+            }
+            if ui.button("📋 Copy").on_hover_text("Copy the plain text layout to the clipboard").clicked() {
+                self.copy_text(ui, emulator_state);
+            }
+            ui.separator();
+            if ui.button("🧾 JSON").on_hover_text("Save commands, lines and diagnostics as a versioned job file").clicked() {
+                self.export_job(emulator_state);
+            }
+            if ui.button("📂 Load JSON").on_hover_text("Replace the session with a saved job file").clicked() {
+                self.import_job(emulator_state);
+            }
+            if !self.status_message.is_empty() {
+                ui.label(&self.status_message);
+            }
+        });
+    }
+
+    /// Received jobs, newest first. Selecting one limits the preview and every
+    /// export to that job.
+    fn render_job_list(&mut self, ui: &mut Ui, emulator_state: &Arc<Mutex<EmulatorState>>) {
+        ui.label(RichText::new("🗂️ Jobs").strong());
+        let mut state = match emulator_state.try_lock() {
+            Ok(state) => state,
+            Err(_) => {
+                ui.label("Loading jobs...");
+                return;
+            }
+        };
+        state.finish_idle_jobs();
+        if state.macro_running() {
+            ui.ctx().request_repaint_after(MACRO_WAIT_STEP);
+        }
+
+        if ui.selectable_label(self.selected_job.is_none(), "🧻 Whole paper roll").clicked() {
+            self.selected_job = None;
+        }
+        ui.separator();
+
+        let mut delete = None;
+        let mut pin = None;
+        ScrollArea::vertical().show(ui, |ui| {
+            if state.jobs.is_empty() {
+                ui.label(RichText::new("No jobs received yet").weak());
+            }
+            for job in state.jobs.iter().rev() {
+                ui.horizontal(|ui| {
+                    let started = chrono::DateTime::<chrono::Local>::from(job.started_at);
+                    let mut title = format!("#{} {}", job.id, job.source);
+                    if job.active {
+                        title.push_str(" ●");
+                    }
+                    let mut details = format!(
+                        "{} · {} B · {} cmds",
+                        started.format("%H:%M:%S"),
+                        job.byte_count,
+                        job.command_count
+                    );
+                    if !job.diagnostics.is_empty() {
+                        details.push_str(&format!(" · ⚠ {}", job.diagnostics.len()));
+                    }
+                    let label = format!("{}\n{}", title, details);
+                    if ui.selectable_label(self.selected_job == Some(job.id), label).clicked() {
+                        self.selected_job = Some(job.id);
+                    }
+                    ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                        let delete_button = ui.add_enabled(!job.active, egui::Button::new("🗑").small());
+                        if delete_button.on_hover_text("Delete this job").clicked() {
+                            delete = Some(job.id);
+                        }
+                        let pin_text = if job.pinned {
+                            RichText::new("📌").color(Color32::from_rgb(230, 126, 34))
+                        } else {
+                            RichText::new("📌").weak()
+                        };
+                        let pin_hover = if job.pinned { "Unpin" } else { "Pin: keep when old jobs are dropped" };
+                        if ui.add(egui::Button::new(pin_text).small()).on_hover_text(pin_hover).clicked() {
+                            pin = Some((job.id, !job.pinned));
+                        }
+                    });
+                });
+            }
+        });
+
+        if let Some(id) = delete {
+            state.delete_job(id);
+        }
+        if let Some((id, pinned)) = pin {
+            state.set_job_pinned(id, pinned);
+        }
+        if self.selected_job.is_some_and(|id| state.get_job(id).is_none()) {
+            self.selected_job = None;
+        }
+    }
+
+    /// The selected job's lines, or the whole paper roll.
+    fn selected_lines<'a>(&self, state: &'a EmulatorState) -> Cow<'a, [ReceiptLine]> {
+        match self.selected_job.and_then(|id| state.get_job(id)) {
+            Some(job) => state.job_lines(job),
+            None => Cow::Borrowed(state.printer_state.get_buffer()),
+        }
+    }
+
+    /// Where an export with `extension` goes: the file name in the export folder.
+    fn export_file(&self, extension: &str) -> PathBuf {
+        self.export_dir.join(self.export_path.trim()).with_extension(extension)
+    }
+
+    fn export(&mut self, emulator_state: &Arc<Mutex<EmulatorState>>, format: ExportFormat) {
+        let path = self.export_file(format.extension());
+        self.status_message = match emulator_state.try_lock() {
+            Ok(state) => match export::export_lines(&state.printer_state, &self.selected_lines(&state), &path, format) {
+                Ok(()) => format!("✅ Saved {}", path.display()),
+                Err(e) => format!("❌ {:#}", e),
+            },
+            Err(_) => "❌ Printer state is busy, try again".to_string(),
+        };
+    }
+
+    fn copy_text(&mut self, ui: &Ui, emulator_state: &Arc<Mutex<EmulatorState>>) {
+        self.status_message = match emulator_state.try_lock() {
+            Ok(state) => {
+                let text = export::render_text(&state.printer_state, &self.selected_lines(&state));
+                let lines = text.lines().count();
+                ui.output_mut(|o| o.copied_text = text);
+                format!("✅ Copied {} lines", lines)
+            }
+            Err(_) => "❌ Printer state is busy, try again".to_string(),
+        };
+    }
+
+    fn export_job(&mut self, emulator_state: &Arc<Mutex<EmulatorState>>) {
+        let path = self.export_file("json");
+        self.status_message = match emulator_state.try_lock() {
+            Ok(state) => {
+                let result = match self.selected_job.and_then(|id| state.get_job(id)) {
+                    Some(job) => JobDocument::from_job(&state, job).save(&path),
+                    None => state.export_job(&path, "session"),
+                };
+                match result {
+                    Ok(()) => format!("✅ Saved {}", path.display()),
+                    Err(e) => format!("❌ {:#}", e),
+                }
+            }
+            Err(_) => "❌ Printer state is busy, try again".to_string(),
+        };
+    }
+
+    fn import_job(&mut self, emulator_state: &Arc<Mutex<EmulatorState>>) {
+        let picked = rfd::FileDialog::new()
+            .set_title("Load a job file")
+            .set_directory(&self.export_dir)
+            .add_filter("JSON job", &["json"])
+            .pick_file();
+        let path = match picked {
+            Some(path) => path,
+            None => return,
+        };
+        self.status_message = match emulator_state.try_lock() {
+            Ok(mut state) => match state.import_job(&path) {
+                Ok(job) => format!("✅ Loaded {} commands from {}", job.metadata.command_count, path.display()),
+                Err(e) => format!("❌ {:#}", e),
+            },
+            Err(_) => "❌ Printer state is busy, try again".to_string(),
+        };
+    }
+
+    fn import_pcap(&mut self, emulator_state: &Arc<Mutex<EmulatorState>>, path: &Path) {
+        self.status_message = match emulator_state.try_lock() {
+            Ok(mut state) => match state.import_pcap(path, self.pcap_port) {
+                Ok(connections) => {
+                    // Show everything imported rather than one connection
+                    self.selected_job = None;
+                    format!("✅ Imported {} connection(s) from {}", connections, path.display())
+                }
+                Err(e) => format!("❌ {:#}", e),
+            },
+            Err(_) => "❌ Printer state is busy, try again".to_string(),
+        };
+    }
+
     fn render_dispenser_and_paper(&mut self, ui: &mut Ui, state: &EmulatorState) {
         let printer_state = state.get_printer_state();
-        let buffer = printer_state.get_buffer();
+        let buffer = self.selected_lines(state);
 
         // Calculate responsive canvas width based on thermal paper width & zoom
         let paper_mm_dots = printer_state.get_paper_width_dots();
-        let base_paper_width_px = match printer_state.paper_width {
-            PaperWidth::Width50mm => 340.0,
-            PaperWidth::Width78mm => 440.0,
-            PaperWidth::Width80mm => 490.0,
-        };
+        let base_paper_width_px = printer_state.paper_width.width_mm * 6.125;
         let paper_width = base_paper_width_px * self.zoom_factor;
 
         // 1. Thermal Printer Slot Dispenser Bar Header
@@ -171,8 +519,11 @@ impl ReceiptViewer {
                     ui.colored_label(Color32::from_rgb(46, 204, 113), "●");
                     ui.label(
                         RichText::new(format!(
-                            "PRINTER DISPENSER | {:?} ({} dots)",
-                            printer_state.paper_width, paper_mm_dots
+                            "{} | {} ({} dots @ {} dpi)",
+                            printer_state.profile.model_name,
+                            printer_state.paper_width,
+                            printer_state.get_printing_width_dots(),
+                            printer_state.dpi
                         ))
                         .color(Color32::from_rgb(200, 205, 220))
                         .small()
@@ -195,9 +546,7 @@ impl ReceiptViewer {
 
         ui.add_space(2.0);
 
-        // 2. Thermal Paper Canvas
-        let paper_fill = Color32::from_rgb(250, 249, 245); // Thermal receipt off-white paper
-        let paper_stroke = Stroke::new(1.0_f32, Color32::from_rgb(220, 218, 210));
+        // 2. Thermal Paper: the rasterized receipt, scaled from dots to screen pixels
         let shadow = if self.show_paper_shadow {
             egui::epaint::Shadow {
                 extrusion: 10.0,
@@ -211,18 +560,11 @@ impl ReceiptViewer {
         };
 
         Frame::none()
-            .fill(paper_fill)
-            .stroke(paper_stroke)
+            .fill(Color32::WHITE)
+            .stroke(Stroke::new(1.0_f32, Color32::from_rgb(220, 218, 210)))
             .shadow(shadow)
-            .rounding(egui::Rounding {
-                nw: 0.0,
-                ne: 0.0,
-                sw: 4.0,
-                se: 4.0,
-            })
-            .inner_margin(Margin::symmetric(20.0 * self.zoom_factor, 16.0 * self.zoom_factor))
             .show(ui, |ui| {
-                ui.set_width(paper_width - 32.0);
+                ui.set_width(paper_width);
 
                 if buffer.is_empty() {
                     ui.add_space(30.0);
@@ -243,23 +585,18 @@ impl ReceiptViewer {
                     return;
                 }
 
-                // Render buffer lines
-                for (line_idx, line) in buffer.iter().enumerate() {
-                    match line {
-                        ReceiptLine::Text(text_line) => {
-                            self.render_text_line(ui, text_line, printer_state);
-                        }
-                        ReceiptLine::Bitmap {
-                            width_px,
-                            height_px,
-                            data,
-                        } => {
-                            self.render_bitmap_line(ui, *width_px, *height_px, data, paper_width);
-                        }
-                        ReceiptLine::Separator => {
-                            self.render_cut_separator(ui, line_idx + 1);
-                        }
-                    }
+                self.update_tiles(ui, printer_state, &buffer);
+                let dots_to_px = paper_width / paper_mm_dots as f32;
+                let paper_top = ui.cursor().top();
+                ui.spacing_mut().item_spacing.y = 0.0;
+                for (texture, rows) in &self.tiles {
+                    ui.image((texture.id(), vec2(paper_width, *rows as f32 * dots_to_px)));
+                }
+
+                // Label / black mark edges, and lines that spill across them. A
+                // single job does not know where on the roll it started.
+                if self.selected_job.is_none() {
+                    self.render_paper_edges(ui, printer_state, paper_top, dots_to_px);
                 }
 
                 // Bottom Paper Margin Feed
@@ -272,142 +609,101 @@ impl ReceiptViewer {
                             .small(),
                     );
                 });
+                ui.add_space(8.0 * self.zoom_factor);
             });
     }
 
-    fn render_text_line(&mut self, ui: &mut Ui, text_line: &TextLine, _printer_state: &PrinterState) {
-        if text_line.text.trim().is_empty() {
-            ui.add_space(6.0 * self.zoom_factor);
+    /// Re-rasterize the receipt when it changed and split it into textures
+    /// no taller than the GPU allows.
+    fn update_tiles(&mut self, ui: &Ui, printer_state: &PrinterState, lines: &[ReceiptLine]) {
+        let revision = Some((printer_state.revision(), self.selected_job));
+        if self.tiles_revision == revision {
             return;
         }
-
-        let font_size = match text_line.font {
-            Font::FontA => 13.5 * self.zoom_factor,
-            Font::FontB => 11.5 * self.zoom_factor,
-            Font::FontC => 10.0 * self.zoom_factor,
-        };
-        let font_size = if text_line.font_size > 12 {
-            font_size * 1.3
-        } else {
-            font_size
-        };
-
-        let ink_color = Color32::from_rgb(18, 18, 22);
-
-        let mut rt = RichText::new(&text_line.text)
-            .monospace()
-            .size(font_size)
-            .color(ink_color);
-
-        if text_line.emphasis {
-            rt = rt.strong();
-        }
-        if text_line.underline {
-            rt = rt.underline();
-        }
-        if text_line.italic {
-            rt = rt.italics();
+        let image = raster::render_lines(printer_state, lines);
+        let tile_rows = ui.ctx().input(|i| i.max_texture_side).min(4096) as u32;
+        let width = image.width() as usize;
+
+        self.tiles.clear();
+        for (index, top) in (0..image.height()).step_by(tile_rows as usize).enumerate() {
+            let rows = tile_rows.min(image.height() - top);
+            let start = top as usize * width * 3;
+            let end = start + rows as usize * width * 3;
+            let color_image = ColorImage::from_rgb([width, rows as usize], &image.as_raw()[start..end]);
+            let texture = ui.ctx().load_texture(
+                format!("receipt_tile_{}", index),
+                color_image,
+                TextureOptions::NEAREST,
+            );
+            self.tiles.push((texture, rows));
         }
+        self.tiles_revision = revision;
+    }
 
-        if text_line.text.contains("[ QR CODE:") {
-            ui.add_space(6.0);
-            ui.vertical_centered(|ui| {
-                Frame::none()
-                    .fill(Color32::WHITE)
-                    .stroke(Stroke::new(1.5_f32, Color32::BLACK))
-                    .inner_margin(Margin::same(8.0))
-                    .show(ui, |ui| {
-                        ui.label(
-                            RichText::new(" 📱 QR CODE ")
-                                .strong()
-                                .monospace()
-                                .color(Color32::BLACK),
-                        );
-                        ui.label(rt);
-                    });
-            });
-            ui.add_space(6.0);
-            return;
+    fn render_paper_edges(&self, ui: &Ui, printer_state: &PrinterState, paper_top: f32, dots_to_px: f32) {
+        let painter = ui.painter().clone();
+        let (left, right) = (ui.min_rect().left(), ui.min_rect().right());
+        let y_of = |dots: u32| paper_top + dots as f32 * dots_to_px;
+        let paper = &printer_state.paper;
+        let total = printer_state.fed_dots();
+        for edge in paper.edges(printer_state.dpi, 0, total + 1) {
+            self.render_paper_edge(&painter, left, right, y_of(edge.position()), &edge, paper.kind);
         }
-
-        match text_line.justification {
-            Justification::Left => {
-                ui.horizontal(|ui| {
-                    ui.label(rt);
-                });
-            }
-            Justification::Center => {
-                ui.vertical_centered(|ui| {
-                    ui.label(rt);
-                });
-            }
-            Justification::Right => {
-                ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
-                    ui.label(rt);
-                });
+        let mut head = 0u32;
+        for line in printer_state.get_buffer() {
+            let line_end = head + printer_state.line_height_dots(line);
+            if !matches!(line, ReceiptLine::Feed { .. }) {
+                for edge in paper.edges(printer_state.dpi, head + 1, line_end) {
+                    self.render_spill_warning(&painter, left, right, y_of(edge.position()), &edge);
+                }
             }
+            head = line_end;
         }
     }
 
-    fn render_bitmap_line(
-        &mut self,
-        ui: &mut Ui,
-        width_px: u32,
-        height_px: u32,
-        data: &[u8],
-        paper_width: f32,
-    ) {
-        let cache_key = hash_bytes(data);
-
-        let texture = self.bitmap_cache.entry(cache_key).or_insert_with(|| {
-            let rgb_image = PrinterState::bitmap_to_rgb(width_px, height_px, data);
-            let size = [rgb_image.width() as usize, rgb_image.height() as usize];
-            let pixels: Vec<Color32> = rgb_image
-                .pixels()
-                .map(|p| Color32::from_rgb(p[0], p[1], p[2]))
-                .collect();
-            let color_image = ColorImage { size, pixels };
-            ui.ctx().load_texture(
-                format!("bitmap_{}", cache_key),
-                color_image,
-                TextureOptions::NEAREST,
-            )
-        });
-
-        let max_display_w = paper_width - 60.0;
-        let scale = (max_display_w / width_px as f32).min(1.5) * self.zoom_factor;
-        let display_size = vec2(width_px as f32 * scale, height_px as f32 * scale);
-
-        ui.vertical_centered(|ui| {
-            ui.add_space(4.0);
-            ui.image((texture.id(), display_size));
-            ui.add_space(4.0);
-        });
+    fn render_paper_edge(&self, painter: &egui::Painter, left: f32, right: f32, y: f32, edge: &PaperEdge, kind: PaperKind) {
+        let edge_color = Color32::from_rgb(52, 152, 219);
+        match edge {
+            PaperEdge::FormTop { index, .. } => {
+                if kind == PaperKind::BlackMark {
+                    let mark = egui::Rect::from_min_size(egui::pos2(left, y - 3.0), vec2(24.0 * self.zoom_factor, 6.0));
+                    painter.rect_filled(mark, 0.0, Color32::BLACK);
+                }
+                painter.line_segment([egui::pos2(left, y), egui::pos2(right, y)], Stroke::new(1.0_f32, edge_color));
+                painter.text(
+                    egui::pos2(right - 4.0, y + 2.0),
+                    egui::Align2::RIGHT_TOP,
+                    format!("▼ {} {}", if kind == PaperKind::Label { "LABEL" } else { "MARK" }, index + 1),
+                    egui::FontId::monospace(10.0),
+                    edge_color,
+                );
+            }
+            PaperEdge::LabelEnd { .. } => {
+                let gap = egui::Rect::from_min_max(egui::pos2(left, y), egui::pos2(right, y + 6.0));
+                painter.rect_filled(gap, 0.0, Color32::from_rgba_unmultiplied(215, 220, 228, 200));
+            }
+        }
     }
 
-    fn render_cut_separator(&mut self, ui: &mut Ui, _line_num: usize) {
-        ui.add_space(8.0 * self.zoom_factor);
-        ui.horizontal(|ui| {
-            let (rect, _) = ui.allocate_exact_size(vec2(ui.available_width(), 2.0), egui::Sense::hover());
-            ui.painter().line_segment(
-                [rect.left_top(), rect.right_top()],
-                Stroke::new(1.0_f32, Color32::from_rgb(180, 180, 190)),
-            );
-        });
-        ui.vertical_centered(|ui| {
-            ui.label(
-                RichText::new("✂ --- CUT PAPER --- ✂")
-                    .small()
-                    .monospace()
-                    .color(Color32::from_rgb(150, 150, 160)),
-            );
-        });
-        ui.add_space(8.0 * self.zoom_factor);
+    fn render_spill_warning(&self, painter: &egui::Painter, left: f32, right: f32, y: f32, edge: &PaperEdge) {
+        let color = Color32::from_rgb(231, 76, 60);
+        painter.line_segment([egui::pos2(left, y), egui::pos2(right, y)], Stroke::new(2.0_f32, color));
+        let what = match edge {
+            PaperEdge::FormTop { .. } => "label / mark edge",
+            PaperEdge::LabelEnd { .. } => "label end",
+        };
+        painter.text(
+            egui::pos2(left + 4.0, y + 2.0),
+            egui::Align2::LEFT_TOP,
+            format!("⚠ content spills across {} at dot {}", what, edge.position()),
+            egui::FontId::monospace(10.0),
+            color,
+        );
     }
 
     fn render_raw_text(&mut self, ui: &mut Ui, state: &EmulatorState) {
         let printer_state = state.get_printer_state();
-        let buffer = printer_state.get_buffer();
+        let buffer = self.selected_lines(state);
 
         if buffer.is_empty() {
             ui.centered_and_justified(|ui| {
@@ -416,11 +712,15 @@ impl ReceiptViewer {
             return;
         }
 
-        let max_chars = printer_state.paper_width.get_max_chars(printer_state.font_size);
+        let max_chars = printer_state.chars_per_line();
 
         ui.group(|ui| {
             ui.horizontal(|ui| {
-                ui.label(format!("📄 Paper: {:?}", printer_state.paper_width));
+                ui.label(format!("🖨️ {}", printer_state.profile.model_name));
+                ui.label(format!(
+                    "📄 Paper: {} ({} chars/line)",
+                    printer_state.paper_width, max_chars
+                ));
                 ui.label(format!("🔤 Font: {:?}", printer_state.current_font));
                 ui.label(format!("📐 Align: {:?}", printer_state.justification));
                 if printer_state.codepage != 0 {
@@ -458,7 +758,44 @@ impl ReceiptViewer {
                             ui.label(format!("[ RASTER BITMAP: {}x{} px ]", width_px, height_px));
                         });
                     }
-                    ReceiptLine::Separator => {
+                    ReceiptLine::Barcode { system, data, .. } => {
+                        ui.horizontal(|ui| {
+                            ui.label(
+                                RichText::new(format!("{:03}", line_num + 1))
+                                    .weak()
+                                    .monospace(),
+                            );
+                            ui.label("│");
+                            ui.label(format!(
+                                "[ BARCODE {}: {} ]",
+                                system.name(),
+                                barcode::hri_text(*system, data)
+                            ));
+                        });
+                    }
+                    ReceiptLine::QrCode { data, .. } => {
+                        ui.horizontal(|ui| {
+                            ui.label(
+                                RichText::new(format!("{:03}", line_num + 1))
+                                    .weak()
+                                    .monospace(),
+                            );
+                            ui.label("│");
+                            ui.label(format!("[ QR CODE: {} ]", String::from_utf8_lossy(data)));
+                        });
+                    }
+                    ReceiptLine::Feed { dots } => {
+                        ui.horizontal(|ui| {
+                            ui.label(
+                                RichText::new(format!("{:03}", line_num + 1))
+                                    .weak()
+                                    .monospace(),
+                            );
+                            ui.label("│");
+                            ui.label(format!("[ FEED: {} dots ]", dots));
+                        });
+                    }
+                    ReceiptLine::Separator(_) => {
                         let sep = "─".repeat(max_chars as usize);
                         ui.horizontal(|ui| {
                             ui.label(
@@ -478,3 +815,11 @@ impl ReceiptViewer {
         });
     }
 }
+
+/// ` as 3 jobs` when a print was split at cuts or idle gaps.
+fn job_count_note(jobs: &[u64]) -> String {
+    match jobs.len() {
+        0 | 1 => String::new(),
+        n => format!(" as {} jobs", n),
+    }
+}
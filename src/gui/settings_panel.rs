@@ -1,7 +1,12 @@
-use crate::emulator::EmulatorState;
+use crate::emulator::session::{start_replay, ReplayHandle};
+use crate::emulator::{EmulatorState, ReplaySpeed, Session};
+use crate::escpos::commands::Font;
+use crate::escpos::paper::{PaperKind, PaperWidth};
+use crate::escpos::profile::PrinterProfile;
 use crate::networking::serial::{list_com_ports, start_serial_listener, SerialHandle};
 use egui::Ui;
 use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
 use std::process::Command;
 use std::sync::Arc;
 use std::time::Duration;
@@ -13,6 +18,10 @@ pub struct SettingsPanel {
     baud_rates: Vec<u32>,
     selected_baud_idx: usize,
     status_message: String,
+    profile_path: String,
+    /// Capture file to record to or replay from
+    session_path: String,
+    replay: Option<ReplayHandle>,
 }
 
 impl Default for SettingsPanel {
@@ -23,6 +32,9 @@ impl Default for SettingsPanel {
             baud_rates: vec![9600, 19200, 38400, 57600, 115200],
             selected_baud_idx: 0,
             status_message: String::new(),
+            profile_path: String::new(),
+            session_path: "session.jsonl".to_string(),
+            replay: None,
         }
     }
 }
@@ -194,15 +206,293 @@ impl SettingsPanel {
 
         ui.separator();
 
+        // Session recording and replay
+        ui.group(|ui| {
+            ui.label("Session Recording");
+            ui.label("Captures every chunk from TCP, HTTP and serial, and the status bytes sent back, with timing");
+
+            ui.horizontal(|ui| {
+                ui.label("Capture file:");
+                ui.text_edit_singleline(&mut self.session_path);
+            });
+
+            ui.horizontal(|ui| {
+                if let Ok(mut state) = emulator_state.try_lock() {
+                    let recording = state.recorder.as_ref().map(|recorder| recorder.event_count());
+                    match recording {
+                        Some(events) => {
+                            if ui.button("⏹ Stop Recording").clicked() {
+                                state.stop_recording();
+                                self.status_message = format!("Recorded {} event(s)", events);
+                            }
+                            ui.colored_label(egui::Color32::RED, format!("● Recording ({} events)", events));
+                        }
+                        None => {
+                            if ui.button("⏺ Record").clicked() {
+                                self.status_message = match state.start_recording(Path::new(self.session_path.trim())) {
+                                    Ok(()) => format!("✅ Recording to {}", self.session_path.trim()),
+                                    Err(e) => format!("❌ {:#}", e),
+                                };
+                            }
+                        }
+                    }
+                }
+
+                ui.separator();
+
+                match self.replay.as_ref().filter(|replay| replay.is_running()) {
+                    Some(replay) => {
+                        let (applied, total) = replay.progress();
+                        if ui.button("⏹ Stop Replay").clicked() {
+                            replay.stop();
+                        }
+                        ui.label(format!("Replaying {}/{} events", applied, total));
+                        ui.ctx().request_repaint_after(Duration::from_millis(200));
+                    }
+                    None => {
+                        if ui.button("▶ Replay Instantly").clicked() {
+                            self.replay_session(ReplaySpeed::Instant, emulator_state, tokio_handle);
+                        }
+                        if ui.button("⏱ Replay at Original Speed").clicked() {
+                            self.replay_session(ReplaySpeed::Original, emulator_state, tokio_handle);
+                        }
+                    }
+                }
+            });
+        });
+
+        ui.separator();
+
+        // Printer model profile
+        ui.group(|ui| {
+            ui.label("Printer Model");
+            ui.label("Sets resolution, line width, fonts, code pages and ignored commands");
+
+            if let Ok(mut state) = emulator_state.try_lock() {
+                let current = state.printer_state.profile.id.clone();
+                let mut selected = current.clone();
+                egui::ComboBox::from_id_source("printer_profile_select")
+                    .selected_text(&state.printer_state.profile.model_name)
+                    .show_ui(ui, |ui| {
+                        for profile in &state.profiles {
+                            let label = format!("{} {}", profile.manufacturer, profile.model_name);
+                            ui.selectable_value(&mut selected, profile.id.clone(), label);
+                        }
+                    });
+                if selected != current {
+                    state.select_profile(&selected);
+                }
+
+                let mut width_mm = state.printer_state.paper_width.width_mm;
+                ui.horizontal(|ui| {
+                    ui.label("Paper width:");
+                    ui.add(
+                        egui::DragValue::new(&mut width_mm)
+                            .speed(0.5)
+                            .clamp_range(PaperWidth::MIN_MM..=PaperWidth::MAX_MM)
+                            .suffix(" mm"),
+                    );
+                });
+                if width_mm != state.printer_state.paper_width.width_mm {
+                    let _ = state.set_paper_width(width_mm);
+                }
+
+                let printer = &state.printer_state;
+                let paper = printer.paper_width;
+                ui.label(format!(
+                    "{} dpi | {} of {} dots printable | {} dots margin",
+                    paper.dpi,
+                    paper.printable_dots(),
+                    paper.width_dots(),
+                    paper.margin_dots()
+                ));
+                ui.label(format!(
+                    "Chars per line: Font A {} | Font B {} | Font C {}",
+                    printer.chars_per_line_for(&Font::FontA),
+                    printer.chars_per_line_for(&Font::FontB),
+                    printer.chars_per_line_for(&Font::FontC)
+                ));
+                let profile = &printer.profile;
+                if !profile.ignored_commands.is_empty() {
+                    ui.label(format!("Ignores: {}", profile.ignored_commands.join(", ")));
+                }
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Profile JSON:");
+                ui.text_edit_singleline(&mut self.profile_path);
+                if ui.button("📂 Load").clicked() {
+                    self.load_profile(emulator_state);
+                }
+            });
+        });
+
+        ui.separator();
+
+        // Parser limits
+        ui.group(|ui| {
+            ui.label("Parser Limits");
+            ui.label("Oversized commands are rejected and reported in the Commands tab");
+
+            if let Ok(mut state) = emulator_state.try_lock() {
+                let state = &mut *state;
+                let limits = &mut state.parser_limits;
+                egui::Grid::new("parser_limits").num_columns(2).show(ui, |ui| {
+                    ui.label("Max image size:");
+                    ui.add(egui::DragValue::new(&mut limits.max_raster_bytes).speed(1024.0).suffix(" bytes"));
+                    ui.end_row();
+
+                    ui.label("Max pending buffer:");
+                    ui.add(egui::DragValue::new(&mut limits.max_pending_bytes).speed(1024.0).suffix(" bytes"));
+                    ui.end_row();
+
+                    ui.label("Max job size:");
+                    ui.add(egui::DragValue::new(&mut limits.max_job_bytes).speed(1024.0).suffix(" bytes"));
+                    ui.end_row();
+
+                    ui.label("Commands kept per job:");
+                    ui.add(egui::DragValue::new(&mut state.max_job_commands).speed(100.0));
+                    ui.end_row();
+
+                    ui.label("Command bytes kept per job:");
+                    ui.add(egui::DragValue::new(&mut state.max_job_command_bytes).speed(1024.0).suffix(" bytes"));
+                    ui.end_row();
+                });
+            }
+            ui.label("Limits apply to new connections");
+        });
+
+        ui.separator();
+
+        // Job splitting
+        ui.group(|ui| {
+            ui.label("Print Jobs");
+            ui.label("Where the stream is divided into the jobs listed beside the receipt");
+
+            if let Ok(mut state) = emulator_state.try_lock() {
+                let before = (state.job_policy.clone(), state.max_jobs);
+                let policy = &mut state.job_policy;
+                ui.checkbox(&mut policy.on_cut, "New job after every cut");
+                ui.checkbox(&mut policy.on_connection, "New job per TCP / HTTP connection");
+                ui.horizontal(|ui| {
+                    let mut idle = policy.idle_timeout_secs.is_some();
+                    ui.checkbox(&mut idle, "New job after");
+                    let mut secs = policy.idle_timeout_secs.unwrap_or(10);
+                    ui.add_enabled(idle, egui::DragValue::new(&mut secs).clamp_range(1..=3600).suffix(" s"));
+                    ui.label("without data");
+                    policy.idle_timeout_secs = if idle { Some(secs) } else { None };
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Keep up to");
+                    ui.add(egui::DragValue::new(&mut state.max_jobs).clamp_range(1..=10000));
+                    ui.label("jobs (pinned jobs are always kept)");
+                });
+                if (state.job_policy.clone(), state.max_jobs) != before {
+                    state.status_changed();
+                }
+            }
+        });
+        ui.separator();
+
+        // Paper stock
+        ui.group(|ui| {
+            ui.label("Paper Stock");
+            ui.label("Black mark and label paper, used by GS FF and FS ( L feeds");
+
+            if let Ok(mut state) = emulator_state.try_lock() {
+                let paper = &mut state.printer_state.paper;
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut paper.kind, PaperKind::Continuous, "Continuous");
+                    ui.selectable_value(&mut paper.kind, PaperKind::BlackMark, "Black mark");
+                    ui.selectable_value(&mut paper.kind, PaperKind::Label, "Label");
+                });
+
+                egui::Grid::new("paper_stock").num_columns(2).show(ui, |ui| {
+                    match paper.kind {
+                        PaperKind::Continuous => {}
+                        PaperKind::BlackMark => {
+                            ui.label("Mark pitch:");
+                            ui.add(egui::DragValue::new(&mut paper.mark_pitch_mm).speed(0.5).clamp_range(5.0..=1000.0).suffix(" mm"));
+                            ui.end_row();
+
+                            ui.label("Mark length:");
+                            ui.add(egui::DragValue::new(&mut paper.mark_length_mm).speed(0.1).clamp_range(1.0..=50.0).suffix(" mm"));
+                            ui.end_row();
+                        }
+                        PaperKind::Label => {
+                            ui.label("Label length:");
+                            ui.add(egui::DragValue::new(&mut paper.label_length_mm).speed(0.5).clamp_range(5.0..=1000.0).suffix(" mm"));
+                            ui.end_row();
+
+                            ui.label("Gap:");
+                            ui.add(egui::DragValue::new(&mut paper.label_gap_mm).speed(0.1).clamp_range(0.0..=50.0).suffix(" mm"));
+                            ui.end_row();
+                        }
+                    }
+
+                    if paper.kind != PaperKind::Continuous {
+                        ui.label("Print start (GS ( F):");
+                        ui.add(egui::DragValue::new(&mut paper.print_start_offset).suffix(" dots"));
+                        ui.end_row();
+
+                        ui.label("Cut position (GS ( F):");
+                        ui.add(egui::DragValue::new(&mut paper.cut_offset).suffix(" dots"));
+                        ui.end_row();
+                    }
+                });
+
+                if let Some(dots) = state.printer_state.dots_to_next_mark() {
+                    ui.label(format!("Next mark / label: {} dots ahead of the print head", dots));
+                }
+            }
+        });
+
+        ui.separator();
+
         ui.group(|ui| {
             ui.label("ℹ️ Automatic Operation");
             ui.label("• The emulator automatically respects ESC/POS standards");
-            ui.label("• Paper width: 50mm, 78mm, 80mm (auto-detection)");
+            ui.label("• Paper width: any roll from 20mm to 120mm, line width from the printer model");
             ui.label("• Font, justification, emphasis: ESC/POS commands");
             ui.label("• No manual configuration needed!");
         });
     }
 
+    fn replay_session(
+        &mut self,
+        speed: ReplaySpeed,
+        emulator_state: &Arc<Mutex<EmulatorState>>,
+        tokio_handle: &tokio::runtime::Handle,
+    ) {
+        let path = Path::new(self.session_path.trim());
+        self.status_message = match Session::load(path) {
+            Ok(session) => {
+                let message = format!(
+                    "✅ Replaying {} event(s), {:.1}s recorded",
+                    session.events.len(),
+                    session.duration().as_secs_f32()
+                );
+                self.replay = Some(start_replay(session, speed, Arc::clone(emulator_state), tokio_handle));
+                message
+            }
+            Err(e) => format!("❌ {:#}", e),
+        };
+    }
+
+    fn load_profile(&mut self, emulator_state: &Arc<Mutex<EmulatorState>>) {
+        match PrinterProfile::load_from_file(Path::new(self.profile_path.trim())) {
+            Ok(profile) => {
+                if let Ok(mut state) = emulator_state.try_lock() {
+                    let id = profile.id.clone();
+                    self.status_message = format!("✅ Loaded profile {}", profile.model_name);
+                    state.add_profile(profile);
+                    state.select_profile(&id);
+                }
+            }
+            Err(e) => self.status_message = format!("❌ {:#}", e),
+        }
+    }
+
     fn install_windows_printer(&mut self) {
         let output = Command::new("powershell")
             .args([
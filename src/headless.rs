@@ -0,0 +1,143 @@
+//! Running the emulator without the GUI, for CI agents and containers.
+
+use crate::emulator::{EmulatorState, RetentionPolicy, MACRO_WAIT_STEP};
+use crate::networking::serial::start_serial_listener;
+use crate::networking::{api, server};
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{watch, Mutex};
+use tracing::info;
+
+#[derive(Debug, Clone)]
+pub struct HeadlessOptions {
+    /// Listener address; port 0 picks a free port
+    pub bind: String,
+    /// Serial port name and baud rate to listen on as well
+    pub serial: Option<(String, u32)>,
+    /// Archive finished jobs here
+    pub archive: Option<PathBuf>,
+    /// Also serve the HTTP control API on this address
+    pub api: Option<String>,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        Self {
+            bind: server::DEFAULT_ADDR.to_string(),
+            serial: None,
+            archive: None,
+            api: None,
+        }
+    }
+}
+
+/// Serve until SIGTERM or Ctrl+C, let both listeners finish the connections
+/// and requests in flight, then close and archive the active job.
+///
+/// Once listening, prints `Listening on <addr>` on its own line to stdout so
+/// a test harness that bound port 0 can find the port, and `API listening on
+/// <addr>` after it when the control API is enabled.
+pub async fn run(emulator_state: Arc<Mutex<EmulatorState>>, options: HeadlessOptions) -> Result<()> {
+    {
+        let mut state = emulator_state.lock().await;
+        // Nothing shows the paper roll; finished jobs keep their own lines
+        state.trim_printed_lines = true;
+        if let Some(dir) = &options.archive {
+            state.open_archive(dir, RetentionPolicy::default())?;
+        }
+    }
+
+    // Both listeners stop on the same signal and finish what is in flight
+    let (stop, stopped) = watch::channel(false);
+    let stop_requested = move || {
+        let mut stopped = stopped.clone();
+        async move {
+            let _ = stopped.wait_for(|stopped| *stopped).await;
+        }
+    };
+
+    let listener = server::bind(&options.bind).await?;
+    let addr: SocketAddr = listener.local_addr()?;
+    let mut stdout = std::io::stdout();
+    writeln!(stdout, "Listening on {}", addr)?;
+    let api_server = match &options.api {
+        Some(api_addr) => {
+            let api_listener = api::bind_api(api_addr).await?;
+            writeln!(stdout, "API listening on {}", api_listener.local_addr()?)?;
+            Some(tokio::spawn(api::serve_api_until(
+                api_listener,
+                Arc::clone(&emulator_state),
+                stop_requested(),
+            )))
+        }
+        None => None,
+    };
+    stdout.flush()?;
+
+    let serial = match &options.serial {
+        Some((port, baud_rate)) => Some(
+            start_serial_listener(port.clone(), *baud_rate, Arc::clone(&emulator_state), tokio::runtime::Handle::current())
+                .with_context(|| format!("Failed to start serial listener on {}", port))?,
+        ),
+        None => None,
+    };
+
+    let server = server::serve_until(listener, Arc::clone(&emulator_state), stop_requested());
+    tokio::pin!(server);
+    // The GUI closes idle jobs and runs waiting macros as it repaints;
+    // without it, poll
+    let mut idle_check = tokio::time::interval(MACRO_WAIT_STEP);
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    let mut result = loop {
+        tokio::select! {
+            result = &mut server => break result,
+            _ = &mut shutdown, if !*stop.borrow() => {
+                info!("Shutting down");
+                let _ = stop.send(true);
+            }
+            _ = idle_check.tick() => emulator_state.lock().await.finish_idle_jobs(),
+        }
+    };
+    // The raw listener only stops on its own after an error
+    let _ = stop.send(true);
+
+    if let Some(serial) = serial {
+        serial.stop();
+    }
+    if let Some(api_server) = api_server {
+        let api_result = api_server.await.context("API task failed").and_then(|result| result);
+        result = result.and(api_result);
+    }
+    let mut state = emulator_state.lock().await;
+    state.finish_jobs();
+    state.stop_recording();
+    info!("Stopped after {} job(s)", state.jobs.len());
+    result
+}
+
+/// SIGTERM as sent by `docker stop` and CI runners, or Ctrl+C.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
@@ -1,7 +1,12 @@
 pub mod emulator;
 pub mod escpos;
+pub mod export;
 pub mod gui;
+pub mod headless;
 pub mod networking;
+pub mod query;
+pub mod render;
+pub mod snapshot;
 
 pub use emulator::EmulatorState;
 pub use escpos::commands::EscPosCommand;
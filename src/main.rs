@@ -1,26 +1,211 @@
-use escpos_emulator::emulator::EmulatorState;
+use clap::{Args, Parser, Subcommand};
+use escpos_emulator::emulator::{EmulatorState, RetentionPolicy};
+use escpos_emulator::escpos::diagnostics::Severity;
 use escpos_emulator::gui::EscPosEmulatorApp;
-use escpos_emulator::networking::server;
+use escpos_emulator::headless::{self, HeadlessOptions};
+use escpos_emulator::networking::{api, server};
+use escpos_emulator::render::{self, OutputFormat, RenderOptions};
+use escpos_emulator::snapshot::{self, SnapshotOptions};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::{info, Level};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::prelude::*;
+
+/// Exit status of `render` when the input had warnings.
+const EXIT_WARNINGS: u8 = 3;
+/// Exit status of `render` when the input had errors.
+const EXIT_ERRORS: u8 = 4;
+/// Exit status of `snapshot` when a reference did not match.
+const EXIT_MISMATCH: u8 = 5;
+
+/// ESC/POS virtual printer. Opens the GUI unless a subcommand or
+/// `--headless` is given.
+#[derive(Parser)]
+#[command(name = "escpos_emulator", version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Run the TCP server without a window until SIGTERM or Ctrl+C
+    #[arg(long)]
+    headless: bool,
+
+    /// Listen address; port 0 picks a free port
+    #[arg(long, value_name = "ADDR", default_value = server::DEFAULT_ADDR, requires = "headless")]
+    bind: String,
+
+    /// Also listen on a serial port
+    #[arg(long, value_name = "PORT", requires = "headless")]
+    serial: Option<String>,
+
+    /// Serial baud rate
+    #[arg(long, value_name = "RATE", default_value_t = 9600, requires = "serial")]
+    baud: u32,
+
+    /// Archive finished jobs in this directory
+    #[arg(long, value_name = "DIR", requires = "headless")]
+    archive: Option<PathBuf>,
+
+    /// Serve the HTTP control API (status, jobs, print, settings)
+    #[arg(long, value_name = "ADDR", num_args = 0..=1, default_missing_value = api::DEFAULT_API_ADDR)]
+    api: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Render an ESC/POS file to PNG, PDF, TXT, HTML, SVG or JSON.
+    ///
+    /// Exits with 0 on a clean parse, 3 if there were warnings, 4 if there
+    /// were errors (the output is still written) and 1 if rendering failed.
+    Render(RenderArgs),
+    /// Render an ESC/POS file and compare it with reference snapshots.
+    ///
+    /// The extension of each reference picks the comparison: .png pixel by
+    /// pixel, .txt as text, .json as a job document without times. Missing
+    /// references are created unless CI is set. Exits with 0 when all match,
+    /// 5 on a mismatch and 1 if rendering failed.
+    Snapshot(SnapshotArgs),
+}
+
+/// Printer setup shared by the subcommands.
+#[derive(Args)]
+struct PrinterArgs {
+    /// Paper width, e.g. 80mm or 58
+    #[arg(long, value_name = "WIDTH", value_parser = parse_paper_width)]
+    paper: Option<f32>,
+
+    /// Printer profile id, e.g. tm-t88vi
+    #[arg(long, value_name = "ID")]
+    profile: Option<String>,
+
+    /// Directory of extra printer profile JSON files
+    #[arg(long, value_name = "DIR", default_value = "profiles")]
+    profiles: PathBuf,
+}
+
+impl PrinterArgs {
+    fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            profile: self.profile.clone(),
+            paper_width_mm: self.paper,
+            profile_dir: Some(self.profiles.clone()),
+        }
+    }
+}
+
+#[derive(Args)]
+struct RenderArgs {
+    /// ESC/POS data, e.g. a .bin or .prn capture
+    input: PathBuf,
+
+    /// Output file; the extension picks the format
+    #[arg(short, long, value_name = "FILE")]
+    output: PathBuf,
+
+    /// Pixels per printer dot in PNG output
+    #[arg(long, default_value_t = 1)]
+    scale: u32,
+
+    #[command(flatten)]
+    printer: PrinterArgs,
+}
+
+#[derive(Args)]
+struct SnapshotArgs {
+    /// ESC/POS data, e.g. a .bin or .prn capture
+    input: PathBuf,
+
+    /// Reference .png, .txt or .json file; may be given more than once
+    #[arg(short, long = "reference", value_name = "FILE", required = true)]
+    references: Vec<PathBuf>,
+
+    /// Overwrite the references with the current output
+    #[arg(long)]
+    update: bool,
+
+    /// Largest per-channel difference for pixels to count as equal
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    tolerance: u8,
+
+    /// Differing pixels allowed per image
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    max_diff_pixels: u64,
+
+    #[command(flatten)]
+    printer: PrinterArgs,
+}
 
 #[tokio::main]
-async fn main() -> Result<(), eframe::Error> {
-    tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
-        .init();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match &cli.command {
+        Some(Command::Render(args)) => {
+            init_batch_tracing();
+            render(args)
+        }
+        Some(Command::Snapshot(args)) => {
+            init_batch_tracing();
+            snapshot(args)
+        }
+        None => {
+            tracing_subscriber::fmt()
+                .with_max_level(Level::INFO)
+                .init();
+            run_emulator(&cli).await
+        }
+    }
+}
 
+async fn run_emulator(cli: &Cli) -> ExitCode {
     info!("🚀 Starting ESC/POS Emulator...");
 
-    let emulator_state = Arc::new(Mutex::new(EmulatorState::new()));
+    let mut state = EmulatorState::new();
+    let loaded = state.load_profiles_from_dir(Path::new("profiles"));
+    if loaded > 0 {
+        info!("Loaded {} printer profile(s) from ./profiles", loaded);
+    }
+    if let Ok(dir) = std::env::var("ESCPOS_ARCHIVE_DIR") {
+        match state.open_archive(Path::new(&dir), RetentionPolicy::default()) {
+            Ok(()) => info!("Archiving finished jobs to {}", dir),
+            Err(e) => eprintln!("❌ Job archive disabled: {:#}", e),
+        }
+    }
+    let emulator_state = Arc::new(Mutex::new(state));
+
+    let result = if cli.headless {
+        let options = HeadlessOptions {
+            bind: cli.bind.clone(),
+            serial: cli.serial.clone().map(|port| (port, cli.baud)),
+            archive: cli.archive.clone(),
+            api: cli.api.clone(),
+        };
+        headless::run(emulator_state, options).await
+    } else {
+        run_gui(emulator_state, cli.api.as_deref()).await
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("❌ {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
 
+async fn run_gui(emulator_state: Arc<Mutex<EmulatorState>>, api_addr: Option<&str>) -> anyhow::Result<()> {
     let server_state = emulator_state.clone();
     tokio::spawn(async move {
         if let Err(e) = server::start_server(server_state).await {
             eprintln!("❌ Server error: {}", e);
         }
     });
+    if let Some(addr) = api_addr {
+        let listener = api::bind_api(addr).await?;
+        tokio::spawn(api::serve_api(listener, emulator_state.clone()));
+    }
 
     let tokio_handle = tokio::runtime::Handle::current();
 
@@ -33,4 +218,87 @@ async fn main() -> Result<(), eframe::Error> {
         options,
         Box::new(move |_cc| Box::new(EscPosEmulatorApp::new(emulator_state, tokio_handle))),
     )
+    .map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+/// Keep stdout free for scripts; only problems are worth reporting, and
+/// parse diagnostics are printed once by the subcommands themselves.
+fn init_batch_tracing() {
+    let filter = Targets::new()
+        .with_default(Level::WARN)
+        .with_target("escpos_emulator::emulator", LevelFilter::OFF);
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(filter)
+        .init();
+}
+
+fn render(args: &RenderArgs) -> ExitCode {
+    let options = args.printer.render_options();
+    let result = OutputFormat::from_path(&args.output, args.scale).and_then(|format| {
+        let job = render::render_file(&args.input, &options)?;
+        job.save(&args.output, format)?;
+        Ok(job)
+    });
+    let job = match result {
+        Ok(job) => job,
+        Err(e) => {
+            eprintln!("❌ {:#}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    for diagnostic in job.diagnostics() {
+        eprintln!("{}", diagnostic);
+    }
+    match job.worst_severity() {
+        Some(Severity::Error) => ExitCode::from(EXIT_ERRORS),
+        Some(Severity::Warning) => ExitCode::from(EXIT_WARNINGS),
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+fn snapshot(args: &SnapshotArgs) -> ExitCode {
+    let job = match render::render_file(&args.input, &args.printer.render_options()) {
+        Ok(job) => job,
+        Err(e) => {
+            eprintln!("❌ {:#}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    for diagnostic in job.diagnostics() {
+        eprintln!("{}", diagnostic);
+    }
+    let mut options = SnapshotOptions {
+        channel_tolerance: args.tolerance,
+        max_diff_pixels: args.max_diff_pixels,
+        ..SnapshotOptions::default()
+    };
+    options.update |= args.update;
+
+    let mut status = ExitCode::SUCCESS;
+    for reference in &args.references {
+        match snapshot::check_snapshot(&job, reference, &options) {
+            Ok(outcome) if outcome.passed() => println!("✅ {} {}", reference.display(), outcome),
+            Ok(outcome) => {
+                println!("❌ {} {}", reference.display(), outcome);
+                if status == ExitCode::SUCCESS {
+                    status = ExitCode::from(EXIT_MISMATCH);
+                }
+            }
+            Err(e) => {
+                eprintln!("❌ {:#}", e);
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}
+
+/// `80mm`, `80 mm` or `80`.
+fn parse_paper_width(text: &str) -> Result<f32, String> {
+    text.trim()
+        .trim_end_matches("mm")
+        .trim()
+        .parse()
+        .map_err(|_| format!("expected a width in millimetres such as 80mm, got {:?}", text))
 }
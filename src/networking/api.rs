@@ -0,0 +1,416 @@
+//! HTTP control API for automation: status, jobs, rendering and settings.
+//!
+//! Runs on its own port, apart from the printer port, so browser test suites
+//! can print from the page under test and check the result without the GUI.
+//! Every response allows any origin.
+//!
+//! | Route | |
+//! |---|---|
+//! | `GET /status` | Printer model, paper, job and command counts |
+//! | `GET /jobs` | Summaries of the jobs, oldest first |
+//! | `GET /jobs/{id}` | The job as a JSON job document |
+//! | `GET /jobs/{id}.png`, `.txt`, `.pdf`, `.html`, `.svg` | The job rendered; PNG takes `?scale=N` |
+//! | `DELETE /jobs` | Drop every job and the printed receipt |
+//! | `POST /print` | Print the raw ESC/POS request body as one job |
+//! | `POST /settings` | Change profile, paper width, job splitting or job limit |
+//! | `GET /events` | WebSocket feed of commands, jobs and status, see `live` |
+//! | `GET /` | Live receipt page subscribed to the feed |
+
+use crate::emulator::{EmulatorState, JobSource, JobSplitPolicy, PrintJob};
+use crate::escpos::paper::PaperWidth;
+use crate::export::{self, ExportFormat, JobDocument, PdfScope};
+use crate::networking::live::{self, FeedShutdown};
+use anyhow::{bail, Context, Result};
+use axum::body::Bytes;
+use axum::extract::{ConnectInfo, DefaultBodyLimit, Path, Query, Request, State};
+use axum::http::header::{
+    ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE,
+};
+use axum::http::{HeaderValue, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{watch, Mutex};
+use tracing::info;
+
+/// Address the control API listens on unless configured otherwise.
+pub const DEFAULT_API_ADDR: &str = "127.0.0.1:9180";
+
+pub(crate) type SharedState = Arc<Mutex<EmulatorState>>;
+
+#[derive(Debug, Serialize)]
+pub struct ApiStatus {
+    pub version: String,
+    pub profile: String,
+    pub model: String,
+    pub paper_width_mm: f32,
+    pub dpi: u32,
+    pub printable_width_dots: u32,
+    pub job_count: usize,
+    pub active_job: Option<u64>,
+    pub command_count: usize,
+    pub diagnostic_count: usize,
+    pub job_policy: JobSplitPolicy,
+    pub max_jobs: usize,
+    pub recording: bool,
+    pub archiving: bool,
+    /// A GS ^ macro waits for `POST /feed`
+    pub waiting_for_feed_button: bool,
+}
+
+impl ApiStatus {
+    pub fn of(state: &EmulatorState) -> Self {
+        let printer = state.get_printer_state();
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            profile: printer.profile.id.clone(),
+            model: printer.profile.model_name.clone(),
+            paper_width_mm: printer.paper_width.width_mm,
+            dpi: printer.dpi,
+            printable_width_dots: printer.get_printing_width_dots(),
+            job_count: state.jobs.len(),
+            active_job: state.active_job().map(|job| job.id),
+            command_count: state.get_command_history().len(),
+            diagnostic_count: state.get_diagnostics().len(),
+            job_policy: state.job_policy.clone(),
+            max_jobs: state.max_jobs,
+            recording: state.recorder.is_some(),
+            archiving: state.archive.is_some(),
+            waiting_for_feed_button: state.waiting_for_feed_button(),
+        }
+    }
+}
+
+/// One entry of `GET /jobs`.
+#[derive(Debug, Serialize)]
+pub struct JobSummary {
+    pub id: u64,
+    /// e.g. `tcp 192.168.1.20:51234`
+    pub source: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub byte_count: u64,
+    pub command_count: usize,
+    pub line_count: usize,
+    pub diagnostic_count: usize,
+    pub active: bool,
+    pub pinned: bool,
+}
+
+impl JobSummary {
+    pub fn of(state: &EmulatorState, job: &PrintJob) -> Self {
+        Self {
+            id: job.id,
+            source: job.source.to_string(),
+            started_at: job.started_at.into(),
+            ended_at: job.ended_at.into(),
+            byte_count: job.byte_count,
+            command_count: job.command_count,
+            line_count: state.job_lines(job).len(),
+            diagnostic_count: job.diagnostics.len(),
+            active: job.active,
+            pinned: job.pinned,
+        }
+    }
+}
+
+/// Body of `POST /settings`; absent fields are left as they are.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SettingsUpdate {
+    /// Printer profile id, e.g. `tm-t88vi`
+    pub profile: Option<String>,
+    pub paper_width_mm: Option<f32>,
+    pub job_policy: Option<JobSplitPolicy>,
+    pub max_jobs: Option<usize>,
+}
+
+impl SettingsUpdate {
+    /// Apply every field or, if one is invalid, none.
+    pub fn apply(&self, state: &mut EmulatorState) -> Result<()> {
+        if let Some(profile) = &self.profile {
+            if !state.profiles.iter().any(|p| p.id == *profile) {
+                let known: Vec<&str> = state.profiles.iter().map(|p| p.id.as_str()).collect();
+                bail!("Unknown printer profile {:?} (known: {})", profile, known.join(", "));
+            }
+        }
+        if let Some(width_mm) = self.paper_width_mm {
+            if !(PaperWidth::MIN_MM..=PaperWidth::MAX_MM).contains(&width_mm) {
+                bail!("Paper width {}mm is outside {}..{}mm", width_mm, PaperWidth::MIN_MM, PaperWidth::MAX_MM);
+            }
+        }
+        if let Some(profile) = &self.profile {
+            state.select_profile(profile);
+        }
+        // After the profile, which may snap the width to its rolls
+        if let Some(width_mm) = self.paper_width_mm {
+            state.set_paper_width(width_mm)?;
+        }
+        if let Some(policy) = &self.job_policy {
+            state.job_policy = policy.clone();
+        }
+        if let Some(max_jobs) = self.max_jobs {
+            state.max_jobs = max_jobs;
+        }
+        // The profile and paper setters announce their own changes
+        if self.job_policy.is_some() || self.max_jobs.is_some() {
+            state.status_changed();
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RenderQuery {
+    scale: Option<u32>,
+}
+
+/// An error answered as `{"error": "..."}`.
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn bad_request(error: anyhow::Error) -> Self {
+        ApiError(StatusCode::BAD_REQUEST, format!("{:#}", error))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// The API routes. Request bodies over `max_body_bytes`, normally the
+/// emulator's job size limit, are refused.
+pub fn router(emulator_state: SharedState, max_body_bytes: usize) -> Router {
+    Router::new()
+        .route("/status", get(status))
+        .route("/jobs", get(list_jobs).delete(clear_jobs))
+        .route("/jobs/{id}", get(get_job))
+        .route("/print", post(print))
+        .route("/settings", post(update_settings))
+        .route("/feed", post(press_feed))
+        .route("/events", get(live::events))
+        .route("/", get(live::page))
+        .layer(DefaultBodyLimit::max(max_body_bytes))
+        .layer(middleware::from_fn(cors))
+        .with_state(emulator_state)
+}
+
+/// Bind the API listener; port 0 picks a free port.
+pub async fn bind_api(addr: &str) -> Result<TcpListener> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    info!("Control API listening on http://{}", listener.local_addr()?);
+    Ok(listener)
+}
+
+pub async fn serve_api(listener: TcpListener, emulator_state: SharedState) -> Result<()> {
+    serve_api_until(listener, emulator_state, std::future::pending()).await
+}
+
+/// Serve until `shutdown` completes, then finish the requests in flight.
+pub async fn serve_api_until(
+    listener: TcpListener,
+    emulator_state: SharedState,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    let (stop_feeds, feeds_stopped) = watch::channel(false);
+    // The printer port takes jobs up to this size as well
+    let max_body_bytes = emulator_state.lock().await.parser_limits.max_job_bytes;
+    let app = router(emulator_state, max_body_bytes)
+        .layer(Extension(FeedShutdown(feeds_stopped)))
+        .into_make_service_with_connect_info::<SocketAddr>();
+    // Live feeds never end on their own, so close them before waiting for
+    // the connections to finish
+    let shutdown = async move {
+        shutdown.await;
+        let _ = stop_feeds.send(true);
+    };
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await
+        .context("Control API failed")
+}
+
+async fn cors(request: Request, next: Next) -> Response {
+    let mut response = match *request.method() {
+        // Preflight requests from the page under test
+        Method::OPTIONS => StatusCode::NO_CONTENT.into_response(),
+        _ => next.run(request).await,
+    };
+    let headers = response.headers_mut();
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    headers.insert(ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("GET, POST, DELETE, OPTIONS"));
+    headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_static("*"));
+    headers.insert("access-control-allow-private-network", HeaderValue::from_static("true"));
+    response
+}
+
+async fn status(State(emulator_state): State<SharedState>) -> Json<ApiStatus> {
+    Json(ApiStatus::of(&*emulator_state.lock().await))
+}
+
+async fn list_jobs(State(emulator_state): State<SharedState>) -> Json<Vec<JobSummary>> {
+    let state = emulator_state.lock().await;
+    Json(state.jobs.iter().map(|job| JobSummary::of(&state, job)).collect())
+}
+
+async fn clear_jobs(State(emulator_state): State<SharedState>) -> Json<serde_json::Value> {
+    let deleted = emulator_state.lock().await.clear_jobs();
+    Json(json!({ "deleted": deleted }))
+}
+
+async fn press_feed(State(emulator_state): State<SharedState>) -> Json<serde_json::Value> {
+    let mut state = emulator_state.lock().await;
+    let pressed = state.waiting_for_feed_button();
+    state.press_feed_button();
+    Json(json!({ "pressed": pressed }))
+}
+
+/// `{id}` is the job id, optionally with the extension of a render format.
+async fn get_job(
+    State(emulator_state): State<SharedState>,
+    Path(name): Path<String>,
+    Query(query): Query<RenderQuery>,
+) -> Result<Response, ApiError> {
+    let (id, extension) = match name.split_once('.') {
+        Some((id, extension)) => (id, extension),
+        None => (name.as_str(), "json"),
+    };
+    let id: u64 = id
+        .parse()
+        .map_err(|_| ApiError(StatusCode::BAD_REQUEST, format!("Invalid job id {:?}", id)))?;
+    let (format, content_type) = match extension {
+        "json" => (None, "application/json"),
+        "png" => (Some(ExportFormat::Png { scale: query.scale.unwrap_or(1).clamp(1, 8) }), "image/png"),
+        "txt" => (Some(ExportFormat::Text), "text/plain; charset=utf-8"),
+        // Every receipt of the job, one page per cut
+        "pdf" => (Some(ExportFormat::Pdf(PdfScope::Session)), "application/pdf"),
+        "html" => (Some(ExportFormat::Html), "text/html; charset=utf-8"),
+        "svg" => (Some(ExportFormat::Svg), "image/svg+xml"),
+        _ => {
+            return Err(ApiError(
+                StatusCode::NOT_FOUND,
+                format!("Unknown job format .{} (use .json, .png, .txt, .pdf, .html or .svg)", extension),
+            ))
+        }
+    };
+
+    let mut state = emulator_state.lock().await;
+    let job = state
+        .get_job(id)
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("No job #{}", id)))?;
+    let body = match format {
+        None => JobDocument::from_job(&state, job).to_json().into_bytes(),
+        Some(format) => {
+            // Encoding can take a while; copy the job out so printing goes on meanwhile
+            let lines = state.job_lines(job).into_owned();
+            let printer = state.printer_settings();
+            drop(state);
+            tokio::task::spawn_blocking(move || export::encode_lines(&printer, &lines, format))
+                .await
+                .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+                .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?
+        }
+    };
+    Ok(([(CONTENT_TYPE, content_type)], body).into_response())
+}
+
+/// Print the body as one connection from the client.
+async fn print(
+    State(emulator_state): State<SharedState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    body: Bytes,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let mut state = emulator_state.lock().await;
+    let jobs = state.print_bytes(&JobSource::Http(peer.to_string()), &body);
+    // Nobody to answer status queries to
+    state.take_responses();
+    match jobs.first() {
+        Some(first) => Ok(Json(json!({ "job_id": first, "job_ids": jobs }))),
+        None => Err(ApiError(StatusCode::UNPROCESSABLE_ENTITY, "The body contains no printable data".to_string())),
+    }
+}
+
+/// The body is parsed here rather than by `Json`, so mistakes are reported
+/// as JSON like every other error.
+async fn update_settings(State(emulator_state): State<SharedState>, body: Bytes) -> Result<Json<ApiStatus>, ApiError> {
+    let update: SettingsUpdate = serde_json::from_slice(&body)
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, format!("Invalid settings: {}", e)))?;
+    let mut state = emulator_state.lock().await;
+    update.apply(&mut state).map_err(ApiError::bad_request)?;
+    Ok(Json(ApiStatus::of(&state)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    /// Start the API on a free port; it stops when the test's runtime ends.
+    async fn start(state: EmulatorState) -> SocketAddr {
+        let listener = bind_api("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_api(listener, Arc::new(Mutex::new(state))));
+        addr
+    }
+
+    /// Send one request and return the status code and body.
+    async fn request(addr: SocketAddr, method: &str, path: &str, body: &[u8]) -> (u16, Vec<u8>) {
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        let head = format!(
+            "{} {} HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            method,
+            path,
+            body.len()
+        );
+        socket.write_all(head.as_bytes()).await.unwrap();
+        socket.write_all(body).await.unwrap();
+        let mut response = Vec::new();
+        socket.read_to_end(&mut response).await.unwrap();
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let status = std::str::from_utf8(&response[9..12]).unwrap().parse().unwrap();
+        (status, response[split + 4..].to_vec())
+    }
+
+    #[tokio::test]
+    async fn print_bodies_over_the_configured_job_limit_are_refused() {
+        let mut state = EmulatorState::new();
+        state.parser_limits.max_job_bytes = 64;
+        let addr = start(state).await;
+
+        let (status, _) = request(addr, "POST", "/print", &[b'A'; 65]).await;
+        assert_eq!(status, 413);
+        let (status, body) = request(addr, "POST", "/print", b"HELLO\n\x1dV\x00WORLD\n").await;
+        assert_eq!(status, 200);
+        let reply: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(reply["job_id"], 1);
+        assert_eq!(reply["job_ids"], json!([1, 2]));
+    }
+
+    #[tokio::test]
+    async fn rendered_jobs_are_encoded_in_the_requested_format() {
+        let addr = start(EmulatorState::new()).await;
+        request(addr, "POST", "/print", b"HELLO\n\x1dV\x00").await;
+
+        let (status, png) = request(addr, "GET", "/jobs/1.png?scale=2", b"").await;
+        assert_eq!(status, 200);
+        assert!(png.starts_with(b"\x89PNG"));
+        let (status, text) = request(addr, "GET", "/jobs/1.txt", b"").await;
+        assert_eq!(status, 200);
+        assert!(String::from_utf8(text).unwrap().contains("HELLO"));
+        let (status, _) = request(addr, "GET", "/jobs/9.png", b"").await;
+        assert_eq!(status, 404);
+    }
+}
@@ -0,0 +1,171 @@
+//! WebSocket feed of printed output, and the live receipt page that shows it.
+//!
+//! Each client of `GET /events` receives JSON text messages tagged by
+//! `type`: the printer status on connect and whenever it changes, every
+//! decoded command, job starts, a preview of the job in progress at most
+//! every `PREVIEW_INTERVAL`, and each finished job with PNG and HTML
+//! snapshots. `GET /` serves a page that subscribes to the feed.
+
+use super::api::{ApiStatus, JobSummary, SharedState};
+use crate::emulator::{EmulatorEvent, EmulatorState, JobSnapshots, PrintJob};
+use crate::export::{self, JobCommand};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::{Html, Response};
+use axum::Extension;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+use tracing::info;
+
+/// Shortest time between two previews of the job in progress.
+pub const PREVIEW_INTERVAL: Duration = Duration::from_millis(250);
+
+const PAGE: &str = include_str!("live.html");
+
+/// One message of the feed.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveMessage {
+    Status {
+        status: ApiStatus,
+    },
+    Command {
+        job_id: u64,
+        /// e.g. `tcp 192.168.1.20:51234`
+        source: String,
+        command: JobCommand,
+    },
+    JobStarted {
+        job_id: u64,
+        source: String,
+    },
+    /// The job in progress as printed so far
+    Preview {
+        job_id: u64,
+        html: String,
+    },
+    JobFinished {
+        job: JobSummary,
+        /// `data:image/png;base64,...` at one pixel per dot; absent if the
+        /// job printed nothing
+        png: Option<String>,
+        /// Self-contained HTML document of the receipt
+        html: String,
+    },
+    /// The client fell behind and this many events were dropped
+    Lagged {
+        missed: u64,
+    },
+}
+
+impl LiveMessage {
+    /// The snapshots were rendered once when the job finished; every client
+    /// gets a copy.
+    fn job_finished(state: &EmulatorState, job: &PrintJob, snapshots: &JobSnapshots) -> Self {
+        LiveMessage::JobFinished {
+            job: JobSummary::of(state, job),
+            png: snapshots.png.clone(),
+            html: snapshots.html.clone(),
+        }
+    }
+}
+
+/// Set when the control API shuts down, so open feeds close and the server
+/// can finish.
+#[derive(Clone)]
+pub struct FeedShutdown(pub watch::Receiver<bool>);
+
+pub async fn page() -> Html<&'static str> {
+    Html(PAGE)
+}
+
+pub async fn events(
+    State(emulator_state): State<SharedState>,
+    shutdown: Option<Extension<FeedShutdown>>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let shutdown = shutdown.map(|Extension(FeedShutdown(receiver))| receiver);
+    upgrade.on_upgrade(move |socket| feed(socket, emulator_state, shutdown))
+}
+
+async fn feed(mut socket: WebSocket, emulator_state: SharedState, mut shutdown: Option<watch::Receiver<bool>>) {
+    let (mut events, status) = {
+        let mut state = emulator_state.lock().await;
+        (state.subscribe_events(), ApiStatus::of(&state))
+    };
+    info!("Live feed client connected");
+    if send(&mut socket, &LiveMessage::Status { status }).await.is_err() {
+        return;
+    }
+
+    let mut preview = tokio::time::interval(PREVIEW_INTERVAL);
+    // Job whose commands arrived since the last preview
+    let mut changed_job = None;
+    loop {
+        let message = tokio::select! {
+            event = events.recv() => match event {
+                Ok(EmulatorEvent::Command { job_id, source, entry }) => {
+                    changed_job = Some(job_id);
+                    Some(LiveMessage::Command { job_id, source: source.to_string(), command: export::job_command(&entry) })
+                }
+                Ok(EmulatorEvent::JobStarted { job_id, source }) => {
+                    Some(LiveMessage::JobStarted { job_id, source: source.to_string() })
+                }
+                Ok(EmulatorEvent::JobFinished { job, snapshots }) => {
+                    changed_job = changed_job.filter(|id| *id != job.id);
+                    Some(LiveMessage::job_finished(&*emulator_state.lock().await, &job, &snapshots))
+                }
+                Ok(EmulatorEvent::StatusChanged) => {
+                    Some(LiveMessage::Status { status: ApiStatus::of(&*emulator_state.lock().await) })
+                }
+                Err(RecvError::Lagged(missed)) => Some(LiveMessage::Lagged { missed }),
+                Err(RecvError::Closed) => break,
+            },
+            _ = preview.tick() => match changed_job.take() {
+                Some(job_id) => preview_message(&emulator_state, job_id).await,
+                None => None,
+            },
+            incoming = socket.recv() => match incoming {
+                // Pings are answered by the socket itself
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => None,
+            },
+            _ = stopped(&mut shutdown) => break,
+        };
+        if let Some(message) = message {
+            if send(&mut socket, &message).await.is_err() {
+                break;
+            }
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+    info!("Live feed client disconnected");
+}
+
+/// The job in progress, copied under the lock and rendered off it.
+async fn preview_message(emulator_state: &SharedState, job_id: u64) -> Option<LiveMessage> {
+    let (printer, lines) = {
+        let mut state = emulator_state.lock().await;
+        let lines = state.get_job(job_id).filter(|job| job.active).map(|job| state.job_lines(job).into_owned())?;
+        (state.printer_settings(), lines)
+    };
+    let html = tokio::task::spawn_blocking(move || export::render_html(&printer, &lines)).await.ok()?;
+    Some(LiveMessage::Preview { job_id, html })
+}
+
+async fn send(socket: &mut WebSocket, message: &LiveMessage) -> Result<(), axum::Error> {
+    // Serializing these types cannot fail: every map key is a string
+    let text = serde_json::to_string(message).unwrap_or_default();
+    socket.send(Message::Text(text.into())).await
+}
+
+async fn stopped(shutdown: &mut Option<watch::Receiver<bool>>) {
+    match shutdown {
+        Some(receiver) => {
+            let _ = receiver.wait_for(|stopped| *stopped).await;
+        }
+        None => std::future::pending().await,
+    }
+}
@@ -1,3 +1,5 @@
+pub mod api;
+pub mod live;
 pub mod server;
 pub mod serial;
 
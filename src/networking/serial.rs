@@ -1,7 +1,7 @@
-use crate::emulator::EmulatorState;
+use crate::emulator::{EmulatorState, JobSource, SessionEventKind};
 use crate::escpos::parser::EscPosParser;
 use anyhow::{Context, Result};
-use std::io::Read;
+use std::io::{Read, Write};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
@@ -43,29 +43,49 @@ pub fn start_serial_listener(
 
     let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
 
+    // Open port synchronously so we can return an error immediately if it fails
+    let port = serialport::new(&port_name, baud_rate)
+        .timeout(Duration::from_millis(100))
+        .open()
+        .with_context(|| format!("Failed to open serial port {}", port_name))?;
+
+    // Status responses go back through a second handle to the same port,
+    // written on their own thread so the emulator is never locked during I/O
+    let mut writer = port
+        .try_clone()
+        .with_context(|| format!("Failed to clone serial port {}", port_name))?;
+    let (out_tx, out_rx) = std::sync::mpsc::channel::<Vec<u8>>();
+    std::thread::spawn(move || {
+        for responses in out_rx {
+            if let Err(e) = writer.write_all(&responses) {
+                warn!("Serial write error: {}", e);
+            }
+        }
+    });
+
     // Async task: receive bytes from channel, parse ESC/POS, process commands
+    let source = JobSource::Serial(port_name.clone());
     tokio_handle.spawn(async move {
-        let mut parser = EscPosParser::new();
+        let mut parser = emulator_state.lock().await.new_parser();
+        let mut job = None;
         while let Some(data) = rx.recv().await {
-            match parser.parse_stream(&data) {
-                Ok(commands) => {
-                    let mut state = emulator_state.lock().await;
-                    for command in &commands {
-                        state.process_command(command);
-                    }
-                }
-                Err(e) => warn!("Serial parse error: {}", e),
+            let responses = {
+                let mut state = emulator_state.lock().await;
+                process_serial_bytes(&mut state, &mut parser, &source, &mut job, &data)
+            };
+            if !responses.is_empty() && out_tx.send(responses).is_err() {
+                break; // writer gone
             }
         }
+        parser.finish();
+        let mut state = emulator_state.lock().await;
+        for diagnostic in parser.take_diagnostics() {
+            state.record_diagnostic(&source, diagnostic);
+        }
+        state.connection_closed(&source);
         info!("Serial data processor stopped");
     });
 
-    // Open port synchronously so we can return an error immediately if it fails
-    let port = serialport::new(&port_name, baud_rate)
-        .timeout(Duration::from_millis(100))
-        .open()
-        .with_context(|| format!("Failed to open serial port {}", port_name))?;
-
     // Sync thread: read bytes from serial port and forward to async channel
     std::thread::spawn(move || {
         let mut port = port;
@@ -96,3 +116,120 @@ pub fn start_serial_listener(
     info!("Serial listener started on {} @ {} baud", port_name, baud_rate);
     Ok(SerialHandle { running })
 }
+
+/// Run one read from the port through the parser and the emulator, returning
+/// the status responses to send back.
+///
+/// A serial line has no connection boundary, so `job` tracks the source's
+/// active job and the parser's job size limit restarts whenever it changes.
+fn process_serial_bytes(
+    state: &mut EmulatorState,
+    parser: &mut EscPosParser,
+    source: &JobSource,
+    job: &mut Option<u64>,
+    data: &[u8],
+) -> Vec<u8> {
+    // The previous job may have been closed for being idle in the meantime
+    let offset = parser.stream_offset();
+    track_job(state, parser, source, job, offset);
+    let result = parser.parse_stream(data);
+    state.record_session_event(source, SessionEventKind::Inbound, data);
+    match result {
+        Ok(commands) => {
+            for parsed in &commands {
+                state.process_command_from(source, parsed);
+                track_job(state, parser, source, job, parsed.offset + parsed.raw.len() as u64);
+            }
+        }
+        Err(e) => warn!("Serial parse error: {}", e),
+    }
+    for diagnostic in parser.take_diagnostics() {
+        state.record_diagnostic(source, diagnostic);
+    }
+    // Close an oversized job with what fit and count the next one afresh
+    if parser.job_limit_exceeded() {
+        state.finish_source_job(source);
+        *job = None;
+        parser.start_job_at(parser.stream_offset());
+    }
+    let responses = state.take_responses();
+    state.record_session_event(source, SessionEventKind::Outbound, &responses);
+    responses
+}
+
+/// Restart the parser's job size count at `offset` once the job `job` was
+/// receiving has been closed.
+fn track_job(
+    state: &EmulatorState,
+    parser: &mut EscPosParser,
+    source: &JobSource,
+    job: &mut Option<u64>,
+    offset: u64,
+) {
+    let current = state.source_job(source).map(|job| job.id);
+    if current != *job {
+        if job.is_some() {
+            parser.start_job_at(offset);
+        }
+        *job = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::printer::ReceiptLine;
+
+    fn texts(lines: &[ReceiptLine]) -> Vec<&str> {
+        lines
+            .iter()
+            .filter_map(|line| match line {
+                ReceiptLine::Text(text) if !text.text.is_empty() => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn job_limit_restarts_for_every_job_on_the_line() {
+        let mut state = EmulatorState::new();
+        state.parser_limits.max_job_bytes = 64;
+        let source = JobSource::Serial("COM1".into());
+        let mut parser = state.new_parser();
+        let mut job = None;
+
+        let mut data = Vec::new();
+        for n in 1..=5 {
+            data.extend_from_slice(format!("Receipt {} of five on the line\n", n).as_bytes());
+            data.extend_from_slice(&[0x1D, b'V', 0]);
+        }
+        assert!(data.len() > 64 * 2);
+        for chunk in data.chunks(13) {
+            process_serial_bytes(&mut state, &mut parser, &source, &mut job, chunk);
+        }
+
+        assert_eq!(state.jobs.len(), 5);
+        for (n, job) in state.jobs.iter().enumerate() {
+            assert!(!job.active);
+            assert_eq!(texts(&job.lines), [format!("Receipt {} of five on the line", n + 1)]);
+            assert!(job.diagnostics.is_empty(), "{:?}", job.diagnostics);
+        }
+    }
+
+    #[test]
+    fn oversized_job_is_closed_and_the_next_one_accepted() {
+        let mut state = EmulatorState::new();
+        state.parser_limits.max_job_bytes = 64;
+        let source = JobSource::Serial("COM1".into());
+        let mut parser = state.new_parser();
+        let mut job = None;
+
+        process_serial_bytes(&mut state, &mut parser, &source, &mut job, &[b'x'; 100]);
+        assert!(state.source_job(&source).is_none());
+        process_serial_bytes(&mut state, &mut parser, &source, &mut job, b"Next\n\x1dV\x00");
+
+        assert_eq!(state.jobs.len(), 2);
+        assert!(!state.jobs[0].diagnostics.is_empty());
+        assert_eq!(texts(&state.jobs[1].lines), ["Next"]);
+    }
+}
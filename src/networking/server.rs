@@ -1,36 +1,220 @@
-use crate::emulator::EmulatorState;
+use crate::emulator::{EmulatorState, JobSource, JobSplitPolicy, PrintJob, SessionEventKind, MACRO_WAIT_STEP};
 use crate::escpos::parser::EscPosParser;
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use std::future::Future;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, oneshot, Mutex};
+use tokio::task::{JoinHandle, JoinSet};
 use tracing::{error, info, warn};
 
+/// Address the GUI listens on.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:9100";
+/// Largest HTTP request head accepted before the body.
+const MAX_HTTP_HEADER_BYTES: usize = 8 * 1024;
+/// How long a shutdown waits for open connections to finish sending.
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
 pub async fn start_server(emulator_state: Arc<Mutex<EmulatorState>>) -> Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:9100").await?;
-    info!("ESC/POS Emulator server listening on 127.0.0.1:9100 (Raw TCP & Web HTTP/CORS)");
+    let listener = bind(DEFAULT_ADDR).await?;
+    serve(listener, emulator_state).await
+}
+
+/// Bind the listener; port 0 picks a free port, see `local_addr`.
+pub async fn bind(addr: &str) -> Result<TcpListener> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    info!("ESC/POS Emulator server listening on {} (Raw TCP & Web HTTP/CORS)", listener.local_addr()?);
+    Ok(listener)
+}
+
+/// Accept raw TCP and HTTP clients until the task is dropped.
+pub async fn serve(listener: TcpListener, emulator_state: Arc<Mutex<EmulatorState>>) -> Result<()> {
+    serve_until(listener, emulator_state, std::future::pending()).await
+}
 
+/// Accept clients until `shutdown` completes, then stop listening and give
+/// open connections `SHUTDOWN_GRACE` to finish before closing them.
+pub async fn serve_until(
+    listener: TcpListener,
+    emulator_state: Arc<Mutex<EmulatorState>>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let mut connections = JoinSet::new();
+    tokio::pin!(shutdown);
     loop {
-        match listener.accept().await {
-            Ok((socket, addr)) => {
-                info!("New connection from: {}", addr);
-                let state = emulator_state.clone();
-                tokio::spawn(async move {
-                    if let Err(e) = handle_connection(socket, state).await {
-                        error!("Error handling connection from {}: {}", addr, e);
-                    }
-                });
-            }
-            Err(e) => {
-                error!("Failed to accept connection: {}", e);
+        tokio::select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept() => match accepted {
+                Ok((socket, addr)) => {
+                    info!("New connection from: {}", addr);
+                    let state = emulator_state.clone();
+                    connections.spawn(async move {
+                        if let Err(e) = handle_connection(socket, addr, state).await {
+                            error!("Error handling connection from {}: {}", addr, e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    error!("Failed to accept connection: {}", e);
+                }
+            },
+            // Reap finished connections so the set does not grow forever
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+        }
+    }
+
+    drop(listener);
+    let drained = tokio::time::timeout(SHUTDOWN_GRACE, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        warn!("Closing {} connection(s) still open after {:?}", connections.len(), SHUTDOWN_GRACE);
+        connections.shutdown().await;
+    }
+    Ok(())
+}
+
+/// An emulator listening in the background of the current tokio runtime,
+/// for test suites that want a printer of their own:
+///
+/// `let printer = EmulatorServer::new().start().await?;` binds a free port on
+/// 127.0.0.1; send to `printer.local_addr()` and `printer.next_job().await`.
+pub struct EmulatorServer {
+    addr: String,
+    state: EmulatorState,
+}
+
+impl Default for EmulatorServer {
+    fn default() -> Self {
+        Self {
+            addr: "127.0.0.1:0".to_string(),
+            state: EmulatorState::new(),
+        }
+    }
+}
+
+impl EmulatorServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Listen address; port 0, the default, picks a free port.
+    pub fn bind(mut self, addr: &str) -> Self {
+        self.addr = addr.to_string();
+        self
+    }
+
+    /// Start from a prepared emulator, e.g. with a profile selected.
+    pub fn state(mut self, state: EmulatorState) -> Self {
+        self.state = state;
+        self
+    }
+
+    pub fn job_policy(mut self, policy: JobSplitPolicy) -> Self {
+        self.state.job_policy = policy;
+        self
+    }
+
+    pub async fn start(mut self) -> Result<ServerHandle> {
+        let listener = bind(&self.addr).await?;
+        let local_addr = listener.local_addr()?;
+        let finished_jobs = self.state.subscribe_finished_jobs();
+        // Jobs are read from the finished-job stream, never the paper roll
+        self.state.trim_printed_lines = true;
+        let state = Arc::new(Mutex::new(self.state));
+        let (shutdown, shutdown_signal) = oneshot::channel::<()>();
+
+        let task_state = Arc::clone(&state);
+        let task = tokio::spawn(async move {
+            let serving = serve_until(listener, Arc::clone(&task_state), async {
+                let _ = shutdown_signal.await;
+            });
+            tokio::pin!(serving);
+            // Nothing repaints to close idle jobs, so poll as headless mode does
+            let mut idle_check = tokio::time::interval(MACRO_WAIT_STEP);
+            let result = loop {
+                tokio::select! {
+                    result = &mut serving => break result,
+                    _ = idle_check.tick() => task_state.lock().await.finish_idle_jobs(),
+                }
+            };
+            task_state.lock().await.finish_jobs();
+            result
+        });
+
+        Ok(ServerHandle {
+            local_addr,
+            state,
+            finished_jobs: Mutex::new(finished_jobs),
+            shutdown: Some(shutdown),
+            task: Some(task),
+        })
+    }
+}
+
+/// A running `EmulatorServer`. Dropping it stops the server without waiting.
+pub struct ServerHandle {
+    local_addr: SocketAddr,
+    state: Arc<Mutex<EmulatorState>>,
+    finished_jobs: Mutex<broadcast::Receiver<PrintJob>>,
+    shutdown: Option<oneshot::Sender<()>>,
+    task: Option<JoinHandle<Result<()>>>,
+}
+
+impl ServerHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn state(&self) -> Arc<Mutex<EmulatorState>> {
+        Arc::clone(&self.state)
+    }
+
+    /// The next finished job this handle has not returned yet, oldest first.
+    /// Jobs finished before the call count, so data can be sent first and
+    /// waited for afterwards. Wrap in `tokio::time::timeout` to bound the wait.
+    pub async fn next_job(&self) -> Result<PrintJob> {
+        let mut finished_jobs = self.finished_jobs.lock().await;
+        loop {
+            match finished_jobs.recv().await {
+                Ok(job) => return Ok(job),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Skipped {} finished job(s) nobody waited for", missed);
+                }
+                Err(broadcast::error::RecvError::Closed) => bail!("The emulator server has stopped"),
             }
         }
     }
+
+    /// Stop accepting, let open connections finish and close the active job.
+    pub async fn shutdown(mut self) -> Result<()> {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        match self.task.take() {
+            Some(task) => task.await.context("Server task failed")?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
 }
 
 async fn handle_connection(
     mut socket: TcpStream,
+    addr: SocketAddr,
     emulator_state: Arc<Mutex<EmulatorState>>,
 ) -> Result<()> {
     let mut buffer = Vec::new();
@@ -58,38 +242,56 @@ async fn handle_connection(
     } 
     // Check if client is a Web Browser sending HTTP POST (fetch / axios)
     else if buffer.starts_with(b"POST ") {
-        // Read full HTTP body if needed
-        let mut full_request = buffer.clone();
-        while !full_request.windows(4).any(|w| w == b"\r\n\r\n") {
-            let n = socket.read(&mut chunk).await?;
-            if n == 0 { break; }
-            full_request.extend_from_slice(&chunk[..n]);
-        }
-
-        if let Some(body_start) = find_subslice(&full_request, b"\r\n\r\n") {
-            let body = &full_request[body_start + 4..];
-            process_raw_bytes(body, &emulator_state).await;
-
-            let response = "HTTP/1.1 200 OK\r\n\
-                            Access-Control-Allow-Origin: *\r\n\
-                            Access-Control-Allow-Private-Network: true\r\n\
-                            Content-Type: text/plain\r\n\
-                            Connection: close\r\n\r\n\
-                            OK";
-            socket.write_all(response.as_bytes()).await?;
-            return Ok(());
-        }
+        let max_body = emulator_state.lock().await.parser_limits.max_job_bytes;
+        let (status, message) = match read_http_body(&mut socket, buffer, max_body).await? {
+            Ok(body) => {
+                process_raw_bytes(&body, &JobSource::Http(addr.to_string()), &emulator_state).await;
+                ("200 OK", "OK".to_string())
+            }
+            Err((status, message)) => {
+                warn!("Rejected HTTP print from {}: {}", addr, message);
+                (status, message)
+            }
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\n\
+             Access-Control-Allow-Origin: *\r\n\
+             Access-Control-Allow-Private-Network: true\r\n\
+             Content-Type: text/plain\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n\
+             {}",
+            status,
+            message.len(),
+            message
+        );
+        socket.write_all(response.as_bytes()).await?;
+        return Ok(());
     }
 
     // Otherwise, handle as standard Raw TCP / ESC-POS stream
-    let mut parser = EscPosParser::new();
-    process_bytes_with_parser(&buffer, &mut parser, &emulator_state).await;
+    let source = JobSource::Tcp(addr.to_string());
+    let mut parser = {
+        let mut state = emulator_state.lock().await;
+        state.connection_opened(&source);
+        state.new_parser()
+    };
+    let responses = process_bytes_with_parser(&buffer, &source, &mut parser, &emulator_state).await;
+    socket.write_all(&responses).await?;
 
     loop {
+        if parser.job_limit_exceeded() {
+            warn!("Job size limit exceeded, closing connection");
+            break;
+        }
         match socket.read(&mut chunk).await {
             Ok(0) => break,
             Ok(n) => {
-                process_bytes_with_parser(&chunk[..n], &mut parser, &emulator_state).await;
+                let responses = process_bytes_with_parser(&chunk[..n], &source, &mut parser, &emulator_state).await;
+                if let Err(e) = socket.write_all(&responses).await {
+                    warn!("Error writing status response: {}", e);
+                    break;
+                }
             }
             Err(e) => {
                 warn!("Error reading from socket: {}", e);
@@ -98,6 +300,15 @@ async fn handle_connection(
         }
     }
 
+    parser.finish();
+    {
+        let mut state = emulator_state.lock().await;
+        for diagnostic in parser.take_diagnostics() {
+            state.record_diagnostic(&source, diagnostic);
+        }
+        state.connection_closed(&source);
+    }
+
     let response = b"OK\n";
     let _ = socket.write_all(response).await;
     Ok(())
@@ -107,26 +318,154 @@ fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
     haystack.windows(needle.len()).position(|window| window == needle)
 }
 
-async fn process_raw_bytes(data: &[u8], emulator_state: &Arc<Mutex<EmulatorState>>) {
-    let mut parser = EscPosParser::new();
-    if let Ok(commands) = parser.parse_stream(data) {
-        let mut state = emulator_state.lock().await;
-        for command in commands {
-            state.process_command(&command);
+/// Read the rest of an HTTP request that starts with `received`: headers up to
+/// `MAX_HTTP_HEADER_BYTES`, then exactly Content-Length body bytes, at most
+/// `max_body`. A request that breaks a limit yields the status to answer with.
+async fn read_http_body(
+    socket: &mut TcpStream,
+    mut received: Vec<u8>,
+    max_body: usize,
+) -> Result<std::result::Result<Vec<u8>, (&'static str, String)>> {
+    let mut chunk = vec![0u8; 4096];
+    let header_end = loop {
+        if let Some(end) = find_subslice(&received, b"\r\n\r\n") {
+            break end;
+        }
+        if received.len() > MAX_HTTP_HEADER_BYTES {
+            let message = format!("request headers exceed {} bytes", MAX_HTTP_HEADER_BYTES);
+            return Ok(Err(("431 Request Header Fields Too Large", message)));
+        }
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            bail!("Connection closed inside the HTTP request headers");
+        }
+        received.extend_from_slice(&chunk[..n]);
+    };
+
+    let headers = String::from_utf8_lossy(&received[..header_end]);
+    let content_length = headers.lines().skip(1).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim().eq_ignore_ascii_case("content-length").then(|| value.trim().parse::<usize>())
+    });
+    let length = match content_length {
+        Some(Ok(length)) => length,
+        Some(Err(_)) => return Ok(Err(("400 Bad Request", "invalid Content-Length".to_string()))),
+        None => return Ok(Err(("411 Length Required", "Content-Length is required".to_string()))),
+    };
+    if length > max_body {
+        let message = format!("body of {} bytes exceeds the {} byte job limit", length, max_body);
+        return Ok(Err(("413 Payload Too Large", message)));
+    }
+
+    let mut body = received.split_off(header_end + 4);
+    while body.len() < length {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            bail!("Connection closed after {} of {} HTTP body bytes", body.len(), length);
         }
+        body.extend_from_slice(&chunk[..n]);
     }
+    body.truncate(length);
+    Ok(Ok(body))
+}
+
+async fn process_raw_bytes(data: &[u8], source: &JobSource, emulator_state: &Arc<Mutex<EmulatorState>>) {
+    let mut state = emulator_state.lock().await;
+    state.print_bytes(source, data);
+    // An HTTP client cannot receive status bytes
+    state.take_responses();
 }
 
 async fn process_bytes_with_parser(
     data: &[u8],
+    source: &JobSource,
     parser: &mut EscPosParser,
     emulator_state: &Arc<Mutex<EmulatorState>>,
-) {
-    if let Ok(commands) = parser.parse_stream(data) {
-        let mut state = emulator_state.lock().await;
-        for command in commands {
-            info!("Received command: {:?}", command);
-            state.process_command(&command);
+) -> Vec<u8> {
+    let result = parser.parse_stream(data);
+    let mut state = emulator_state.lock().await;
+    state.record_session_event(source, SessionEventKind::Inbound, data);
+    if let Ok(commands) = result {
+        for parsed in commands {
+            info!("Received command @{}: {:?}", parsed.offset, parsed.command);
+            state.process_command_from(source, &parsed);
         }
     }
+    for diagnostic in parser.take_diagnostics() {
+        state.record_diagnostic(source, diagnostic);
+    }
+    let responses = state.take_responses();
+    state.record_session_event(source, SessionEventKind::Outbound, &responses);
+    responses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::printer::ReceiptLine;
+    use tokio::time::timeout;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    fn texts(job: &PrintJob) -> Vec<&str> {
+        job.lines
+            .iter()
+            .filter_map(|line| match line {
+                ReceiptLine::Text(text) if !text.text.is_empty() => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn raw_tcp_job_arrives_through_next_job() {
+        let printer = EmulatorServer::new().start().await.unwrap();
+        let mut socket = TcpStream::connect(printer.local_addr()).await.unwrap();
+        socket.write_all(b"\x1b@HELLO\nWORLD\n\x1dV\x00").await.unwrap();
+        drop(socket);
+
+        let job = timeout(WAIT, printer.next_job()).await.unwrap().unwrap();
+        assert!(matches!(job.source, JobSource::Tcp(_)));
+        assert_eq!(texts(&job), ["HELLO", "WORLD"]);
+        assert!(!job.active);
+        printer.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_finishes_the_job_of_a_closed_connection() {
+        let policy = JobSplitPolicy { on_cut: false, on_connection: false, idle_timeout_secs: None };
+        let printer = EmulatorServer::new().job_policy(policy).start().await.unwrap();
+        let mut socket = TcpStream::connect(printer.local_addr()).await.unwrap();
+        socket.write_all(b"ONE\n\x1dV\x00TWO\n").await.unwrap();
+        socket.shutdown().await.unwrap();
+        let mut rest = Vec::new();
+        socket.read_to_end(&mut rest).await.unwrap();
+
+        let state = printer.state();
+        let mut finished = state.lock().await.subscribe_finished_jobs();
+        printer.shutdown().await.unwrap();
+        let job = finished.try_recv().unwrap();
+        assert_eq!(texts(&job), ["ONE", "TWO"]);
+        assert_eq!(state.lock().await.jobs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn http_post_prints_exactly_the_declared_body() {
+        let printer = EmulatorServer::new().start().await.unwrap();
+        let mut socket = TcpStream::connect(printer.local_addr()).await.unwrap();
+        socket
+            .write_all(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nHTTP\nEXTRA\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+
+        printer.state().lock().await.finish_jobs();
+        let job = timeout(WAIT, printer.next_job()).await.unwrap().unwrap();
+        assert!(matches!(job.source, JobSource::Http(_)));
+        assert_eq!(job.byte_count, 5);
+        assert_eq!(texts(&job), ["HTTP"]);
+        printer.shutdown().await.unwrap();
+    }
 }
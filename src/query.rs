@@ -0,0 +1,684 @@
+//! Queries and assertions over printed receipts, for integration tests.
+//!
+//! A `Receipt` pairs the laid-out lines of a job with the commands behind
+//! them, so tests can check content, styling, barcodes, cuts and drawer kicks
+//! without comparing pixels. The `assert_*` methods panic with a listing of
+//! the whole receipt when they fail.
+
+use crate::emulator::{EmulatorState, PrintJob};
+use crate::escpos::barcode;
+use crate::escpos::commands::{BarcodeSystem, CutKind, EscPosCommand, Font, Justification};
+use crate::escpos::printer::{ReceiptLine, TextLine};
+use crate::export::json::JobDocument;
+use crate::render::RenderedJob;
+use anyhow::Result;
+use regex::Regex;
+use std::fmt;
+
+/// A cash drawer pulse sent with ESC p or DLE DC4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrawerKick {
+    /// Connector pin, 2 or 5
+    pub pin: u8,
+    pub on_ms: u32,
+    pub off_ms: u32,
+}
+
+/// The lines and commands of a printed job.
+#[derive(Debug, Clone)]
+pub struct Receipt {
+    lines: Vec<ReceiptLine>,
+    commands: Vec<EscPosCommand>,
+}
+
+impl Receipt {
+    pub fn new(lines: Vec<ReceiptLine>, commands: Vec<EscPosCommand>) -> Self {
+        Self { lines, commands }
+    }
+
+    /// Everything in the printer buffer and the command history of `state`.
+    pub fn from_state(state: &EmulatorState) -> Self {
+        Self::new(
+            state.get_printer_state().get_buffer().to_vec(),
+            state.get_command_history().iter().map(|entry| entry.command.clone()).collect(),
+        )
+    }
+
+    /// One job from the job history of `state`.
+    pub fn from_job(state: &EmulatorState, job: &PrintJob) -> Self {
+        Self::new(state.job_lines(job).to_vec(), job.commands.iter().map(|entry| entry.command.clone()).collect())
+    }
+
+    /// A job read back from its JSON document.
+    pub fn from_document(document: &JobDocument) -> Result<Self> {
+        let commands = document.command_entries(&EmulatorState::new())?;
+        Ok(Self::new(document.receipt_lines()?, commands.into_iter().map(|entry| entry.command).collect()))
+    }
+
+    pub fn lines(&self) -> &[ReceiptLine] {
+        &self.lines
+    }
+
+    pub fn text_lines(&self) -> impl Iterator<Item = &TextLine> {
+        self.lines.iter().filter_map(|line| match line {
+            ReceiptLine::Text(text) => Some(text),
+            _ => None,
+        })
+    }
+
+    /// The first text line reading `text`, ignoring surrounding spaces.
+    pub fn find_text(&self, text: &str) -> Option<&TextLine> {
+        self.text_lines().find(|line| line.text.trim() == text)
+    }
+
+    pub fn find_containing(&self, needle: &str) -> Vec<&TextLine> {
+        self.text_lines().filter(|line| line.text.contains(needle)).collect()
+    }
+
+    pub fn find_matching(&self, pattern: &Regex) -> Vec<&TextLine> {
+        self.text_lines().filter(|line| pattern.is_match(&line.text)).collect()
+    }
+
+    /// Every line `expect` matches, in order.
+    pub fn find_all(&self, expect: &Expect) -> Vec<&ReceiptLine> {
+        self.lines.iter().filter(|line| expect.matches(line)).collect()
+    }
+
+    pub fn find(&self, expect: &Expect) -> Option<&ReceiptLine> {
+        self.lines.iter().find(|line| expect.matches(line))
+    }
+
+    pub fn cuts(&self) -> Vec<CutKind> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                ReceiptLine::Separator(kind) => Some(*kind),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn cut_count(&self) -> usize {
+        self.cuts().len()
+    }
+
+    /// Symbology and human readable text of each barcode.
+    pub fn barcodes(&self) -> Vec<(BarcodeSystem, String)> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                ReceiptLine::Barcode { system, data, .. } => Some((*system, barcode::hri_text(*system, data))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Payloads of the QR codes.
+    pub fn qr_codes(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                ReceiptLine::QrCode { data, .. } => Some(String::from_utf8_lossy(data).into_owned()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn drawer_kicks(&self) -> Vec<DrawerKick> {
+        self.commands
+            .iter()
+            .filter_map(|command| match command {
+                EscPosCommand::DrawerKick { pin, on_ms, off_ms } => {
+                    Some(DrawerKick { pin: *pin, on_ms: *on_ms, off_ms: *off_ms })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Panic unless some line matches `expect`.
+    #[track_caller]
+    pub fn assert_has(&self, expect: Expect) -> &Self {
+        if self.find(&expect).is_none() {
+            self.fail(&format!("expected {}{}", expect, self.near_misses(&expect)));
+        }
+        self
+    }
+
+    /// Panic if any line matches `expect`.
+    #[track_caller]
+    pub fn assert_lacks(&self, expect: Expect) -> &Self {
+        if let Some(index) = self.lines.iter().position(|line| expect.matches(line)) {
+            self.fail(&format!("expected no {}, found one at line {}", expect, index + 1));
+        }
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_count(&self, expect: Expect, count: usize) -> &Self {
+        let found = self.find_all(&expect).len();
+        if found != count {
+            self.fail(&format!("expected {} × {}, found {}", count, expect, found));
+        }
+        self
+    }
+
+    /// Panic unless the expectations match lines in this order; other lines
+    /// may come between them.
+    #[track_caller]
+    pub fn assert_sequence(&self, expected: &[Expect]) -> &Self {
+        let mut next = 0;
+        for (step, expect) in expected.iter().enumerate() {
+            match self.lines[next..].iter().position(|line| expect.matches(line)) {
+                Some(offset) => next += offset + 1,
+                None => {
+                    let after = match next {
+                        0 => String::new(),
+                        n => format!(" after line {}", n),
+                    };
+                    self.fail(&format!(
+                        "expected {}{} (step {} of {}){}",
+                        expect,
+                        after,
+                        step + 1,
+                        expected.len(),
+                        self.near_misses(expect)
+                    ));
+                }
+            }
+        }
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_cut_count(&self, count: usize) -> &Self {
+        if self.cut_count() != count {
+            self.fail(&format!("expected {} cut(s), found {}", count, self.cut_count()));
+        }
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_drawer_kick_count(&self, count: usize) -> &Self {
+        let found = self.drawer_kicks().len();
+        if found != count {
+            self.fail(&format!("expected {} drawer kick(s), found {}", count, found));
+        }
+        self
+    }
+
+    /// Panic unless the drawer on `pin` was kicked.
+    #[track_caller]
+    pub fn assert_drawer_kicked(&self, pin: u8) -> &Self {
+        if !self.drawer_kicks().iter().any(|kick| kick.pin == pin) {
+            self.fail(&format!("expected a drawer kick on pin {}", pin));
+        }
+        self
+    }
+
+    /// Lines with the expected text but the wrong style, as a hint.
+    fn near_misses(&self, expect: &Expect) -> String {
+        let line_expect = match &expect.kind {
+            Kind::Line(line_expect) => line_expect,
+            _ => return String::new(),
+        };
+        let mut hints = String::new();
+        for (index, line) in self.lines.iter().enumerate() {
+            if let ReceiptLine::Text(text) = line {
+                if line_expect.text.matches(&text.text) {
+                    let differences = line_expect.style_differences(text);
+                    if differences.is_empty() {
+                        hints += &format!("\n  line {} matches but comes earlier", index + 1);
+                    } else {
+                        hints += &format!("\n  line {} has the text but {}", index + 1, differences.join(", "));
+                    }
+                }
+            }
+        }
+        hints
+    }
+
+    #[track_caller]
+    fn fail(&self, message: &str) -> ! {
+        panic!("receipt assertion failed: {}\n\n{}", message, self)
+    }
+}
+
+impl From<&RenderedJob> for Receipt {
+    fn from(job: &RenderedJob) -> Self {
+        Receipt::from_state(&job.state)
+    }
+}
+
+/// One row per line with its style, then the drawer kicks.
+impl fmt::Display for Receipt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "receipt ({} lines, {} cut(s)):", self.lines.len(), self.cut_count())?;
+        for (index, line) in self.lines.iter().enumerate() {
+            write!(f, "{:>4} │ ", index + 1)?;
+            match line {
+                ReceiptLine::Text(text) => writeln!(f, "{:<24} {:?}", style_summary(text), text.text)?,
+                ReceiptLine::Feed { dots } => writeln!(f, "feed {} dots", dots)?,
+                ReceiptLine::Separator(kind) => writeln!(f, "{} cut", cut_name(*kind))?,
+                ReceiptLine::Bitmap { width_px, height_px, .. } => writeln!(f, "image {}x{}", width_px, height_px)?,
+                ReceiptLine::Barcode { system, data, .. } => {
+                    writeln!(f, "barcode {} {:?}", system.name(), barcode::hri_text(*system, data))?
+                }
+                ReceiptLine::QrCode { data, .. } => writeln!(f, "qr code {:?}", String::from_utf8_lossy(data))?,
+            }
+        }
+        for kick in self.drawer_kicks() {
+            writeln!(f, "     drawer kick on pin {} ({} ms)", kick.pin, kick.on_ms)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a line is expected to be. Style methods such as `bold()` apply to
+/// text lines only.
+#[derive(Debug, Clone)]
+pub struct Expect {
+    kind: Kind,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Line(LineExpect),
+    Barcode { system: Option<BarcodeSystem>, data: Option<String> },
+    QrCode(Option<String>),
+    Cut(Option<CutKind>),
+}
+
+impl Expect {
+    /// A text line reading `text`, ignoring surrounding spaces.
+    pub fn line(text: &str) -> Self {
+        Self::text(TextMatch::Exact(text.to_string()))
+    }
+
+    pub fn line_containing(needle: &str) -> Self {
+        Self::text(TextMatch::Contains(needle.to_string()))
+    }
+
+    /// A text line matching a regular expression; panics if it is invalid.
+    #[track_caller]
+    pub fn line_matching(pattern: &str) -> Self {
+        match Regex::new(pattern) {
+            Ok(regex) => Self::text(TextMatch::Regex(regex)),
+            Err(e) => panic!("invalid line pattern {:?}: {}", pattern, e),
+        }
+    }
+
+    fn text(text: TextMatch) -> Self {
+        Self { kind: Kind::Line(LineExpect { text, ..LineExpect::default() }) }
+    }
+
+    /// A barcode whose data or human readable text is `data`.
+    pub fn barcode(system: BarcodeSystem, data: &str) -> Self {
+        Self { kind: Kind::Barcode { system: Some(system), data: Some(data.to_string()) } }
+    }
+
+    pub fn any_barcode() -> Self {
+        Self { kind: Kind::Barcode { system: None, data: None } }
+    }
+
+    pub fn qr_code(data: &str) -> Self {
+        Self { kind: Kind::QrCode(Some(data.to_string())) }
+    }
+
+    pub fn any_qr_code() -> Self {
+        Self { kind: Kind::QrCode(None) }
+    }
+
+    /// A cut of either kind.
+    pub fn cut() -> Self {
+        Self { kind: Kind::Cut(None) }
+    }
+
+    pub fn full_cut() -> Self {
+        Self { kind: Kind::Cut(Some(CutKind::Full)) }
+    }
+
+    pub fn partial_cut() -> Self {
+        Self { kind: Kind::Cut(Some(CutKind::Partial)) }
+    }
+
+    pub fn left(self) -> Self {
+        self.justified(Justification::Left)
+    }
+
+    pub fn centered(self) -> Self {
+        self.justified(Justification::Center)
+    }
+
+    pub fn right(self) -> Self {
+        self.justified(Justification::Right)
+    }
+
+    #[track_caller]
+    pub fn justified(self, justification: Justification) -> Self {
+        self.style(|line| line.justification = Some(justification))
+    }
+
+    #[track_caller]
+    pub fn bold(self) -> Self {
+        self.style(|line| line.emphasis = Some(true))
+    }
+
+    #[track_caller]
+    pub fn not_bold(self) -> Self {
+        self.style(|line| line.emphasis = Some(false))
+    }
+
+    #[track_caller]
+    pub fn underlined(self) -> Self {
+        self.style(|line| line.underline = Some(true))
+    }
+
+    #[track_caller]
+    pub fn italic(self) -> Self {
+        self.style(|line| line.italic = Some(true))
+    }
+
+    #[track_caller]
+    pub fn reversed(self) -> Self {
+        self.style(|line| line.reverse = Some(true))
+    }
+
+    /// Character magnification, e.g. `size(2, 2)` for double width and height.
+    #[track_caller]
+    pub fn size(self, width: u8, height: u8) -> Self {
+        self.style(|line| line.size = Some((width, height)))
+    }
+
+    #[track_caller]
+    pub fn font(self, font: Font) -> Self {
+        self.style(|line| line.font = Some(font))
+    }
+
+    #[track_caller]
+    fn style(mut self, apply: impl FnOnce(&mut LineExpect)) -> Self {
+        match &mut self.kind {
+            Kind::Line(line) => apply(line),
+            _ => panic!("text styles only apply to line expectations, not {}", self),
+        }
+        self
+    }
+
+    pub fn matches(&self, line: &ReceiptLine) -> bool {
+        match (&self.kind, line) {
+            (Kind::Line(expect), ReceiptLine::Text(text)) => {
+                expect.text.matches(&text.text) && expect.style_differences(text).is_empty()
+            }
+            (Kind::Barcode { system, data: expected }, ReceiptLine::Barcode { system: actual, data, .. }) => {
+                system.is_none_or(|system| system == *actual)
+                    && expected.as_ref().is_none_or(|expected| {
+                        expected.as_bytes() == data.as_slice() || *expected == barcode::hri_text(*actual, data)
+                    })
+            }
+            (Kind::QrCode(expected), ReceiptLine::QrCode { data, .. }) => {
+                expected.as_ref().is_none_or(|expected| expected.as_bytes() == data.as_slice())
+            }
+            (Kind::Cut(expected), ReceiptLine::Separator(kind)) => expected.is_none_or(|expected| expected == *kind),
+            _ => false,
+        }
+    }
+}
+
+/// Reads like the assertion it came from, e.g. `a centered bold line "TOTAL"`.
+impl fmt::Display for Expect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            Kind::Line(line) => {
+                write!(f, "a")?;
+                if let Some(justification) = &line.justification {
+                    write!(f, " {}", justification_name(justification))?;
+                }
+                for (flag, name) in [
+                    (line.emphasis, "bold"),
+                    (line.underline, "underlined"),
+                    (line.italic, "italic"),
+                    (line.reverse, "reversed"),
+                ] {
+                    match flag {
+                        Some(true) => write!(f, " {}", name)?,
+                        Some(false) => write!(f, " non-{}", name)?,
+                        None => {}
+                    }
+                }
+                if let Some((width, height)) = line.size {
+                    write!(f, " {}x{}", width, height)?;
+                }
+                if let Some(font) = &line.font {
+                    write!(f, " {:?}", font)?;
+                }
+                match &line.text {
+                    TextMatch::Exact(text) => write!(f, " line {:?}", text),
+                    TextMatch::Contains(text) => write!(f, " line containing {:?}", text),
+                    TextMatch::Regex(regex) => write!(f, " line matching /{}/", regex),
+                }
+            }
+            Kind::Barcode { system, data } => {
+                write!(f, "a")?;
+                if let Some(system) = system {
+                    write!(f, " {}", system.name())?;
+                }
+                write!(f, " barcode")?;
+                match data {
+                    Some(data) => write!(f, " with data {:?}", data),
+                    None => Ok(()),
+                }
+            }
+            Kind::QrCode(Some(data)) => write!(f, "a QR code with data {:?}", data),
+            Kind::QrCode(None) => write!(f, "a QR code"),
+            Kind::Cut(Some(kind)) => write!(f, "a {} cut", cut_name(*kind)),
+            Kind::Cut(None) => write!(f, "a cut"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum TextMatch {
+    Exact(String),
+    Contains(String),
+    Regex(Regex),
+}
+
+impl TextMatch {
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            TextMatch::Exact(expected) => text.trim() == expected,
+            TextMatch::Contains(needle) => text.contains(needle.as_str()),
+            TextMatch::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+impl Default for TextMatch {
+    fn default() -> Self {
+        TextMatch::Contains(String::new())
+    }
+}
+
+/// Text and the style attributes a test cares about; `None` means any.
+#[derive(Debug, Clone, Default)]
+struct LineExpect {
+    text: TextMatch,
+    justification: Option<Justification>,
+    emphasis: Option<bool>,
+    underline: Option<bool>,
+    italic: Option<bool>,
+    reverse: Option<bool>,
+    size: Option<(u8, u8)>,
+    font: Option<Font>,
+}
+
+impl LineExpect {
+    /// How `line` falls short of the expected style, e.g. `is left, not center`.
+    fn style_differences(&self, line: &TextLine) -> Vec<String> {
+        let mut differences = Vec::new();
+        if let Some(justification) = self.justification.as_ref().filter(|j| **j != line.justification) {
+            differences.push(format!(
+                "is {}, not {}",
+                justification_name(&line.justification),
+                justification_name(justification)
+            ));
+        }
+        for (expected, actual, name) in [
+            (self.emphasis, line.emphasis, "bold"),
+            (self.underline, line.underline, "underlined"),
+            (self.italic, line.italic, "italic"),
+            (self.reverse, line.reverse, "reversed"),
+        ] {
+            match expected {
+                Some(true) if !actual => differences.push(format!("is not {}", name)),
+                Some(false) if actual => differences.push(format!("is {}", name)),
+                _ => {}
+            }
+        }
+        if let Some((width, height)) = self.size.filter(|size| *size != (line.width_mult, line.height_mult)) {
+            differences.push(format!("is {}x{}, not {}x{}", line.width_mult, line.height_mult, width, height));
+        }
+        if let Some(font) = self.font.as_ref().filter(|font| **font != line.font) {
+            differences.push(format!("is {:?}, not {:?}", line.font, font));
+        }
+        differences
+    }
+}
+
+/// `center bold 2x2`: the justification, then whatever differs from plain text.
+fn style_summary(line: &TextLine) -> String {
+    let mut parts = vec![justification_name(&line.justification)];
+    for (on, name) in [
+        (line.emphasis, "bold"),
+        (line.underline, "underlined"),
+        (line.italic, "italic"),
+        (line.reverse, "reversed"),
+    ] {
+        if on {
+            parts.push(name);
+        }
+    }
+    let mut summary = parts.join(" ");
+    if (line.width_mult, line.height_mult) != (1, 1) {
+        summary += &format!(" {}x{}", line.width_mult, line.height_mult);
+    }
+    if line.font != Font::FontA {
+        summary += &format!(" {:?}", line.font);
+    }
+    summary
+}
+
+fn justification_name(justification: &Justification) -> &'static str {
+    match justification {
+        Justification::Left => "left",
+        Justification::Center => "centered",
+        Justification::Right => "right",
+    }
+}
+
+fn cut_name(kind: CutKind) -> &'static str {
+    match kind {
+        CutKind::Full => "full",
+        CutKind::Partial => "partial",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{render_bytes, RenderOptions};
+    use std::panic::{self, AssertUnwindSafe};
+
+    /// A centred bold total, a plain line, a Code 39 barcode, a QR code and a
+    /// partial cut.
+    fn receipt() -> Receipt {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"\x1ba\x01\x1bE\x01TOTAL 12.50\n\x1bE\x00\x1ba\x00");
+        data.extend_from_slice(b"Thank you\n");
+        data.extend_from_slice(b"\x1dk\x04ABC123\x00");
+        data.extend_from_slice(b"\x1d(k\x08\x001P0https\x1d(k\x03\x001Q0");
+        data.extend_from_slice(b"\x1dV\x01");
+        Receipt::from(&render_bytes(&data, "test.bin", &RenderOptions::default()).unwrap())
+    }
+
+    /// The panic message of `check`, which must fail.
+    fn failure(check: impl FnOnce()) -> String {
+        let payload = panic::catch_unwind(AssertUnwindSafe(check)).expect_err("assertion should fail");
+        match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast_ref::<&str>().unwrap().to_string(),
+        }
+    }
+
+    #[test]
+    fn expect_matches_text_and_style() {
+        let receipt = receipt();
+        let total = receipt.find_text("TOTAL 12.50").map(|line| ReceiptLine::Text(line.clone())).unwrap();
+
+        assert!(Expect::line("TOTAL 12.50").matches(&total));
+        assert!(Expect::line("TOTAL 12.50").centered().bold().matches(&total));
+        assert!(Expect::line_containing("12.50").size(1, 1).matches(&total));
+        assert!(Expect::line_matching(r"^\s*TOTAL \d+\.\d\d").matches(&total));
+        assert!(!Expect::line("TOTAL").matches(&total));
+        assert!(!Expect::line("TOTAL 12.50").not_bold().matches(&total));
+        assert!(!Expect::line("TOTAL 12.50").right().matches(&total));
+        assert!(!Expect::cut().matches(&total));
+    }
+
+    #[test]
+    fn expect_matches_barcodes_qr_codes_and_cuts() {
+        let receipt = receipt();
+        assert!(receipt.find(&Expect::barcode(BarcodeSystem::Code39, "ABC123")).is_some());
+        assert!(receipt.find(&Expect::barcode(BarcodeSystem::Code39, "*ABC123*")).is_some());
+        assert!(receipt.find(&Expect::barcode(BarcodeSystem::Code128, "ABC123")).is_none());
+        assert!(receipt.find(&Expect::qr_code("https")).is_some());
+        assert!(receipt.find(&Expect::qr_code("http")).is_none());
+        assert!(receipt.find(&Expect::partial_cut()).is_some());
+        assert!(receipt.find(&Expect::full_cut()).is_none());
+        assert_eq!(receipt.find_all(&Expect::cut()).len(), 1);
+    }
+
+    #[test]
+    fn assert_sequence_allows_gaps_but_not_reordering() {
+        let receipt = receipt();
+        receipt.assert_sequence(&[Expect::line("TOTAL 12.50"), Expect::any_qr_code(), Expect::cut()]);
+
+        let message = failure(|| {
+            receipt.assert_sequence(&[Expect::line("Thank you"), Expect::line("TOTAL 12.50").bold()]);
+        });
+        assert!(
+            message.contains("expected a bold line \"TOTAL 12.50\" after line 2 (step 2 of 2)"),
+            "{}",
+            message
+        );
+        assert!(message.contains("line 1 matches but comes earlier"), "{}", message);
+    }
+
+    #[test]
+    fn failure_message_names_the_style_difference_and_lists_the_receipt() {
+        let receipt = receipt();
+        let message = failure(|| {
+            receipt.assert_has(Expect::line("TOTAL 12.50").right().underlined());
+        });
+        assert!(message.starts_with("receipt assertion failed: expected a right underlined line \"TOTAL 12.50\""));
+        assert!(message.contains("line 1 has the text but is centered, not right, is not underlined"), "{}", message);
+        assert!(message.contains("centered bold"), "{}", message);
+        assert!(message.contains("\"Thank you\""), "{}", message);
+        assert!(message.contains("partial cut"), "{}", message);
+    }
+
+    #[test]
+    fn count_assertions_report_what_was_found() {
+        let receipt = receipt();
+        receipt.assert_cut_count(1).assert_drawer_kick_count(0).assert_lacks(Expect::full_cut());
+
+        let message = failure(|| {
+            receipt.assert_count(Expect::line_containing("T"), 3);
+        });
+        assert!(message.contains("expected 3 × a line containing \"T\", found 2"), "{}", message);
+        let message = failure(|| {
+            receipt.assert_lacks(Expect::any_barcode());
+        });
+        assert!(message.contains("expected no a barcode, found one at line"), "{}", message);
+    }
+}
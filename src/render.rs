@@ -0,0 +1,132 @@
+//! Rendering ESC/POS data without the GUI or the network.
+//!
+//! The data goes through `EscPosParser` and `PrinterState` exactly as bytes
+//! received on port 9100 would, on an emulator of its own.
+
+use crate::emulator::{EmulatorState, JobSource};
+use crate::escpos::diagnostics::{Diagnostic, Severity};
+use crate::escpos::printer::ReceiptLine;
+use crate::escpos::raster;
+use crate::export::{self, ExportFormat, PdfScope};
+use crate::query::Receipt;
+use anyhow::{bail, Context, Result};
+use image::RgbImage;
+use std::path::{Path, PathBuf};
+
+/// Printer setup for a render; unset fields keep the emulator defaults.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    /// Printer profile id, e.g. `tm-t88vi`
+    pub profile: Option<String>,
+    pub paper_width_mm: Option<f32>,
+    /// Directory of extra profile JSON files
+    pub profile_dir: Option<PathBuf>,
+}
+
+/// Output file types, chosen by extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Export(ExportFormat),
+    /// The versioned JSON job document
+    Json,
+}
+
+impl OutputFormat {
+    /// The format for `png`, `pdf`, `txt`, `html`, `svg` or `json`.
+    pub fn from_extension(extension: &str, png_scale: u32) -> Option<Self> {
+        Some(match extension.to_ascii_lowercase().as_str() {
+            "png" => OutputFormat::Export(ExportFormat::Png { scale: png_scale }),
+            // Every receipt in the input, one page per cut
+            "pdf" => OutputFormat::Export(ExportFormat::Pdf(PdfScope::Session)),
+            "txt" | "text" => OutputFormat::Export(ExportFormat::Text),
+            "html" | "htm" => OutputFormat::Export(ExportFormat::Html),
+            "svg" => OutputFormat::Export(ExportFormat::Svg),
+            "json" => OutputFormat::Json,
+            _ => return None,
+        })
+    }
+
+    pub fn from_path(path: &Path, png_scale: u32) -> Result<Self> {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+        match Self::from_extension(&extension, png_scale) {
+            Some(format) => Ok(format),
+            None => bail!(
+                "Cannot tell the output format of {} (use .png, .pdf, .txt, .html, .svg or .json)",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// An ESC/POS stream printed on a fresh emulator.
+#[derive(Debug, Clone)]
+pub struct RenderedJob {
+    pub state: EmulatorState,
+    /// Name the data is labelled with in job documents
+    pub name: String,
+}
+
+impl RenderedJob {
+    /// Everything printed, including cut markers.
+    pub fn lines(&self) -> &[ReceiptLine] {
+        self.state.printer_state.get_buffer()
+    }
+
+    pub fn diagnostics(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.state.get_diagnostics().iter().map(|entry| &entry.diagnostic)
+    }
+
+    /// The most severe parse diagnostic, if any.
+    pub fn worst_severity(&self) -> Option<Severity> {
+        self.diagnostics().map(|diagnostic| diagnostic.severity).max()
+    }
+
+    /// One pixel per printer dot.
+    pub fn image(&self) -> RgbImage {
+        raster::render_lines(&self.state.printer_state, self.lines())
+    }
+
+    pub fn text(&self) -> String {
+        export::render_text(&self.state.printer_state, self.lines())
+    }
+
+    /// The lines and commands, for assertions in tests.
+    pub fn receipt(&self) -> Receipt {
+        Receipt::from(self)
+    }
+
+    pub fn save(&self, path: &Path, format: OutputFormat) -> Result<()> {
+        match format {
+            OutputFormat::Export(format) => export::export_lines(&self.state.printer_state, self.lines(), path, format),
+            OutputFormat::Json => self.state.export_job(path, &self.name),
+        }
+    }
+}
+
+/// Print `data` on a new emulator set up with `options`.
+pub fn render_bytes(data: &[u8], name: &str, options: &RenderOptions) -> Result<RenderedJob> {
+    let mut state = EmulatorState::new();
+    if let Some(dir) = &options.profile_dir {
+        state.load_profiles_from_dir(dir);
+    }
+    if let Some(profile) = &options.profile {
+        if !state.select_profile(profile) {
+            let known: Vec<&str> = state.profiles.iter().map(|p| p.id.as_str()).collect();
+            bail!("Unknown printer profile {:?} (known: {})", profile, known.join(", "));
+        }
+    }
+    if let Some(width_mm) = options.paper_width_mm {
+        state.set_paper_width(width_mm)?;
+    }
+    state.print_bytes(&JobSource::File(name.to_string()), data);
+    state.finish_jobs();
+    // Nothing to answer status queries to
+    state.take_responses();
+    Ok(RenderedJob { state, name: name.to_string() })
+}
+
+pub fn render_file(path: &Path, options: &RenderOptions) -> Result<RenderedJob> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().to_string();
+    render_bytes(&data, &name, options)
+}
@@ -0,0 +1,413 @@
+//! Golden snapshots: compare a rendered job with a stored reference.
+//!
+//! The reference's extension picks the comparison. `.png` references are
+//! compared pixel by pixel at one pixel per printer dot, `.txt` references
+//! as plain text and `.json` references as job documents with the times and
+//! emulator version blanked out. A missing reference is written from the
+//! actual output, except when the `CI` environment variable is set.
+//!
+//! On a mismatch the actual output is written next to the reference as
+//! `<name>.actual.<ext>`, plus `<name>.diff.png` for images, with differing
+//! pixels in red over a faded copy of the actual receipt.
+
+use crate::export::json::JobDocument;
+use crate::render::RenderedJob;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use image::{Rgb, RgbImage};
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Setting this to anything but `0` makes `SnapshotOptions::default()`
+/// overwrite references instead of comparing.
+pub const UPDATE_ENV: &str = "ESCPOS_UPDATE_SNAPSHOTS";
+
+const MISMATCH_COLOR: Rgb<u8> = Rgb([230, 0, 0]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotKind {
+    Image,
+    Text,
+    Json,
+}
+
+impl SnapshotKind {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+        Ok(match extension.as_str() {
+            "png" => SnapshotKind::Image,
+            "txt" | "text" => SnapshotKind::Text,
+            "json" => SnapshotKind::Json,
+            _ => bail!("Cannot tell the snapshot type of {} (use .png, .txt or .json)", path.display()),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotOptions {
+    /// Largest difference in any colour channel for two pixels to count as equal
+    pub channel_tolerance: u8,
+    /// Differing pixels allowed before an image snapshot fails
+    pub max_diff_pixels: u64,
+    /// Overwrite the reference with the actual output instead of comparing
+    pub update: bool,
+}
+
+impl Default for SnapshotOptions {
+    fn default() -> Self {
+        Self {
+            channel_tolerance: 0,
+            max_diff_pixels: 0,
+            update: std::env::var(UPDATE_ENV).is_ok_and(|value| value != "0"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotOutcome {
+    Matched,
+    /// There was no reference, so the actual output became it
+    Created,
+    Updated,
+    /// Explains what differs and where the actual output was written
+    Mismatch(String),
+}
+
+impl SnapshotOutcome {
+    pub fn passed(&self) -> bool {
+        !matches!(self, SnapshotOutcome::Mismatch(_))
+    }
+}
+
+impl fmt::Display for SnapshotOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotOutcome::Matched => write!(f, "matches"),
+            SnapshotOutcome::Created => write!(f, "created"),
+            SnapshotOutcome::Updated => write!(f, "updated"),
+            SnapshotOutcome::Mismatch(message) => write!(f, "differs: {}", message),
+        }
+    }
+}
+
+/// The actual output of a job in the form stored for `kind`.
+enum Actual {
+    Image(RgbImage),
+    Text(String),
+}
+
+impl Actual {
+    fn of(job: &RenderedJob, kind: SnapshotKind) -> Self {
+        match kind {
+            SnapshotKind::Image => Actual::Image(job.image()),
+            SnapshotKind::Text => Actual::Text(job.text()),
+            SnapshotKind::Json => Actual::Text(stable_document(job).to_json() + "\n"),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        match self {
+            Actual::Image(image) => image.save(path).with_context(|| format!("Failed to write {}", path.display())),
+            Actual::Text(text) => std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display())),
+        }
+    }
+}
+
+/// Compare `job` with the reference at `reference`, or write the reference
+/// when updating or when it does not exist yet.
+pub fn check_snapshot(job: &RenderedJob, reference: &Path, options: &SnapshotOptions) -> Result<SnapshotOutcome> {
+    let kind = SnapshotKind::from_path(reference)?;
+    let actual = Actual::of(job, kind);
+    let actual_path = sibling(reference, "actual");
+    let diff_path = sibling(reference, "diff");
+    // Only image snapshots have a diff image
+    let stale: &[&Path] = match kind {
+        SnapshotKind::Image => &[&actual_path, &diff_path],
+        _ => &[&actual_path],
+    };
+
+    if options.update || !reference.exists() {
+        if !options.update && std::env::var_os("CI").is_some() {
+            bail!("Reference {} is missing; run with {}=1 to create it", reference.display(), UPDATE_ENV);
+        }
+        let created = !reference.exists();
+        if let Some(dir) = reference.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        actual.save(reference)?;
+        remove_stale(stale);
+        return Ok(if created { SnapshotOutcome::Created } else { SnapshotOutcome::Updated });
+    }
+
+    let mismatch = match (&actual, kind) {
+        (Actual::Image(image), _) => {
+            let expected = image::open(reference)
+                .with_context(|| format!("Failed to read reference {}", reference.display()))?
+                .to_rgb8();
+            let diff = diff_images(&expected, image, options.channel_tolerance);
+            if diff.differing > options.max_diff_pixels {
+                diff.image.save(&diff_path).with_context(|| format!("Failed to write {}", diff_path.display()))?;
+                Some(format!("{}; diff in {}", diff.summary(&expected, image, options.max_diff_pixels), diff_path.display()))
+            } else {
+                None
+            }
+        }
+        (Actual::Text(text), kind) => {
+            let expected = std::fs::read_to_string(reference)
+                .with_context(|| format!("Failed to read reference {}", reference.display()))?;
+            match kind {
+                SnapshotKind::Json => {
+                    let expected: Value = serde_json::from_str(&expected)
+                        .with_context(|| format!("Reference {} is not valid JSON", reference.display()))?;
+                    let actual: Value = serde_json::from_str(text)?;
+                    // Quote both in the same layout, so whitespace and key
+                    // order do not hide the change
+                    (expected != actual).then(|| {
+                        let pretty = |value: &Value| serde_json::to_string_pretty(value).unwrap_or_default();
+                        first_difference(&pretty(&expected), &pretty(&actual))
+                    })
+                }
+                _ => (normalize_newlines(&expected) != normalize_newlines(text)).then(|| first_difference(&expected, text)),
+            }
+        }
+    };
+
+    match mismatch {
+        Some(message) => {
+            actual.save(&actual_path)?;
+            Ok(SnapshotOutcome::Mismatch(format!("{}; actual output in {}", message, actual_path.display())))
+        }
+        None => {
+            remove_stale(stale);
+            Ok(SnapshotOutcome::Matched)
+        }
+    }
+}
+
+/// Panic unless `job` matches the reference, for use in `cargo test`.
+/// Honours `ESCPOS_UPDATE_SNAPSHOTS`.
+#[track_caller]
+pub fn assert_snapshot(job: &RenderedJob, reference: impl AsRef<Path>) {
+    assert_snapshot_with(job, reference, &SnapshotOptions::default());
+}
+
+#[track_caller]
+pub fn assert_snapshot_with(job: &RenderedJob, reference: impl AsRef<Path>, options: &SnapshotOptions) {
+    let reference = reference.as_ref();
+    match check_snapshot(job, reference, options) {
+        Ok(SnapshotOutcome::Mismatch(message)) => panic!(
+            "Snapshot {} differs: {}\nRun with {}=1 to accept the new output.",
+            reference.display(),
+            message,
+            UPDATE_ENV
+        ),
+        Ok(_) => {}
+        Err(e) => panic!("Snapshot {} failed: {:#}", reference.display(), e),
+    }
+}
+
+/// The job document with everything that changes from run to run blanked out.
+pub fn stable_document(job: &RenderedJob) -> JobDocument {
+    let epoch: DateTime<Utc> = DateTime::UNIX_EPOCH;
+    let mut document = JobDocument::from_state(&job.state, &job.name);
+    document.metadata.started_at = epoch;
+    document.metadata.ended_at = epoch;
+    document.metadata.exported_at = epoch;
+    document.metadata.emulator_version = String::new();
+    for command in &mut document.commands {
+        command.time = epoch;
+    }
+    for diagnostic in &mut document.diagnostics {
+        diagnostic.time = epoch;
+    }
+    document
+}
+
+/// Result of comparing two images pixel by pixel.
+pub struct ImageDiff {
+    /// Pixels that differ by more than the tolerance, or lie outside the
+    /// smaller image
+    pub differing: u64,
+    pub image: RgbImage,
+}
+
+impl ImageDiff {
+    fn summary(&self, expected: &RgbImage, actual: &RgbImage, allowed: u64) -> String {
+        let mut summary = format!("{} pixel(s) differ (up to {} allowed)", self.differing, allowed);
+        if expected.dimensions() != actual.dimensions() {
+            summary += &format!(
+                ", size changed from {}x{} to {}x{}",
+                expected.width(),
+                expected.height(),
+                actual.width(),
+                actual.height()
+            );
+        }
+        summary
+    }
+}
+
+/// Compare two images; the diff image covers both and shows the actual image
+/// faded, with differing pixels in red.
+pub fn diff_images(expected: &RgbImage, actual: &RgbImage, channel_tolerance: u8) -> ImageDiff {
+    let width = expected.width().max(actual.width());
+    let height = expected.height().max(actual.height());
+    let mut differing = 0;
+    let image = RgbImage::from_fn(width, height, |x, y| {
+        let expected = in_bounds(expected, x, y);
+        let actual = in_bounds(actual, x, y);
+        match (expected, actual) {
+            (Some(e), Some(a)) if e.0.iter().zip(a.0.iter()).all(|(e, a)| e.abs_diff(*a) <= channel_tolerance) => {
+                Rgb(a.0.map(|channel| 255 - (255 - channel) / 4))
+            }
+            _ => {
+                differing += 1;
+                MISMATCH_COLOR
+            }
+        }
+    });
+    ImageDiff { differing, image }
+}
+
+fn in_bounds(image: &RgbImage, x: u32, y: u32) -> Option<Rgb<u8>> {
+    (x < image.width() && y < image.height()).then(|| *image.get_pixel(x, y))
+}
+
+fn normalize_newlines(text: &str) -> String {
+    text.replace("\r\n", "\n")
+}
+
+/// The first line that differs, quoted from both sides.
+fn first_difference(expected: &str, actual: &str) -> String {
+    let expected = normalize_newlines(expected);
+    let actual = normalize_newlines(actual);
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut number = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(e), Some(a)) if e == a => number += 1,
+            (None, None) => return "only whitespace differs".to_string(),
+            (e, a) => {
+                return format!(
+                    "line {} expected {:?}, got {:?}",
+                    number,
+                    e.unwrap_or("<end of file>"),
+                    a.unwrap_or("<end of file>")
+                )
+            }
+        }
+    }
+}
+
+/// `receipt.png` → `receipt.<tag>.png`.
+fn sibling(reference: &Path, tag: &str) -> PathBuf {
+    let stem = reference.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match reference.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, tag, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, tag),
+    };
+    reference.with_file_name(name)
+}
+
+fn remove_stale(paths: &[&Path]) {
+    for path in paths {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{render_bytes, RenderOptions};
+
+    const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+
+    fn job(data: &[u8]) -> RenderedJob {
+        render_bytes(data, "test.bin", &RenderOptions::default()).unwrap()
+    }
+
+    fn compare_only() -> SnapshotOptions {
+        SnapshotOptions { update: false, ..SnapshotOptions::default() }
+    }
+
+    #[test]
+    fn diff_images_honours_the_channel_tolerance() {
+        let expected = RgbImage::from_pixel(4, 4, WHITE);
+        let mut actual = expected.clone();
+        actual.put_pixel(1, 1, Rgb([250, 255, 255]));
+        actual.put_pixel(2, 2, Rgb([0, 0, 0]));
+
+        assert_eq!(diff_images(&expected, &actual, 0).differing, 2);
+        let diff = diff_images(&expected, &actual, 5);
+        assert_eq!(diff.differing, 1);
+        assert_eq!(*diff.image.get_pixel(2, 2), MISMATCH_COLOR);
+        assert_ne!(*diff.image.get_pixel(1, 1), MISMATCH_COLOR);
+    }
+
+    #[test]
+    fn diff_images_counts_pixels_outside_the_smaller_image() {
+        let expected = RgbImage::from_pixel(4, 4, WHITE);
+        let actual = RgbImage::from_pixel(4, 6, WHITE);
+        let diff = diff_images(&expected, &actual, 0);
+        assert_eq!(diff.differing, 8);
+        assert_eq!(diff.image.dimensions(), (4, 6));
+        assert!(diff.summary(&expected, &actual, 0).contains("size changed from 4x4 to 4x6"));
+    }
+
+    #[test]
+    fn first_difference_quotes_both_sides() {
+        assert_eq!(first_difference("a\nb\n", "a\nc\n"), "line 2 expected \"b\", got \"c\"");
+        assert_eq!(first_difference("a\n", "a\nb\n"), "line 2 expected \"<end of file>\", got \"b\"");
+        assert_eq!(first_difference("a\r\n", "a\n"), "only whitespace differs");
+    }
+
+    #[test]
+    fn check_snapshot_creates_matches_and_reports_mismatches() {
+        let dir = tempfile::tempdir().unwrap();
+        let reference = dir.path().join("receipt.png");
+        let receipt = job(b"TOTAL 12.50\n");
+
+        // Written through `update`, which works the same with and without CI
+        let update = SnapshotOptions { update: true, ..SnapshotOptions::default() };
+        assert_eq!(check_snapshot(&receipt, &reference, &update).unwrap(), SnapshotOutcome::Created);
+        assert!(reference.exists());
+        assert_eq!(check_snapshot(&receipt, &reference, &compare_only()).unwrap(), SnapshotOutcome::Matched);
+
+        let changed = job(b"TOTAL 13.50\n");
+        let outcome = check_snapshot(&changed, &reference, &compare_only()).unwrap();
+        assert!(matches!(&outcome, SnapshotOutcome::Mismatch(message) if message.contains("pixel(s) differ")));
+        assert!(dir.path().join("receipt.actual.png").exists());
+        assert!(dir.path().join("receipt.diff.png").exists());
+
+        // A match cleans up the files a previous mismatch left behind
+        assert_eq!(check_snapshot(&receipt, &reference, &compare_only()).unwrap(), SnapshotOutcome::Matched);
+        assert!(!dir.path().join("receipt.actual.png").exists());
+        assert!(!dir.path().join("receipt.diff.png").exists());
+    }
+
+    #[test]
+    fn text_snapshot_mismatch_names_the_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let reference = dir.path().join("receipt.txt");
+        std::fs::write(&reference, job(b"ITEM\nTOTAL 12.50\n").text()).unwrap();
+
+        let outcome = check_snapshot(&job(b"ITEM\nTOTAL 13.50\n"), &reference, &compare_only()).unwrap();
+        match outcome {
+            SnapshotOutcome::Mismatch(message) => {
+                assert!(message.contains("line 2"), "{}", message);
+                assert!(message.contains("TOTAL 13.50"), "{}", message);
+            }
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn json_snapshot_is_stable_across_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let reference = dir.path().join("receipt.json");
+        std::fs::write(&reference, stable_document(&job(b"TOTAL\n")).to_json()).unwrap();
+        assert_eq!(check_snapshot(&job(b"TOTAL\n"), &reference, &compare_only()).unwrap(), SnapshotOutcome::Matched);
+    }
+}